@@ -0,0 +1,72 @@
+//! Module for static analysis of an AST, which produces [`Warning`]s about
+//! code that is valid but likely to be a mistake.
+
+use std::fmt::Display;
+
+use super::ast::AstNode;
+
+/// A problem found in valid source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// One or more statements follow a statement which always exits the block.
+    UnreachableCode {
+        /// The kind of statement which exits the block (e.g. `return`).
+        after: &'static str,
+        /// The number of statements which can never run.
+        statements: usize,
+    },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnreachableCode { after, statements } => {
+                let plural = if *statements == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "unreachable code: {statements} statement{plural} after `{after}`"
+                )
+            }
+        }
+    }
+}
+
+/// Analyze an AST, returning any warnings found in source order.
+#[must_use]
+pub fn analyze(ast: &AstNode) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    check_node(ast, &mut warnings);
+    warnings
+}
+
+/// Check a node and all of its descendants for problems.
+fn check_node(node: &AstNode, warnings: &mut Vec<Warning>) {
+    if let AstNode::Block(statements) = node {
+        check_unreachable(statements, warnings);
+    }
+    for child in node.children() {
+        check_node(child, warnings);
+    }
+}
+
+/// Check for statements following a `return`, `break`, or `continue` in a block.
+fn check_unreachable(statements: &[AstNode], warnings: &mut Vec<Warning>) {
+    let exit = statements.iter().enumerate().find_map(|(i, statement)| {
+        let after = match statement {
+            AstNode::Return { .. } => "return",
+            AstNode::Break => "break",
+            AstNode::Continue => "continue",
+            _ => return None,
+        };
+        Some((i, after))
+    });
+    if let Some((i, after)) = exit {
+        let unreachable = statements.len() - i - 1;
+        if unreachable > 0 {
+            warnings.push(Warning::UnreachableCode {
+                after,
+                statements: unreachable,
+            });
+        }
+    }
+}
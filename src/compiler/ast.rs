@@ -31,22 +31,48 @@ pub enum AstNode {
     StringLiteral(String),
     /// A literal boolean. Holds the value of the boolean.
     BooleanLiteral(bool),
+    /// A literal list. Holds the expressions for each element.
+    ListLiteral(Vec<Self>),
     // -------------- Operations --------------
     /// A unary operation.
     UnaryOperation {
         /// The operation kind.
         kind: UnaryOperationKind,
         /// The target of the operation.
-        operand: Box<AstNode>,
+        operand: Box<Self>,
     },
     /// A binary operation.
     BinaryOperation {
         /// The operation kind.
         kind: BinaryOperationKind,
         /// The left operand.
-        left: Box<AstNode>,
+        left: Box<Self>,
         /// The right operand.
-        right: Box<AstNode>,
+        right: Box<Self>,
+    },
+    /// Two or more comparisons chained together, e.g. `a < b < c`.
+    ///
+    /// Equivalent to comparing each adjacent pair of operands and combining the results
+    /// with `and` (`a < b and b < c`), except that each operand is evaluated only once.
+    ComparisonChain {
+        /// The compared operands, one more than there are comparisons.
+        operands: Vec<Self>,
+        /// The comparison between each adjacent pair of operands.
+        kinds: Vec<BinaryOperationKind>,
+    },
+    /// An index into a list or string.
+    Index {
+        /// The object being indexed.
+        target: Box<Self>,
+        /// The index. Negative indices count from the end.
+        index: Box<Self>,
+    },
+    /// Access to a field of a table.
+    Field {
+        /// The table being accessed.
+        target: Box<Self>,
+        /// The name of the field.
+        key: String,
     },
     // -------------- Functions --------------
     /// A function call.
@@ -54,14 +80,21 @@ pub enum AstNode {
         /// Name of the function to call. Will be loaded from the current scope or a parent scope.
         identifier: String,
         /// Arguments to pass to the function.
-        args: Vec<AstNode>,
+        args: Vec<Self>,
+    },
+    /// A call to the result of an expression, e.g. `math.abs(x)`.
+    Call {
+        /// The expression producing the function to call.
+        target: Box<Self>,
+        /// Arguments to pass to the function.
+        args: Vec<Self>,
     },
     /// A function definition.
     FunctionDef {
         /// Arguments taken by the function. Should technically be called parameters, but whatever :D
         args: Vec<String>,
         /// The body of the function.
-        body: Box<AstNode>,
+        body: Box<Self>,
     },
     // ============== Statements ==============
     // -------------- Misc --------------
@@ -70,46 +103,111 @@ pub enum AstNode {
         /// The name of the variable to assign to.
         identifier: String,
         /// The value to assign to the variable.
-        value: Box<AstNode>,
+        value: Box<Self>,
+    },
+    /// Assignment to an index of a list or string.
+    IndexAssignment {
+        /// The object being indexed.
+        target: Box<Self>,
+        /// The index. Negative indices count from the end.
+        index: Box<Self>,
+        /// The value to assign.
+        value: Box<Self>,
+    },
+    /// Assignment to a field of a table.
+    FieldAssignment {
+        /// The table being accessed.
+        target: Box<Self>,
+        /// The name of the field.
+        key: String,
+        /// The value to assign.
+        value: Box<Self>,
+    },
+    /// Increment of a numeric variable in the local scope (`x++`).
+    Increment {
+        /// The name of the variable to increment.
+        identifier: String,
+    },
+    /// Decrement of a numeric variable in the local scope (`x--`).
+    Decrement {
+        /// The name of the variable to decrement.
+        identifier: String,
     },
     /// A collection of back-to-back statements.
-    Block(Vec<AstNode>),
+    Block(Vec<Self>),
+    /// A `do` block, which runs its statements in a new scope and evaluates to
+    /// its trailing expression.
+    Do {
+        /// The statements of the block.
+        body: Box<Self>,
+        /// The trailing expression. The block evaluates to nil if there is none.
+        value: Option<Box<Self>>,
+    },
     // -------------- Control Flow --------------
     /// An if statement. `If` nodes only contain "if" or "else" blocks for simplicity.
     /// An else-if block can be formed with nested if statements.
     If {
         /// The condition evaluated to determine which block to run
-        condition: Box<AstNode>,
+        condition: Box<Self>,
         /// The "then" block
-        body: Box<AstNode>,
+        body: Box<Self>,
         /// The "else" block
-        else_body: Option<Box<AstNode>>,
+        else_body: Option<Box<Self>>,
     },
     // A for loop. The for loop takes on the C form: `for (initialization; condition; increment) { body }`.
     For {
         /// The initialization statement. This is run before the loop starts.
         /// It may be omitted to not run any initialization.
-        initialization: Option<Box<AstNode>>,
+        initialization: Option<Box<Self>>,
         /// The condition statement. This is run before each iteration of the loop.
         /// This may be omitted to run the loop forever, or until broken.
-        condition: Option<Box<AstNode>>,
+        condition: Option<Box<Self>>,
         /// The increment statement. This is run after each iteration of the loop.
         /// This may be omitted to not run any increment.
-        increment: Option<Box<AstNode>>,
+        increment: Option<Box<Self>>,
         /// The body of the loop.
-        body: Box<AstNode>,
+        body: Box<Self>,
+        /// The "else" block. This is run if the loop finishes without a `break`.
+        else_body: Option<Box<Self>>,
     },
     /// A while loop.
     While {
         /// The condition evaluated before each iteration.
-        condition: Box<AstNode>,
+        condition: Box<Self>,
         /// The body of the loop.
-        body: Box<AstNode>,
+        body: Box<Self>,
+        /// The "else" block. This is run if the loop finishes without a `break`.
+        else_body: Option<Box<Self>>,
     },
     /// An infinite loop.
     Loop {
         /// The body of the loop.
-        body: Box<AstNode>,
+        body: Box<Self>,
+    },
+    /// A loop which runs its body a fixed number of times.
+    Repeat {
+        /// The number of times to run the body. Evaluated once, before the first run.
+        count: Box<Self>,
+        /// The body of the loop.
+        body: Box<Self>,
+    },
+    /// A with statement, which closes a resource when its body exits.
+    With {
+        /// The resource to close.
+        resource: Box<Self>,
+        /// The name of the variable the resource is assigned to.
+        identifier: String,
+        /// The body of the statement.
+        body: Box<Self>,
+    },
+    /// An assert statement, which raises an error if its condition is false.
+    Assert {
+        /// The condition which must be true.
+        condition: Box<Self>,
+        /// The message to report if the condition is false (optional).
+        message: Option<Box<Self>>,
+        /// The source text of the condition, reported if there is no message.
+        source: String,
     },
     /// A break statement.
     Break,
@@ -118,13 +216,95 @@ pub enum AstNode {
     /// A return statement.
     Return {
         /// The value to return (optional).
-        value: Option<Box<AstNode>>,
+        value: Option<Box<Self>>,
     },
 }
 
+impl AstNode {
+    /// Returns the direct child nodes of this node, in source order.
+    #[must_use]
+    pub fn children(&self) -> Vec<&Self> {
+        match self {
+            Self::Identifier(_)
+            | Self::NumberLiteral(_)
+            | Self::NilLiteral
+            | Self::StringLiteral(_)
+            | Self::BooleanLiteral(_)
+            | Self::Increment { .. }
+            | Self::Decrement { .. }
+            | Self::Break
+            | Self::Continue => vec![],
+            Self::ListLiteral(nodes)
+            | Self::Block(nodes)
+            | Self::ComparisonChain {
+                operands: nodes, ..
+            }
+            | Self::FunctionCall { args: nodes, .. } => nodes.iter().collect(),
+            Self::UnaryOperation { operand, .. } => vec![operand],
+            Self::BinaryOperation { left, right, .. } => vec![left, right],
+            Self::Index { target, index } => vec![target, index],
+            Self::Field { target, .. } => vec![target],
+            Self::Call { target, args } => std::iter::once(target.as_ref()).chain(args).collect(),
+            Self::With { resource, body, .. } => vec![resource, body],
+            Self::Do { body, value } => std::iter::once(body)
+                .chain(value)
+                .map(AsRef::as_ref)
+                .collect(),
+            Self::Repeat { count, body } => vec![count, body],
+            Self::Assert {
+                condition, message, ..
+            } => std::iter::once(condition)
+                .chain(message)
+                .map(AsRef::as_ref)
+                .collect(),
+            Self::FunctionDef { body, .. } | Self::Loop { body } => vec![body],
+            Self::Assignment { value, .. } => vec![value],
+            Self::IndexAssignment {
+                target,
+                index,
+                value,
+            } => vec![target, index, value],
+            Self::FieldAssignment { target, value, .. } => vec![target, value],
+            Self::If {
+                condition,
+                body,
+                else_body,
+            }
+            | Self::While {
+                condition,
+                body,
+                else_body,
+            } => [Some(condition), Some(body), else_body.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(AsRef::as_ref)
+                .collect(),
+            Self::For {
+                initialization,
+                condition,
+                increment,
+                body,
+                else_body,
+            } => [
+                initialization.as_ref(),
+                condition.as_ref(),
+                increment.as_ref(),
+                Some(body),
+                else_body.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .map(AsRef::as_ref)
+            .collect(),
+            Self::Return { value } => value.iter().map(AsRef::as_ref).collect(),
+        }
+    }
+}
+
 /// The type of a unary operation.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum UnaryOperationKind {
+    Plus,
     Negate,
     Not,
 }
@@ -137,11 +317,22 @@ impl UnaryOperationKind {
     #[must_use]
     pub fn dunder(&self) -> String {
         match self {
+            Self::Plus => "__pos__",
             Self::Negate => "__neg__",
             Self::Not => "__not__",
         }
         .to_string()
     }
+
+    /// Returns the operator symbol for this operation, as written in source code.
+    #[must_use]
+    pub const fn symbol(&self) -> &'static str {
+        match self {
+            Self::Plus => "+",
+            Self::Negate => "-",
+            Self::Not => "not",
+        }
+    }
 }
 
 /// The type of a binary operation.
@@ -151,10 +342,13 @@ pub enum BinaryOperationKind {
     Subtract,
     Multiply,
     Divide,
+    FloorDivide,
     Remainder,
+    Modulo,
     Power,
     And,
     Or,
+    Xor,
     Equal,
     NotEqual,
     GreaterThan,
@@ -166,8 +360,7 @@ pub enum BinaryOperationKind {
 impl BinaryOperationKind {
     /// Returns the dunder method name for this operation.
     ///
-    /// Currently unused. This may be used for implementing operator
-    /// overloading in the future.
+    /// Used to look up operator overloads in an object's metatable.
     #[must_use]
     pub fn dunder(&self) -> String {
         match self {
@@ -175,10 +368,13 @@ impl BinaryOperationKind {
             Self::Subtract => "__sub__",
             Self::Multiply => "__mul__",
             Self::Divide => "__div__",
+            Self::FloorDivide => "__floordiv__",
             Self::Remainder => "__rem__",
+            Self::Modulo => "__mod__",
             Self::Power => "__pow__",
             Self::And => "__and__",
             Self::Or => "__or__",
+            Self::Xor => "__xor__",
             Self::Equal => "__eq__",
             Self::NotEqual => "__ne__",
             Self::GreaterThan => "__gt__",
@@ -188,6 +384,44 @@ impl BinaryOperationKind {
         }
         .to_string()
     }
+
+    /// Returns whether this operation is a comparison, which may be chained (`a < b < c`).
+    #[must_use]
+    pub const fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Self::Equal
+                | Self::NotEqual
+                | Self::GreaterThan
+                | Self::GreaterThanOrEqual
+                | Self::LessThan
+                | Self::LessThanOrEqual
+        )
+    }
+
+    /// Returns the operator symbol for this operation, as written in source code.
+    #[must_use]
+    pub const fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::FloorDivide => "//",
+            Self::Remainder => "%",
+            Self::Modulo => "mod",
+            Self::Power => "**",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Xor => "xor",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::GreaterThan => ">",
+            Self::GreaterThanOrEqual => ">=",
+            Self::LessThan => "<",
+            Self::LessThanOrEqual => "<=",
+        }
+    }
 }
 
 /// Holds either an integer or float value.
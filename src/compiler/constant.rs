@@ -0,0 +1,113 @@
+//! Evaluation of constant expressions at compile time.
+//!
+//! An expression is constant if it is made only of literals and operators whose result
+//! doesn't depend on the state, e.g. `1 + 2 < 4`. Evaluating such expressions ahead of time
+//! lets the translator skip work at runtime, such as dropping the dead branch of an `if`.
+//!
+//! Only operations which can't fail are evaluated; anything that would raise an error at
+//! runtime (such as comparing a string to an integer) is left for the runtime to report.
+
+use super::ast::{AstNode, BinaryOperationKind, Number, UnaryOperationKind};
+use crate::runtime::types::primitive::{to_float, Primitive};
+
+/// Evaluate an expression, if its value is known at compile time.
+#[must_use]
+pub fn evaluate(node: &AstNode) -> Option<Primitive> {
+    match node {
+        AstNode::NumberLiteral(Number::Integer(x)) => Some(Primitive::Integer(*x)),
+        AstNode::NumberLiteral(Number::Float(x)) => Some(Primitive::Float(*x)),
+        AstNode::StringLiteral(x) => Some(Primitive::String(x.clone())),
+        AstNode::BooleanLiteral(x) => Some(Primitive::Boolean(*x)),
+        AstNode::NilLiteral => Some(Primitive::Nil),
+        AstNode::UnaryOperation { kind, operand } => match (kind, evaluate(operand)?) {
+            (UnaryOperationKind::Not, Primitive::Boolean(x)) => Some(Primitive::Boolean(!x)),
+            (UnaryOperationKind::Plus, x @ (Primitive::Integer(_) | Primitive::Float(_))) => {
+                Some(x)
+            }
+            (UnaryOperationKind::Negate, Primitive::Integer(x)) => {
+                x.checked_neg().map(Primitive::Integer)
+            }
+            (UnaryOperationKind::Negate, Primitive::Float(x)) => Some(Primitive::Float(-x)),
+            _ => None,
+        },
+        AstNode::BinaryOperation { kind, left, right } => {
+            evaluate_binary(*kind, evaluate(left)?, evaluate(right)?)
+        }
+        AstNode::ComparisonChain { operands, kinds } => {
+            let operands = operands.iter().map(evaluate).collect::<Option<Vec<_>>>()?;
+            let mut result = true;
+            for (kind, pair) in kinds.iter().zip(operands.windows(2)) {
+                match evaluate_binary(*kind, pair[0].clone(), pair[1].clone())? {
+                    Primitive::Boolean(x) => result &= x,
+                    _ => return None,
+                }
+            }
+            Some(Primitive::Boolean(result))
+        }
+        _ => None,
+    }
+}
+
+/// Evaluate an expression as an `if` condition, if its value is known at compile time.
+///
+/// Non-boolean values are not valid conditions, so they are left for the runtime to report.
+#[must_use]
+pub fn evaluate_condition(node: &AstNode) -> Option<bool> {
+    match evaluate(node)? {
+        Primitive::Boolean(x) => Some(x),
+        _ => None,
+    }
+}
+
+/// Evaluate a binary operation on two constant operands.
+fn evaluate_binary(kind: BinaryOperationKind, lhs: Primitive, rhs: Primitive) -> Option<Primitive> {
+    match kind {
+        BinaryOperationKind::Add => lhs + rhs,
+        BinaryOperationKind::Subtract => lhs - rhs,
+        BinaryOperationKind::Multiply => lhs * rhs,
+        BinaryOperationKind::Equal => Some(Primitive::Boolean(lhs == rhs)),
+        BinaryOperationKind::NotEqual => Some(Primitive::Boolean(lhs != rhs)),
+        BinaryOperationKind::LessThan => compare(lhs, rhs).map(|x| Primitive::Boolean(x.is_lt())),
+        BinaryOperationKind::LessThanOrEqual => {
+            compare(lhs, rhs).map(|x| Primitive::Boolean(x.is_le()))
+        }
+        BinaryOperationKind::GreaterThan => {
+            compare(lhs, rhs).map(|x| Primitive::Boolean(x.is_gt()))
+        }
+        BinaryOperationKind::GreaterThanOrEqual => {
+            compare(lhs, rhs).map(|x| Primitive::Boolean(x.is_ge()))
+        }
+        BinaryOperationKind::And => match (lhs, rhs) {
+            (Primitive::Boolean(a), Primitive::Boolean(b)) => Some(Primitive::Boolean(a && b)),
+            _ => None,
+        },
+        BinaryOperationKind::Or => match (lhs, rhs) {
+            (Primitive::Boolean(a), Primitive::Boolean(b)) => Some(Primitive::Boolean(a || b)),
+            _ => None,
+        },
+        BinaryOperationKind::Xor => match (lhs, rhs) {
+            (Primitive::Boolean(a), Primitive::Boolean(b)) => Some(Primitive::Boolean(a ^ b)),
+            (Primitive::Integer(a), Primitive::Integer(b)) => Some(Primitive::Integer(a ^ b)),
+            _ => None,
+        },
+        // Division by zero is an error, so division is left for the runtime
+        BinaryOperationKind::Divide
+        | BinaryOperationKind::FloorDivide
+        | BinaryOperationKind::Remainder
+        | BinaryOperationKind::Modulo
+        | BinaryOperationKind::Power => None,
+    }
+}
+
+/// Order two numbers, as the runtime's comparison operators do.
+///
+/// Returns `None` if either operand isn't a number, or if either is `NaN`.
+fn compare(lhs: Primitive, rhs: Primitive) -> Option<std::cmp::Ordering> {
+    match (lhs, rhs) {
+        (Primitive::Integer(a), Primitive::Integer(b)) => Some(a.cmp(&b)),
+        (Primitive::Integer(a), Primitive::Float(b)) => to_float(a).partial_cmp(&b),
+        (Primitive::Float(a), Primitive::Integer(b)) => a.partial_cmp(&to_float(b)),
+        (Primitive::Float(a), Primitive::Float(b)) => a.partial_cmp(&b),
+        _ => None,
+    }
+}
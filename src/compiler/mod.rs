@@ -4,16 +4,23 @@
 //! 1. Parse the source string into an AST (Abstract Syntax Tree).
 //! 2. Translate the AST into a list of opcodes ("bytecode") which can be later executed.
 //!
-//! The compiler is split into three modules:
+//! The compiler is split into five modules:
 //! - [`ast`] - Contains data structures representing an AST.
 //! - [`parser`] - Contains the parser, which parses a source string into an AST.
 //! - [`translator`] - Contains the translator, which translates an AST into bytecode.
+//! - [`constant`] - Contains evaluation of constant expressions, used by the translator.
+//! - [`analysis`] - Contains static analysis of an AST, which produces warnings.
 
 use crate::runtime::bytecode::Bytecode;
 
-use self::translator::translate_node;
+use self::{
+    analysis::{analyze, Warning},
+    translator::translate_node,
+};
 
+pub mod analysis;
 pub mod ast;
+pub mod constant;
 pub mod parser;
 pub mod translator;
 
@@ -29,3 +36,16 @@ pub use parser::*;
 pub fn compile(source: impl AsRef<str>) -> Result<Bytecode, anyhow::Error> {
     Ok(translate_node(&parser::parse(source)?))
 }
+
+/// Compile a source string into bytecode, also returning any warnings about the source.
+///
+/// See [`analysis`] for the kinds of warnings produced.
+///
+/// # Errors
+/// Returns an error if the source string could not be compiled.
+pub fn compile_with_warnings(
+    source: impl AsRef<str>,
+) -> Result<(Bytecode, Vec<Warning>), anyhow::Error> {
+    let ast = parser::parse(source)?;
+    Ok((translate_node(&ast), analyze(&ast)))
+}
@@ -4,6 +4,7 @@
 
 use once_cell::sync::OnceCell;
 use pest::{
+    error::{Error, ErrorVariant},
     pratt_parser::{Assoc, Op, PrattParser},
     Parser,
 };
@@ -21,13 +22,129 @@ struct GrammarParser {}
 
 /// Try to parse a string into an [`AstNode`].
 ///
+/// Integer literals which don't fit in 64 bits are reported as errors, as are
+/// functions with more than one parameter of the same name. The smallest integer
+/// can still be written by negating its magnitude:
+///
+/// ```
+/// use scriptyscript::compiler::parse;
+///
+/// assert!(parse("x = 9223372036854775807;").is_ok());
+/// assert!(parse("x = 99999999999999999999999;").is_err());
+/// assert!(parse("x = -9223372036854775808;").is_ok());
+/// assert!(parse("x = 9223372036854775808;").is_err());
+///
+/// assert!(parse("f = fn(a, b) { return a; };").is_ok());
+/// assert!(parse("f = fn(a, a) { return a; };").is_err());
+/// ```
+///
+/// Stray semicolons are empty statements, which are skipped:
+///
+/// ```
+/// use scriptyscript::compiler::compile;
+///
+/// assert_eq!(compile(";").unwrap(), compile("").unwrap());
+/// assert_eq!(
+///     compile("x = 1;; if x > 0 { x = 2; };").unwrap(),
+///     compile("x = 1; if x > 0 { x = 2; }").unwrap(),
+/// );
+/// ```
+///
+/// A trailing expression in a function body is parsed as a `return` statement:
+///
+/// ```
+/// use scriptyscript::compiler::compile;
+///
+/// assert_eq!(
+///     compile("f = fn(x) { y = x; y * y };").unwrap(),
+///     compile("f = fn(x) { y = x; return y * y; };").unwrap(),
+/// );
+/// ```
+///
 /// # Errors
 /// Returns a [`pest::error::Error`] if the string cannot be parsed.
 pub fn parse(s: impl AsRef<str>) -> Result<AstNode, Box<pest::error::Error<Rule>>> {
     let mut pairs = GrammarParser::parse(Rule::script, s.as_ref())?;
+    check_number_literals(pairs.clone())?;
+    check_function_arguments(pairs.clone())?;
     Ok(parse_statements(pairs.next().unwrap().into_inner()))
 }
 
+/// Check that every number literal in the parsed source is in range, so that
+/// building the AST can't fail.
+///
+/// The magnitude of `i64::MIN` is out of range on its own, but is allowed as the direct
+/// operand of a negation, as in `-9223372036854775808` (see [`parse_negated_literal`]).
+fn check_number_literals(pairs: Pairs) -> Result<(), Box<pest::error::Error<Rule>>> {
+    for expression in pairs
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::expression)
+    {
+        let children: Vec<_> = expression.into_inner().collect();
+        for (i, pair) in children.iter().enumerate() {
+            let is_integer = matches!(
+                pair.as_rule(),
+                Rule::dec_literal | Rule::hex_literal | Rule::bin_literal | Rule::oct_literal
+            );
+            if !is_integer || parse_number_literal(pair).is_some() {
+                continue;
+            }
+            // Postfix operators bind tighter than prefix ones, so `-x[0]` negates `x[0]`
+            let negated = i > 0
+                && children[i - 1].as_rule() == Rule::neg
+                && !children.get(i + 1).is_some_and(|next| {
+                    matches!(next.as_rule(), Rule::index | Rule::field | Rule::call)
+                });
+            if !negated || parse_negated_literal(pair).is_none() {
+                return Err(custom_error("integer literal out of range", pair));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that no function in the parsed source has two parameters with the same name.
+fn check_function_arguments(pairs: Pairs) -> Result<(), Box<pest::error::Error<Rule>>> {
+    for arguments in pairs
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::arguments)
+    {
+        let mut names = Vec::new();
+        for name in arguments.into_inner() {
+            if names.contains(&name.as_str()) {
+                return Err(custom_error(
+                    format!("duplicate parameter '{}'", name.as_str()),
+                    &name,
+                ));
+            }
+            names.push(name.as_str());
+        }
+    }
+    Ok(())
+}
+
+/// Create an error with a custom message, pointing at the source of a pair.
+fn custom_error(message: impl Into<String>, pair: &Pair) -> Box<pest::error::Error<Rule>> {
+    Box::new(Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: message.into(),
+        },
+        pair.as_span(),
+    ))
+}
+
+/// Try to parse a string holding only a number literal into a [`Number`].
+///
+/// The string follows the same rules as number literals in source code, so numbers
+/// with a fractional part or an exponent are floats and all others are integers.
+///
+/// Returns `None` if the string isn't a number literal, or if it is out of range.
+#[must_use]
+pub fn parse_number(s: impl AsRef<str>) -> Option<Number> {
+    let mut pairs = GrammarParser::parse(Rule::standalone_number, s.as_ref()).ok()?;
+    parse_number_literal(&pairs.next()?.into_inner().next()?)
+}
+
 /// Parse a block of statements into an [`AstNode`]
 fn parse_statements(pairs: Pairs) -> AstNode {
     AstNode::Block(
@@ -46,6 +163,8 @@ fn parse_statement(pairs: Pairs) -> AstNode {
     let pair = pairs.next().unwrap();
     match pair.as_rule() {
         Rule::assign_statement => parse_assignment(pair.into_inner()),
+        Rule::step_statement => parse_step(pair.into_inner()),
+        Rule::assert_statement => parse_assert_statement(pair.into_inner()),
         Rule::expression => parse_expression(pair.into_inner()),
         Rule::return_statement => parse_return(pair.into_inner()),
         Rule::break_statement => AstNode::Break,
@@ -54,21 +173,95 @@ fn parse_statement(pairs: Pairs) -> AstNode {
         Rule::for_statement => parse_for_statement(pair.into_inner()),
         Rule::while_statement => parse_while_statement(pair.into_inner()),
         Rule::inf_loop_statement => parse_infinite_loop_statement(pair.into_inner()),
+        Rule::repeat_statement => parse_repeat_statement(pair.into_inner()),
+        Rule::with_statement => parse_with_statement(pair.into_inner()),
         _ => unreachable!(),
     }
 }
 
-/// Parse an expression primary into an [`AstNode`]
+/// Parse an assignment into an [`AstNode`]
+///
+/// Assignments to an index (e.g. `x[0] = 1`) produce an [`AstNode::IndexAssignment`],
+/// and assignments to a field (e.g. `x.y = 1`) produce an [`AstNode::FieldAssignment`].
 fn parse_assignment(pairs: Pairs) -> AstNode {
     let mut pairs = pairs;
     let identifier = pairs.next().unwrap().as_str().to_string();
-    let value = pairs.next().unwrap().into_inner();
-    AstNode::Assignment {
-        identifier,
-        value: Box::new(parse_expression(value)),
+    let mut accessors = Vec::new();
+    let mut value = None;
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::index | Rule::field => accessors.push(pair),
+            Rule::expression => value = Some(Box::new(parse_expression(pair.into_inner()))),
+            _ => unreachable!(),
+        }
+    }
+    let value = value.unwrap();
+
+    let Some(last_accessor) = accessors.pop() else {
+        return AstNode::Assignment { identifier, value };
+    };
+    let target = Box::new(
+        accessors
+            .into_iter()
+            .fold(AstNode::Identifier(identifier), parse_accessor),
+    );
+    match last_accessor.as_rule() {
+        Rule::index => AstNode::IndexAssignment {
+            target,
+            index: Box::new(parse_index(last_accessor)),
+            value,
+        },
+        Rule::field => AstNode::FieldAssignment {
+            target,
+            key: parse_field(last_accessor),
+            value,
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Parse an increment (`x++`) or decrement (`x--`) into an [`AstNode`].
+fn parse_step(mut pairs: Pairs) -> AstNode {
+    let identifier = pairs.next().unwrap().as_str().to_string();
+    match pairs.next().unwrap().as_rule() {
+        Rule::increment => AstNode::Increment { identifier },
+        Rule::decrement => AstNode::Decrement { identifier },
+        _ => unreachable!(),
+    }
+}
+
+/// Parse an accessor (an index, a field or a call) applied to the given target into an [`AstNode`].
+fn parse_accessor(target: AstNode, pair: Pair) -> AstNode {
+    match pair.as_rule() {
+        Rule::index => AstNode::Index {
+            target: Box::new(target),
+            index: Box::new(parse_index(pair)),
+        },
+        Rule::field => AstNode::Field {
+            target: Box::new(target),
+            key: parse_field(pair),
+        },
+        Rule::call => AstNode::Call {
+            target: Box::new(target),
+            args: pair
+                .into_inner()
+                .map(|pair| parse_expression(pair.into_inner()))
+                .collect(),
+        },
+        _ => unreachable!(),
     }
 }
 
+/// Parse a field (e.g. the `.y` in `x.y`) into the name of the field.
+fn parse_field(pair: Pair) -> String {
+    pair.into_inner().next().unwrap().as_str().to_string()
+}
+
+/// Parse an index (e.g. the `[0]` in `x[0]`) into the [`AstNode`] for the index expression.
+fn parse_index(pair: Pair) -> AstNode {
+    parse_expression(pair.into_inner().next().unwrap().into_inner())
+}
+
 fn parse_return(pairs: Pairs) -> AstNode {
     let mut pairs = pairs;
     match pairs.next() {
@@ -85,12 +278,19 @@ fn parse_return(pairs: Pairs) -> AstNode {
 fn parse_while_statement(mut pairs: Pairs) -> AstNode {
     let condition = parse_expression(pairs.next().unwrap().into_inner());
     let body = parse_statements(pairs.next().unwrap().into_inner());
+    let else_body = pairs.next().map(|pair| Box::new(parse_else_clause(pair)));
     AstNode::While {
         condition: Box::new(condition),
         body: Box::new(body),
+        else_body,
     }
 }
 
+/// Parse an else clause (e.g. `else { ... }`) into the [`AstNode`] for its block.
+fn parse_else_clause(pair: Pair) -> AstNode {
+    parse_statements(pair.into_inner().next().unwrap().into_inner())
+}
+
 fn parse_infinite_loop_statement(mut pairs: Pairs) -> AstNode {
     let body = parse_statements(pairs.next().unwrap().into_inner());
     AstNode::Loop {
@@ -98,13 +298,51 @@ fn parse_infinite_loop_statement(mut pairs: Pairs) -> AstNode {
     }
 }
 
+fn parse_repeat_statement(mut pairs: Pairs) -> AstNode {
+    // Skip the `repeat` keyword
+    pairs.next();
+    let count = parse_expression(pairs.next().unwrap().into_inner());
+    let body = parse_statements(pairs.next().unwrap().into_inner());
+    AstNode::Repeat {
+        count: Box::new(count),
+        body: Box::new(body),
+    }
+}
+
+/// Parse an assert statement into an [`AstNode`], keeping the source text of its condition.
+fn parse_assert_statement(mut pairs: Pairs) -> AstNode {
+    // Skip the `assert` keyword
+    pairs.next();
+    let condition = pairs.next().unwrap();
+    let source = condition.as_str().trim().to_string();
+    AstNode::Assert {
+        condition: Box::new(parse_expression(condition.into_inner())),
+        message: pairs
+            .next()
+            .map(|pair| Box::new(parse_expression(pair.into_inner()))),
+        source,
+    }
+}
+
+fn parse_with_statement(mut pairs: Pairs) -> AstNode {
+    let resource = parse_expression(pairs.next().unwrap().into_inner());
+    let identifier = pairs.next().unwrap().as_str().to_string();
+    let body = parse_statements(pairs.next().unwrap().into_inner());
+    AstNode::With {
+        resource: Box::new(resource),
+        identifier,
+        body: Box::new(body),
+    }
+}
+
 fn parse_for_statement(mut pairs: Pairs) -> AstNode {
     let mut initialization = None;
     let mut condition = None;
     let mut increment = None;
     let mut body = None;
+    let mut else_body = None;
 
-    for _ in 0..4 {
+    for _ in 0..5 {
         let pair = match pairs.next() {
             Some(pair) => pair,
             None => break,
@@ -118,11 +356,20 @@ fn parse_for_statement(mut pairs: Pairs) -> AstNode {
                 condition = Some(Box::new(parse_expression(pair.into_inner())));
             }
             Rule::for_increment => {
-                increment = Some(Box::new(parse_assignment(pair.into_inner())));
+                let inner = pair.into_inner();
+                let is_step = inner
+                    .clone()
+                    .any(|pair| matches!(pair.as_rule(), Rule::increment | Rule::decrement));
+                increment = Some(Box::new(if is_step {
+                    parse_step(inner)
+                } else {
+                    parse_assignment(inner)
+                }));
             }
             Rule::statements => {
                 body = Some(Box::new(parse_statements(pair.into_inner())));
             }
+            Rule::else_clause => else_body = Some(Box::new(parse_else_clause(pair))),
             _ => unreachable!(),
         };
     }
@@ -134,6 +381,7 @@ fn parse_for_statement(mut pairs: Pairs) -> AstNode {
         condition,
         increment,
         body,
+        else_body,
     }
 }
 
@@ -144,7 +392,9 @@ fn expression_parser() -> &'static PrattParser<Rule> {
     EXPRESSION_PARSER.get_or_init(|| {
         // Infix operators are listed in order of increasing precedence
         PrattParser::new()
-            .op(Op::infix(Rule::op_and, Assoc::Left) | Op::infix(Rule::op_or, Assoc::Left))
+            .op(Op::infix(Rule::op_and, Assoc::Left)
+                | Op::infix(Rule::op_or, Assoc::Left)
+                | Op::infix(Rule::op_xor, Assoc::Left))
             .op(Op::infix(Rule::op_eq, Assoc::Left)
                 | Op::infix(Rule::op_neq, Assoc::Left)
                 | Op::infix(Rule::op_lt, Assoc::Left)
@@ -154,26 +404,67 @@ fn expression_parser() -> &'static PrattParser<Rule> {
             .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
             .op(Op::infix(Rule::mul, Assoc::Left)
                 | Op::infix(Rule::div, Assoc::Left)
-                | Op::infix(Rule::rem, Assoc::Left))
-            .op(Op::prefix(Rule::neg) | Op::prefix(Rule::not))
+                | Op::infix(Rule::rem, Assoc::Left)
+                | Op::infix(Rule::op_mod, Assoc::Left)
+                | Op::infix(Rule::op_div, Assoc::Left))
+            .op(Op::prefix(Rule::pos) | Op::prefix(Rule::neg) | Op::prefix(Rule::not))
+            .op(Op::postfix(Rule::index) | Op::postfix(Rule::field) | Op::postfix(Rule::call))
     })
 }
 
+/// An operand produced partway through parsing an expression.
+///
+/// Comparisons are collected into a chain as they are parsed, and only turned into
+/// an [`AstNode`] once something else uses them as an operand. A parenthesized comparison
+/// is finished by the time the enclosing expression sees it, which keeps `(a < b) < c`
+/// from being mistaken for the chain `a < b < c`.
+enum Operand {
+    Node(AstNode),
+    Chain {
+        operands: Vec<AstNode>,
+        kinds: Vec<BinaryOperationKind>,
+    },
+}
+
+impl Operand {
+    /// Turn the operand into an [`AstNode`]; a chain of one comparison is a plain binary operation.
+    fn finish(self) -> AstNode {
+        match self {
+            Self::Node(node) => node,
+            Self::Chain {
+                mut operands,
+                kinds,
+            } if kinds.len() == 1 => {
+                let right = operands.pop().unwrap();
+                let left = operands.pop().unwrap();
+                AstNode::BinaryOperation {
+                    kind: kinds[0],
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+            Self::Chain { operands, kinds } => AstNode::ComparisonChain { operands, kinds },
+        }
+    }
+}
+
 /// Parse an expression into an [`AstNode`]
 fn parse_expression(pairs: Pairs) -> AstNode {
     expression_parser()
-        .map_primary(parse_expression_primary)
-        .map_prefix(|op, rhs| match op.as_rule() {
-            Rule::neg => AstNode::UnaryOperation {
-                kind: UnaryOperationKind::Negate,
-                operand: Box::new(rhs),
-            },
-            Rule::not => AstNode::UnaryOperation {
-                kind: UnaryOperationKind::Not,
-                operand: Box::new(rhs),
-            },
-            _ => unreachable!(),
+        .map_primary(|pair| Operand::Node(parse_expression_primary(pair)))
+        .map_prefix(|op, rhs| {
+            let kind = match op.as_rule() {
+                Rule::pos => UnaryOperationKind::Plus,
+                Rule::neg => UnaryOperationKind::Negate,
+                Rule::not => UnaryOperationKind::Not,
+                _ => unreachable!(),
+            };
+            Operand::Node(AstNode::UnaryOperation {
+                kind,
+                operand: Box::new(rhs.finish()),
+            })
         })
+        .map_postfix(|lhs, op| Operand::Node(parse_accessor(lhs.finish(), op)))
         .map_infix(|lhs, op, rhs| {
             let kind = match op.as_rule() {
                 Rule::add => BinaryOperationKind::Add,
@@ -181,6 +472,8 @@ fn parse_expression(pairs: Pairs) -> AstNode {
                 Rule::mul => BinaryOperationKind::Multiply,
                 Rule::div => BinaryOperationKind::Divide,
                 Rule::rem => BinaryOperationKind::Remainder,
+                Rule::op_mod => BinaryOperationKind::Modulo,
+                Rule::op_div => BinaryOperationKind::FloorDivide,
                 Rule::op_eq => BinaryOperationKind::Equal,
                 Rule::op_neq => BinaryOperationKind::NotEqual,
                 Rule::op_lt => BinaryOperationKind::LessThan,
@@ -189,16 +482,33 @@ fn parse_expression(pairs: Pairs) -> AstNode {
                 Rule::op_gte => BinaryOperationKind::GreaterThanOrEqual,
                 Rule::op_and => BinaryOperationKind::And,
                 Rule::op_or => BinaryOperationKind::Or,
+                Rule::op_xor => BinaryOperationKind::Xor,
                 _ => unreachable!(),
             };
 
-            AstNode::BinaryOperation {
-                kind,
-                left: Box::new(lhs),
-                right: Box::new(rhs),
+            let rhs = rhs.finish();
+            match lhs {
+                Operand::Chain {
+                    mut operands,
+                    mut kinds,
+                } if kind.is_comparison() => {
+                    operands.push(rhs);
+                    kinds.push(kind);
+                    Operand::Chain { operands, kinds }
+                }
+                Operand::Node(lhs) if kind.is_comparison() => Operand::Chain {
+                    operands: vec![lhs, rhs],
+                    kinds: vec![kind],
+                },
+                lhs => Operand::Node(AstNode::BinaryOperation {
+                    kind,
+                    left: Box::new(lhs.finish()),
+                    right: Box::new(rhs),
+                }),
             }
         })
         .parse(pairs)
+        .finish()
 }
 
 /// Parse an expression primary (i.e. atom) into an [`AstNode`].
@@ -210,14 +520,24 @@ fn parse_expression_primary(pair: Pair) -> AstNode {
         Rule::dec_literal
         | Rule::hex_literal
         | Rule::bin_literal
+        | Rule::oct_literal
         | Rule::float_literal
-        | Rule::scinot_literal => AstNode::NumberLiteral(parse_number_literal(pair)),
+        | Rule::scinot_literal => {
+            let number = parse_number_literal(&pair).or_else(|| parse_negated_literal(&pair));
+            AstNode::NumberLiteral(number.expect("number literal out of range"))
+        }
         Rule::nil_literal => AstNode::NilLiteral,
         Rule::string_literal => AstNode::StringLiteral(parse_string_literal(pair)),
         Rule::bool_literal => AstNode::BooleanLiteral(parse_boolean_literal(pair)),
+        Rule::list_literal => AstNode::ListLiteral(
+            pair.into_inner()
+                .map(|pair| parse_expression(pair.into_inner()))
+                .collect(),
+        ),
         Rule::expression => parse_expression(pair.into_inner()),
         Rule::function_call => parse_function_call(pair.into_inner()),
         Rule::function_def => parse_function_def(pair.into_inner()),
+        Rule::do_block => parse_do_block(pair.into_inner()),
         _ => unreachable!(),
     }
 }
@@ -226,28 +546,46 @@ fn parse_expression_primary(pair: Pair) -> AstNode {
 fn parse_function_call(pairs: Pairs) -> AstNode {
     let mut pairs = pairs;
     let identifier = pairs.next().unwrap().as_str().to_string();
-    AstNode::FunctionCall {
-        identifier,
-        args: pairs
-            .map(|pair| parse_expression(pair.into_inner()))
-            .collect(),
-    }
+    let args = pairs
+        .map(|pair| parse_expression(pair.into_inner()))
+        .collect();
+    AstNode::FunctionCall { identifier, args }
 }
 
 fn parse_function_def_arguments(pairs: Pairs) -> Vec<String> {
     pairs.map(|pair| pair.as_str().to_string()).collect()
 }
 
+/// Parse a function definition into an [`AstNode`].
+///
+/// A trailing expression in the body becomes a `return` statement.
 fn parse_function_def(pairs: Pairs) -> AstNode {
     let mut pairs = pairs;
     let args = parse_function_def_arguments(pairs.next().unwrap().into_inner());
-    let body = parse_statements(pairs.next().unwrap().into_inner());
+    let mut body = parse_statements(pairs.next().unwrap().into_inner());
+    if let (Some(value), AstNode::Block(statements)) = (pairs.next(), &mut body) {
+        statements.push(AstNode::Return {
+            value: Some(Box::new(parse_expression(value.into_inner()))),
+        });
+    }
     AstNode::FunctionDef {
         args,
         body: Box::new(body),
     }
 }
 
+fn parse_do_block(pairs: Pairs) -> AstNode {
+    let mut pairs = pairs;
+    let body = parse_statements(pairs.next().unwrap().into_inner());
+    let value = pairs
+        .next()
+        .map(|pair| Box::new(parse_expression(pair.into_inner())));
+    AstNode::Do {
+        body: Box::new(body),
+        value,
+    }
+}
+
 fn parse_if(pairs: Pairs) -> AstNode {
     let mut pairs = pairs;
     let condition = pairs.next().unwrap().into_inner();
@@ -255,9 +593,7 @@ fn parse_if(pairs: Pairs) -> AstNode {
     let else_body = match pairs.next() {
         Some(pair) => match pair.as_rule() {
             Rule::elseif_clause => Some(Box::new(parse_if(pair.into_inner()))),
-            Rule::else_clause => Some(Box::new(parse_statements(
-                pair.into_inner().next().unwrap().into_inner(),
-            ))),
+            Rule::else_clause => Some(Box::new(parse_else_clause(pair))),
             _ => unreachable!(),
         },
         None => None,
@@ -270,16 +606,41 @@ fn parse_if(pairs: Pairs) -> AstNode {
 }
 
 /// Parse a number literal into a [`Number`].
-fn parse_number_literal(pair: Pair) -> Number {
+///
+/// Returns `None` if the literal is out of range.
+fn parse_number_literal(pair: &Pair) -> Option<Number> {
     match pair.as_rule() {
-        Rule::dec_literal | Rule::hex_literal | Rule::bin_literal => {
-            Number::Integer(pair.as_str().parse().unwrap())
+        Rule::dec_literal => pair.as_str().parse().ok().map(Number::Integer),
+        Rule::hex_literal | Rule::bin_literal | Rule::oct_literal => {
+            parse_prefixed_integer(pair.as_str()).map(Number::Integer)
         }
-        Rule::float_literal | Rule::scinot_literal => Number::Float(pair.as_str().parse().unwrap()),
+        Rule::float_literal | Rule::scinot_literal => pair.as_str().parse().ok().map(Number::Float),
         _ => unreachable!(),
     }
 }
 
+/// Parse an integer literal which is only in range once negated, i.e. the magnitude of `i64::MIN`.
+///
+/// The literal is parsed as `i64::MIN`, which negating leaves unchanged, so the
+/// negation applied to it gives the intended value.
+fn parse_negated_literal(pair: &Pair) -> Option<Number> {
+    parse_number(format!("-{}", pair.as_str()))
+}
+
+/// Parse an integer literal with a radix prefix (e.g. `-0x1F`, `0b101`, or `0o17`).
+fn parse_prefixed_integer(literal: &str) -> Option<i64> {
+    let (sign, literal) = literal
+        .strip_prefix(['+', '-'])
+        .map_or(("", literal), |unsigned| (&literal[..1], unsigned));
+    let radix = match &literal[..2] {
+        "0x" | "0X" => 16,
+        "0b" | "0B" => 2,
+        "0o" | "0O" => 8,
+        _ => unreachable!(),
+    };
+    i64::from_str_radix(&format!("{sign}{}", &literal[2..]), radix).ok()
+}
+
 /// Parse a string literal into a `String`.
 fn parse_string_literal(pair: Pair) -> String {
     let token = pair.as_str();
@@ -3,10 +3,13 @@
 //! There's a single public function, [`translate_node`], which can be used to translate any
 //! node in an AST (including the root node) into its bytecode representation.
 
-use std::borrow::Borrow;
+use std::{borrow::Borrow, sync::Arc};
 
-use super::ast::{AstNode, Number};
-use crate::runtime::bytecode::{Bytecode, OpCode};
+use super::{
+    ast::{AstNode, BinaryOperationKind, Number, UnaryOperationKind},
+    constant,
+};
+use crate::runtime::bytecode::{Bytecode, OpCode, SlotNames};
 
 impl<T: Borrow<AstNode>> From<T> for Bytecode {
     fn from(node: T) -> Self {
@@ -16,6 +19,18 @@ impl<T: Borrow<AstNode>> From<T> for Bytecode {
 
 /// Translates an AST node into a list of opcodes which can be executed on a state.
 ///
+/// Negated number literals are translated into a single push of the negative number:
+///
+/// ```
+/// use scriptyscript::{
+///     compiler::{parse, translator::translate_node},
+///     runtime::bytecode::OpCode,
+/// };
+///
+/// let bytecode = translate_node(&parse("-5;").unwrap());
+/// assert_eq!(bytecode.iter().collect::<Vec<_>>(), [&OpCode::PushInteger(-5)]);
+/// ```
+///
 /// # Errors
 /// Returns an error if the AST node could not be compiled.
 #[must_use]
@@ -33,12 +48,58 @@ pub fn translate_node(ast: &AstNode) -> Bytecode {
             inner.extend(translate_node(value));
             inner.push(OpCode::Store(identifier.clone()));
         }
+        AstNode::Increment { identifier } => {
+            inner.extend(translate_step(identifier, BinaryOperationKind::Add));
+        }
+        AstNode::Decrement { identifier } => {
+            inner.extend(translate_step(identifier, BinaryOperationKind::Subtract));
+        }
+        AstNode::IndexAssignment {
+            target,
+            index,
+            value,
+        } => {
+            inner.extend(translate_node(target));
+            inner.extend(translate_node(index));
+            inner.extend(translate_node(value));
+            inner.push(OpCode::SetIndex);
+        }
+        AstNode::FieldAssignment { target, key, value } => {
+            inner.extend(translate_node(target));
+            inner.extend(translate_node(value));
+            inner.push(OpCode::SetKey(key.clone()));
+        }
+        AstNode::Field { target, key } => {
+            inner.extend(translate_node(target));
+            inner.push(OpCode::GetKey {
+                key: key.clone(),
+                target: describe_target(target),
+            });
+        }
+        AstNode::Index { target, index } => {
+            inner.extend(translate_node(target));
+            inner.extend(translate_node(index));
+            inner.push(OpCode::GetIndex);
+        }
         AstNode::FunctionCall { identifier, args } => {
             for arg in args.iter() {
                 inner.extend(translate_node(arg));
             }
             inner.push(OpCode::Load(identifier.clone()));
-            inner.push(OpCode::Call(args.len()));
+            inner.push(OpCode::Call {
+                args: args.len(),
+                name: Some(identifier.as_str().into()),
+            });
+        }
+        AstNode::Call { target, args } => {
+            for arg in args {
+                inner.extend(translate_node(arg));
+            }
+            inner.extend(translate_node(target));
+            inner.push(OpCode::Call {
+                args: args.len(),
+                name: describe_target(target).map(Arc::from),
+            });
         }
         AstNode::FunctionDef { args, body } => {
             let mut translated_body = Bytecode::new();
@@ -48,7 +109,50 @@ pub fn translate_node(ast: &AstNode) -> Bytecode {
                     .push(OpCode::Store(name.clone()))
             }
             translated_body.inner_mut().extend(translate_node(body));
-            inner.push(OpCode::PushFunction(translated_body));
+            inner.push(OpCode::PushFunction {
+                locals: Arc::new(resolve_locals(&mut translated_body)),
+                body: translated_body,
+            });
+        }
+        AstNode::Do { .. }
+        | AstNode::Return { .. }
+        | AstNode::Break
+        | AstNode::Continue
+        | AstNode::If { .. }
+        | AstNode::For { .. }
+        | AstNode::While { .. }
+        | AstNode::Loop { .. }
+        | AstNode::Assert { .. }
+        | AstNode::Repeat { .. }
+        | AstNode::With { .. } => inner.extend(translate_control_flow(ast)),
+        AstNode::BinaryOperation { .. }
+        | AstNode::ComparisonChain { .. }
+        | AstNode::UnaryOperation { .. }
+        | AstNode::Identifier(_)
+        | AstNode::NumberLiteral(_)
+        | AstNode::StringLiteral(_)
+        | AstNode::BooleanLiteral(_)
+        | AstNode::ListLiteral(_)
+        | AstNode::NilLiteral => inner.extend(translate_expression(ast)),
+    }
+    result
+}
+
+/// Translate a statement which changes the flow of control, such as a loop or `return`.
+fn translate_control_flow(ast: &AstNode) -> Bytecode {
+    let mut result = Bytecode::new();
+    let inner = result.inner_mut();
+
+    match ast {
+        AstNode::Do { body, value } => {
+            let mut translated_body = translate_node(body);
+            match value {
+                Some(value) => translated_body.inner_mut().extend(translate_node(value)),
+                None => translated_body.inner_mut().push(OpCode::PushNil),
+            }
+            inner.push(OpCode::Do {
+                body: translated_body,
+            });
         }
         AstNode::Return { value } => {
             // Return can be empty, or can return the result of an expression.
@@ -59,42 +163,52 @@ pub fn translate_node(ast: &AstNode) -> Bytecode {
             }
             inner.push(OpCode::Return(n));
         }
-        AstNode::Break => {
-            inner.push(OpCode::Break);
-        }
-        AstNode::Continue => {
-            inner.push(OpCode::Continue);
-        }
+        AstNode::Break => inner.push(OpCode::Break),
+        AstNode::Continue => inner.push(OpCode::Continue),
         AstNode::If {
             condition,
             body,
             else_body,
-        } => {
-            inner.push(OpCode::If {
+        } => match constant::evaluate_condition(condition) {
+            // Only the branch which is taken needs to be emitted
+            Some(true) => inner.extend(translate_node(body)),
+            Some(false) => {
+                if let Some(else_body) = else_body {
+                    inner.extend(translate_node(else_body));
+                }
+            }
+            None => inner.push(OpCode::If {
                 condition: translate_node(condition),
                 body: translate_node(body),
                 else_body: else_body
                     .as_ref()
                     .map(|else_body| translate_node(else_body)),
-            });
-        }
+            }),
+        },
         AstNode::For {
             initialization,
             condition,
             increment,
             body,
+            else_body,
         } => {
             inner.push(OpCode::For {
                 initialization: initialization.as_ref().map(|node| translate_node(node)),
                 condition: condition.as_ref().map(|node| translate_node(node)),
                 increment: increment.as_ref().map(|node| translate_node(node)),
                 body: translate_node(body),
+                else_body: else_body.as_ref().map(|node| translate_node(node)),
             });
         }
-        AstNode::While { condition, body } => {
+        AstNode::While {
+            condition,
+            body,
+            else_body,
+        } => {
             inner.push(OpCode::While {
                 condition: translate_node(condition),
                 body: translate_node(body),
+                else_body: else_body.as_ref().map(|node| translate_node(node)),
             });
         }
         AstNode::Loop { body } => {
@@ -102,15 +216,61 @@ pub fn translate_node(ast: &AstNode) -> Bytecode {
                 body: translate_node(body),
             });
         }
+        AstNode::Assert {
+            condition,
+            message,
+            source,
+        } => inner.extend(translate_assert(condition, message.as_deref(), source)),
+        AstNode::Repeat { count, body } => {
+            inner.push(OpCode::Repeat {
+                count: translate_node(count),
+                body: translate_node(body),
+            });
+        }
+        AstNode::With {
+            resource,
+            identifier,
+            body,
+        } => {
+            inner.push(OpCode::With {
+                resource: translate_node(resource),
+                identifier: identifier.clone(),
+                body: translate_node(body),
+            });
+        }
+        _ => unreachable!("not a control flow node"),
+    }
+    result
+}
+
+/// Translate an expression other than a function call, index, or function definition.
+fn translate_expression(ast: &AstNode) -> Bytecode {
+    let mut result = Bytecode::new();
+    let inner = result.inner_mut();
+
+    match ast {
         AstNode::BinaryOperation { kind, left, right } => {
             inner.extend(translate_node(left));
             inner.extend(translate_node(right));
             inner.push(OpCode::BinaryOperation(*kind));
         }
-        AstNode::UnaryOperation { kind, operand } => {
-            inner.extend(translate_node(operand));
-            inner.push(OpCode::UnaryOperation(*kind));
+        AstNode::ComparisonChain { operands, kinds } => {
+            inner.extend(translate_comparison_chain(operands, kinds));
         }
+        AstNode::UnaryOperation { kind, operand } => match (kind, operand.as_ref()) {
+            // Negative literals are parsed as a negation, but are pushed directly. The magnitude
+            // of `i64::MIN` is parsed as `i64::MIN` itself, which wrapping negation keeps.
+            (UnaryOperationKind::Negate, AstNode::NumberLiteral(Number::Integer(x))) => {
+                inner.push(OpCode::PushInteger(x.wrapping_neg()));
+            }
+            (UnaryOperationKind::Negate, AstNode::NumberLiteral(Number::Float(x))) => {
+                inner.push(OpCode::PushFloat(-x));
+            }
+            _ => {
+                inner.extend(translate_node(operand));
+                inner.push(OpCode::UnaryOperation(*kind));
+            }
+        },
         AstNode::Identifier(identifier) => {
             inner.push(OpCode::Load(identifier.clone()));
         }
@@ -124,9 +284,187 @@ pub fn translate_node(ast: &AstNode) -> Bytecode {
         AstNode::BooleanLiteral(boolean) => {
             inner.push(OpCode::PushBool(*boolean));
         }
+        AstNode::ListLiteral(elements) => {
+            for element in elements {
+                inner.extend(translate_node(element));
+            }
+            inner.push(OpCode::PushList(elements.len()));
+        }
         AstNode::NilLiteral => {
             inner.push(OpCode::PushNil);
         }
+        _ => unreachable!("not an expression node"),
+    }
+    result
+}
+
+/// Assign a slot to each local stored by the bytecode of a function body, and replace
+/// loads and stores of those locals with loads and stores of their slots.
+///
+/// Control flow bodies run in the function's call frame, so their locals are resolved too,
+/// but nested function definitions and `do` blocks have their own call frames and are skipped.
+///
+/// Returns the names of the locals, indexed by their slot.
+fn resolve_locals(body: &mut Bytecode) -> SlotNames {
+    fn collect(body: &Bytecode, names: &mut Vec<String>) {
+        for opcode in body.iter() {
+            match opcode {
+                OpCode::Store(name)
+                | OpCode::With {
+                    identifier: name, ..
+                } if !names.contains(name) => names.push(name.clone()),
+                OpCode::PushFunction { .. } | OpCode::Do { .. } => continue,
+                _ => {}
+            }
+            for nested in opcode.bodies() {
+                collect(nested, names);
+            }
+        }
+    }
+
+    fn replace(body: &mut Bytecode, slots: &SlotNames) {
+        for opcode in body.iter_mut() {
+            let slot = match opcode {
+                OpCode::Load(name) | OpCode::Store(name) => slots.slot(name),
+                _ => None,
+            };
+            match (slot, &*opcode) {
+                (Some(slot), OpCode::Load(_)) => *opcode = OpCode::LoadLocal(slot),
+                (Some(slot), OpCode::Store(_)) => *opcode = OpCode::StoreLocal(slot),
+                (_, OpCode::PushFunction { .. } | OpCode::Do { .. }) => {}
+                _ => {
+                    for nested in opcode.bodies_mut() {
+                        replace(nested, slots);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    collect(body, &mut names);
+    let slots = SlotNames::from(names);
+    replace(body, &slots);
+    slots
+}
+
+/// Translate an increment or decrement of a variable, which is sugar for
+/// adding or subtracting one and storing the result back to the variable.
+fn translate_step(identifier: &str, kind: BinaryOperationKind) -> [OpCode; 4] {
+    [
+        OpCode::Load(identifier.to_owned()),
+        OpCode::PushInteger(1),
+        OpCode::BinaryOperation(kind),
+        OpCode::Store(identifier.to_owned()),
+    ]
+}
+
+/// Translate an assert statement, recording the source text of its condition and the
+/// variables it uses so that failures can report them.
+fn translate_assert(condition: &AstNode, message: Option<&AstNode>, source: &str) -> Bytecode {
+    let mut variables = Vec::new();
+    collect_variables(condition, &mut variables);
+    let mut result = Bytecode::new();
+    result.inner_mut().push(OpCode::Assert {
+        condition: translate_node(condition),
+        message: message.map(translate_node),
+        source: source.to_owned(),
+        variables,
+    });
+    result
+}
+
+/// Collect the names of the variables read by an expression, without duplicates.
+///
+/// Function definitions are not searched, since their variables aren't in scope.
+fn collect_variables(node: &AstNode, names: &mut Vec<String>) {
+    match node {
+        AstNode::Identifier(name) => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        AstNode::FunctionDef { .. } => {}
+        _ => {
+            for child in node.children() {
+                collect_variables(child, names);
+            }
+        }
+    }
+}
+
+/// Translate a comparison chain (e.g. `a < b < c`) into the equivalent of
+/// `a < b and b < c`, evaluating each operand only once.
+///
+/// Every operand except the first and last is duplicated and rotated below the left
+/// operand of its comparison, so it stays on the stack for the next comparison. The
+/// results of the comparisons are kept below it and combined once they are all done.
+fn translate_comparison_chain(operands: &[AstNode], kinds: &[BinaryOperationKind]) -> Bytecode {
+    let mut result = Bytecode::new();
+    let inner = result.inner_mut();
+
+    inner.extend(translate_node(&operands[0]));
+    for (i, (kind, operand)) in kinds.iter().zip(&operands[1..]).enumerate() {
+        inner.extend(translate_node(operand));
+        if i + 1 < kinds.len() {
+            // Keep a copy of the right operand below the result of the comparison
+            inner.extend([OpCode::Dup, OpCode::Rotate]);
+            inner.push(OpCode::BinaryOperation(*kind));
+            inner.push(OpCode::Swap);
+        } else {
+            inner.push(OpCode::BinaryOperation(*kind));
+        }
+    }
+    for _ in 1..kinds.len() {
+        inner.push(OpCode::BinaryOperation(BinaryOperationKind::And));
     }
     result
 }
+
+/// Describe the source text of an accessed expression, for use in error messages.
+///
+/// Only variables and field accesses (e.g. `a.b`) can be described.
+fn describe_target(target: &AstNode) -> Option<String> {
+    match target {
+        AstNode::Identifier(identifier) => Some(identifier.clone()),
+        AstNode::Field { target, key } => Some(format!("{}.{key}", describe_target(target)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::translate_node;
+    use crate::{compiler::parse, runtime::bytecode::OpCode};
+
+    fn translate(source: &str) -> Vec<OpCode> {
+        translate_node(&parse(source).unwrap()).into_inner()
+    }
+
+    #[test]
+    fn negated_integer_literals_are_pushed_directly() {
+        assert_eq!(translate("-5;"), [OpCode::PushInteger(-5)]);
+        assert_eq!(
+            translate("-9223372036854775807;"),
+            [OpCode::PushInteger(-i64::MAX)]
+        );
+        assert_eq!(
+            translate("-9223372036854775808;"),
+            [OpCode::PushInteger(i64::MIN)]
+        );
+        assert_eq!(
+            translate("-0x8000000000000000;"),
+            [OpCode::PushInteger(i64::MIN)]
+        );
+    }
+
+    #[test]
+    fn function_frames_are_sized_by_their_distinct_locals() {
+        let source = "f = fn(a) { b = a; if a { b = 1; c = 2; } g = fn() { d = 3; }; };";
+        let [OpCode::PushFunction { locals, .. }, OpCode::Store(_)] = &translate(source)[..] else {
+            panic!("expected a function definition");
+        };
+        // Stored twice but counted once, and the nested function's locals are its own
+        assert_eq!(locals[..], ["a", "b", "c", "g"]);
+    }
+}
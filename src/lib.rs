@@ -8,6 +8,8 @@
 //!     - A [translator](compiler::translator) which translates an AST into bytecode.
 //! - A [runtime] which executes bytecode.
 //! - A [standard library](stdlib) which contains built-in functions and types that are available to scripts.
+//! - A [REPL](repl) which runs statements as they are typed.
 pub mod compiler;
+pub mod repl;
 pub mod runtime;
 pub mod stdlib;
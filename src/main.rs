@@ -6,21 +6,37 @@ use std::path::{Path, PathBuf};
 
 use clap::Parser;
 
-use scriptyscript::runtime::{executor::execute_source, state::State};
+use scriptyscript::{
+    compiler::compile_with_warnings,
+    repl,
+    runtime::{state::State, types::object::Object},
+};
 
 /// Program arguments.
 #[derive(clap::Parser)]
 struct Arguments {
     /// Script file to run
     file: Option<PathBuf>,
+    /// Arguments passed to the script as the `args` global
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    script_args: Vec<String>,
     /// Show compiler output for the given file
     #[arg(short, long, default_value_t = false)]
     bytecode: bool,
+    /// Maximum number of instructions a script may execute
+    #[arg(long)]
+    max_instructions: Option<u64>,
+    /// Maximum depth of nested function calls
+    #[arg(long)]
+    max_depth: Option<usize>,
 }
 
 fn main() {
     let args = Arguments::parse();
     let mut state = State::new();
+    state.set_instruction_limit(args.max_instructions);
+    state.set_call_depth_limit(args.max_depth);
+    state.set_args(&args.script_args);
 
     if let Some(file) = args.file {
         if args.bytecode {
@@ -29,74 +45,35 @@ fn main() {
             run_file(&mut state, file);
         }
     } else {
-        repl::run(&mut state);
+        repl::run(&mut state, &repl::Config::default());
     }
 }
 
 /// Run a script file on the given state.
+///
+/// Exits the process with a non-zero status if the script fails.
 fn run_file(state: &mut State, file: impl AsRef<Path>) {
     let source = std::fs::read_to_string(file).unwrap();
-    execute_source(state, &source).unwrap();
+    if let Err(e) = execute(state, &source) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Compile and run source code on the given state, printing any compiler warnings.
+///
+/// Returns the value left on top of the stack by the source, if any.
+fn execute(state: &mut State, source: &str) -> Result<Option<Object>, anyhow::Error> {
+    let (bytecode, warnings) = compile_with_warnings(source)?;
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+    Ok(state.run(&bytecode)?)
 }
 
 /// Show the compiled bytecode for a script file.
 fn show_bytecode(file: impl AsRef<Path>) {
     let source = std::fs::read_to_string(file).unwrap();
     let bytecode = scriptyscript::compiler::compile(source).unwrap();
-    println!("{:?}", bytecode);
-}
-
-/// REPL-related functionality.
-mod repl {
-    use std::io::Write;
-
-    use scriptyscript::{
-        runtime::{executor::execute_source, state::State, types::primitive::Primitive},
-        stdlib::to_string,
-    };
-
-    /// Main entry point for the REPL.
-    ///
-    /// Runs continuously until the user exits.
-    pub fn run(state: &mut State) {
-        loop {
-            let input = next_statement();
-
-            let pushed_amt = execute_source(state, &input);
-            if let Err(e) = pushed_amt {
-                println!("Error: {}", e);
-                continue;
-            }
-            display_top(state);
-        }
-    }
-
-    /// Display the object at the top of the stack.
-    ///
-    /// Will pop the object from the stack, if it exists.
-    fn display_top(state: &mut State) {
-        if state.peek().is_some() {
-            let pushed_amt = to_string(state, 1);
-            assert_eq!(pushed_amt, 1);
-            let primitive = state.pop().unwrap().as_primitive();
-            match primitive {
-                Some(Primitive::String(s)) => println!("{}", s),
-                _ => panic!("expected string primitive"),
-            }
-        }
-    }
-
-    /// Read a statement from the user.
-    fn next_statement() -> String {
-        print!(">> ");
-        let _ = std::io::stdout().lock().flush();
-        // read input from user
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        input = input.trim_end().to_owned();
-        if !input.ends_with(';') {
-            input.push(';');
-        }
-        input
-    }
+    print!("{}", bytecode.to_text());
 }
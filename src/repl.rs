@@ -0,0 +1,370 @@
+//! Module containing the REPL (read-eval-print loop).
+//!
+//! [`run`] is the REPL of the `scriptyscript` executable. Embedders can run the same REPL
+//! on their own streams with [`run_with`], customize it with a [`Config`], or build their
+//! own loop from [`next_statement`] and [`evaluate`].
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use rustyline::DefaultEditor;
+
+use crate::{
+    compiler::compile_with_warnings,
+    runtime::{state::State, types::object::Object},
+    stdlib::to_string,
+};
+
+/// Name of the global variable holding the last displayed value.
+const LAST_RESULT: &str = "_";
+
+/// Name of the file in the user's home directory where input history is stored.
+const HISTORY_FILE: &str = ".scriptyscript_history";
+
+/// Options controlling how the REPL looks and behaves.
+pub struct Config {
+    /// Printed before reading each statement.
+    pub prompt: String,
+    /// Printed before reading each further line of a statement with unclosed brackets.
+    pub continuation_prompt: String,
+    /// Whether the value of each statement is displayed.
+    pub echo_results: bool,
+    /// Printed once when the REPL starts, if any.
+    pub greeting: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prompt: ">> ".to_owned(),
+            continuation_prompt: ".. ".to_owned(),
+            echo_results: true,
+            greeting: None,
+        }
+    }
+}
+
+/// Main entry point for the REPL, reading from stdin and writing to stdout.
+///
+/// Statements are read with a line editor, and recorded in a history file in the user's
+/// home directory which is loaded again on startup. Runs until the end of the input.
+pub fn run(state: &mut State, config: &Config) {
+    let Ok(mut editor) = Editor::new(history_path()) else {
+        run_with(
+            state,
+            config,
+            &mut std::io::stdin().lock(),
+            &mut std::io::stdout(),
+            |_| {},
+        );
+        return;
+    };
+    let mut output = std::io::stdout();
+    if let Some(greeting) = &config.greeting {
+        let _ = writeln!(output, "{greeting}");
+    }
+    while let Some(statement) = editor.next_statement(config) {
+        editor.record(&statement);
+        run_statement(state, config, &mut output, &statement);
+    }
+}
+
+/// Run the REPL on the given streams, calling `on_statement` with each statement read.
+///
+/// Runs until the end of the input.
+///
+/// ```
+/// use scriptyscript::{repl::{run_with, Config}, runtime::state::State};
+///
+/// let config = Config {
+///     prompt: "> ".to_owned(),
+///     ..Config::default()
+/// };
+/// let mut output = Vec::new();
+/// run_with(&mut State::new(), &config, &mut "1 + 2\n".as_bytes(), &mut output, |_| {});
+/// assert_eq!(String::from_utf8(output).unwrap(), "> 3\n> ");
+/// ```
+pub fn run_with(
+    state: &mut State,
+    config: &Config,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    mut on_statement: impl FnMut(&str),
+) {
+    if let Some(greeting) = &config.greeting {
+        let _ = writeln!(output, "{greeting}");
+    }
+    while let Some(statement) = next_statement(input, output, config) {
+        on_statement(&statement);
+        run_statement(state, config, output, &statement);
+    }
+}
+
+/// Run a statement read by the REPL, displaying its value or error.
+fn run_statement(state: &mut State, config: &Config, output: &mut impl Write, statement: &str) {
+    match evaluate(state, statement) {
+        Ok(Some(result)) if config.echo_results => display(state, output, &result),
+        Ok(_) => {}
+        Err(e) => {
+            let _ = writeln!(output, "Error: {e}");
+        }
+    }
+}
+
+/// Run a statement, storing its value (if any) in the global `_` variable.
+///
+/// Compiler warnings are printed to stderr.
+///
+/// # Errors
+/// Returns an error if the statement cannot be compiled, or fails while running.
+pub fn evaluate(state: &mut State, input: &str) -> Result<Option<Object>, anyhow::Error> {
+    let (bytecode, warnings) = compile_with_warnings(input)?;
+    for warning in warnings {
+        eprintln!("Warning: {warning}");
+    }
+    let result = state.run(&bytecode)?;
+    if let Some(result) = &result {
+        state.set_global(LAST_RESULT, result.clone());
+    }
+    Ok(result)
+}
+
+/// Display the result of a statement, or the error raised while converting it to a string.
+fn display(state: &mut State, output: &mut impl Write, result: &Object) {
+    state.push(result);
+    let _ = match to_string(state, 1).and_then(|_| state.pop_string()) {
+        Ok(s) => writeln!(output, "{s}"),
+        Err(e) => writeln!(output, "Error: {e}"),
+    };
+}
+
+/// Read a statement, writing the prompts of the config to the given output.
+///
+/// Further lines are read while the statement has unclosed brackets. A `;` is added
+/// to the end of the statement if it is missing. Returns `None` at the end of the input.
+///
+/// ```
+/// use scriptyscript::repl::{next_statement, Config};
+///
+/// let mut output = Vec::new();
+/// let statement = next_statement(&mut "f(\n1)\n".as_bytes(), &mut output, &Config::default());
+/// assert_eq!(statement.as_deref(), Some("f(\n1);"));
+/// assert_eq!(output, b">> .. ");
+/// ```
+pub fn next_statement(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    config: &Config,
+) -> Option<String> {
+    read_statement(config, |prompt| {
+        let _ = write!(output, "{prompt}");
+        let _ = output.flush();
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(read) if read > 0 => Some(line),
+            _ => None,
+        }
+    })
+}
+
+/// Read a statement line by line, showing the prompts of the config.
+///
+/// `read_line` is given the prompt to show, and gives the line read including its line
+/// ending, or `None` at the end of the input.
+fn read_statement(
+    config: &Config,
+    mut read_line: impl FnMut(&str) -> Option<String>,
+) -> Option<String> {
+    let mut statement = String::new();
+    let mut prompt = &config.prompt;
+    loop {
+        let Some(line) = read_line(prompt) else {
+            if statement.is_empty() {
+                return None;
+            }
+            break;
+        };
+        statement.push_str(&line);
+        if !has_unclosed_brackets(&statement) {
+            break;
+        }
+        prompt = &config.continuation_prompt;
+    }
+    let mut statement = statement.trim_end().to_owned();
+    if !statement.ends_with(';') {
+        statement.push(';');
+    }
+    Some(statement)
+}
+
+/// Whether some source has more opening than closing brackets, ignoring strings and comments.
+fn has_unclosed_brackets(source: &str) -> bool {
+    let mut depth = 0i64;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '#' => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+/// Get the path of the history file, if a home directory is available.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+}
+
+/// Line editor whose input history is persisted across sessions.
+struct Editor {
+    editor: DefaultEditor,
+    /// File the history is loaded from and appended to, if any.
+    history: Option<PathBuf>,
+}
+
+impl Editor {
+    /// Create an editor, loading the history of earlier sessions from the given file.
+    fn new(history: Option<PathBuf>) -> rustyline::Result<Self> {
+        let mut editor = DefaultEditor::new()?;
+        if let Some(path) = &history {
+            // The file doesn't exist before the first session
+            let _ = editor.load_history(path);
+        }
+        Ok(Self { editor, history })
+    }
+
+    /// Read a statement, see [`next_statement`].
+    fn next_statement(&mut self, config: &Config) -> Option<String> {
+        read_statement(config, |prompt| {
+            self.editor.readline(prompt).ok().map(|line| line + "\n")
+        })
+    }
+
+    /// Record a statement in the history, appending it to the history file.
+    fn record(&mut self, input: &str) {
+        if input.trim() == ";" {
+            return;
+        }
+        let _ = self.editor.add_history_entry(input);
+        if let Some(path) = &self.history {
+            let _ = self.editor.append_history(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::{state::State, types::primitive::Primitive};
+
+    use super::{evaluate, next_statement, run_with, Config, Editor};
+
+    /// Run the REPL on some input, giving its output.
+    fn session(config: &Config, input: &str) -> String {
+        let mut output = Vec::new();
+        run_with(
+            &mut State::new(),
+            config,
+            &mut input.as_bytes(),
+            &mut output,
+            |_| {},
+        );
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn last_result_is_stored() {
+        let mut state = State::new();
+        evaluate(&mut state, "1 + 2;").unwrap();
+        let doubled = evaluate(&mut state, "_ * 2;").unwrap().unwrap();
+        assert_eq!(doubled.as_primitive(), Some(Primitive::Integer(6)));
+    }
+
+    #[test]
+    fn statements_without_a_value_keep_the_last_result() {
+        let mut state = State::new();
+        evaluate(&mut state, "5;").unwrap();
+        evaluate(&mut state, "x = 1;").unwrap();
+        let last = evaluate(&mut state, "_;").unwrap().unwrap();
+        assert_eq!(last.as_primitive(), Some(Primitive::Integer(5)));
+    }
+
+    #[test]
+    fn configured_prompts_are_written() {
+        let config = Config {
+            prompt: "ss> ".to_owned(),
+            continuation_prompt: "... ".to_owned(),
+            echo_results: true,
+            greeting: Some("hello".to_owned()),
+        };
+        assert_eq!(
+            session(&config, "x = [\n1, 2\n];\nlen(x)\n"),
+            "hello\nss> ... ... ss> 2\nss> "
+        );
+
+        let mut output = Vec::new();
+        let statement = next_statement(&mut &b"1\n"[..], &mut output, &config);
+        assert_eq!(statement.as_deref(), Some("1;"));
+        assert_eq!(output, b"ss> ");
+    }
+
+    #[test]
+    fn results_are_only_echoed_if_configured() {
+        let config = Config {
+            echo_results: false,
+            ..Config::default()
+        };
+        assert_eq!(session(&config, "1 + 2\n_ * 2\n"), ">> >> >> ");
+        assert_eq!(
+            session(&Config::default(), "1 + 2\n_ * 2\n"),
+            ">> 3\n>> 6\n>> "
+        );
+        // Errors are shown either way
+        assert!(session(&config, "1 + nil\n").contains("Error: "));
+    }
+
+    #[test]
+    fn history_is_loaded_in_later_sessions() {
+        let path =
+            std::env::temp_dir().join(format!("scriptyscript_history_test_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut editor = Editor::new(Some(path.clone())).unwrap();
+        editor.record("x = 1;");
+        editor.record(";");
+        editor.record("f(\n1);");
+        let mut editor = Editor::new(Some(path.clone())).unwrap();
+        editor.record("x;");
+
+        let editor = Editor::new(Some(path.clone())).unwrap();
+        let entries: Vec<_> = editor.editor.history().iter().collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries, ["x = 1;", "f(\n1);", "x;"]);
+    }
+}
@@ -1,4 +1,17 @@
 //! Module containing [`OpCode`]s and the [`Bytecode`] container.
+//!
+//! Bytecode can also be written and read in a readable [text format](text).
+
+pub mod text;
+
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -8,16 +21,44 @@ use crate::compiler::{BinaryOperationKind, UnaryOperationKind};
 ///
 /// Currently this is simply a wrapper around a `Vec<OpCode>`. This type
 /// should be used rather than `Vec<OpCode>` for forward-compatibility.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+///
+/// Each container also has an [id](Bytecode::id) which is shared by its clones, and
+/// ignored when comparing containers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Bytecode {
     inner: Vec<OpCode>,
+    #[serde(skip, default = "next_id")]
+    id: u64,
+}
+
+impl PartialEq for Bytecode {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+/// Make an id which no other bytecode container has been given.
+fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Bytecode {
     /// Create an empty bytecode container.
     #[must_use]
     pub fn new() -> Self {
-        Self { inner: Vec::new() }
+        Self {
+            inner: Vec::new(),
+            id: next_id(),
+        }
+    }
+
+    /// Identifies the container and its clones, unlike its address which may be reused.
+    ///
+    /// Together with an index, this identifies an opcode, e.g. for coverage.
+    #[must_use]
+    pub const fn id(&self) -> u64 {
+        self.id
     }
 
     /// Fetch the inner vector of opcodes.
@@ -38,12 +79,12 @@ impl Bytecode {
     }
 
     /// Returns an iterator over the opcodes.
-    pub fn iter(&self) -> std::slice::Iter<OpCode> {
+    pub fn iter(&self) -> std::slice::Iter<'_, OpCode> {
         self.inner.iter()
     }
 
     /// Returns a mutable iterator over the opcodes.
-    pub fn iter_mut(&mut self) -> std::slice::IterMut<OpCode> {
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, OpCode> {
         self.inner.iter_mut()
     }
 
@@ -56,6 +97,34 @@ impl Bytecode {
     pub fn push(&mut self, op: OpCode) {
         self.inner.push(op);
     }
+
+    /// Returns the total number of opcodes, including those nested in the bodies of
+    /// control flow and function definitions.
+    #[must_use]
+    pub fn instruction_count(&self) -> usize {
+        self.iter()
+            .map(|op| {
+                1 + op
+                    .bodies()
+                    .into_iter()
+                    .map(Self::instruction_count)
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Returns the deepest nesting of bodies within this bytecode.
+    ///
+    /// Bytecode with no nested bodies has a depth of 0, and each control flow statement
+    /// or function definition adds one level for the bodies it contains.
+    #[must_use]
+    pub fn max_depth(&self) -> usize {
+        self.iter()
+            .flat_map(OpCode::bodies)
+            .map(|body| 1 + body.max_depth())
+            .max()
+            .unwrap_or(0)
+    }
 }
 
 impl Default for Bytecode {
@@ -74,6 +143,50 @@ impl IntoIterator for Bytecode {
     }
 }
 
+/// The names of a function's locals which are stored in slots, indexed by their slot.
+///
+/// Dereferences to the slice of names. The slot of a name can also be found without
+/// scanning the names, since locals are still loaded and stored by name from nested scopes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "Vec<String>", into = "Vec<String>")]
+pub struct SlotNames {
+    names: Vec<String>,
+    slots: HashMap<String, usize>,
+}
+
+impl SlotNames {
+    /// Find the slot of a local variable, if it is stored in one.
+    #[must_use]
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+}
+
+impl From<Vec<String>> for SlotNames {
+    fn from(names: Vec<String>) -> Self {
+        let slots = names
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| (name.clone(), slot))
+            .collect();
+        Self { names, slots }
+    }
+}
+
+impl From<SlotNames> for Vec<String> {
+    fn from(names: SlotNames) -> Self {
+        names.names
+    }
+}
+
+impl Deref for SlotNames {
+    type Target = [String];
+
+    fn deref(&self) -> &Self::Target {
+        &self.names
+    }
+}
+
 /// Opcodes representing instructions which the executor can apply to a [`State`](crate::runtime::state::State).
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum OpCode {
@@ -86,14 +199,45 @@ pub enum OpCode {
     ///
     /// Stack: `[value] -> []`
     Store(String),
+    /// Load the value of a local variable from the given slot of the current scope.
+    ///
+    /// Slots are assigned to the locals of a function at compile time (see
+    /// [`OpCode::PushFunction`]). If the slot has not been assigned yet, the variable
+    /// is looked up by name in the parent scopes, as with [`OpCode::Load`].
+    ///
+    /// Stack: `[] -> [value]`
+    LoadLocal(usize),
+    /// Store a value in the given slot of the current scope.
+    ///
+    /// Stack: `[value] -> []`
+    StoreLocal(usize),
     /// Load a value from a table
     ///
+    /// A key which is absent (rather than present with a nil value) is passed to the
+    /// table's `__missing__` method, if it has one. See [`get_key`](crate::runtime::types::operations::get_key).
+    ///
+    /// The target is the source text of the indexed expression (e.g. `a.b` in `a.b.c`),
+    /// if it is a variable or field access. It is only used for error messages.
+    ///
     /// Stack: `[object] -> [value]`
-    GetKey(String),
+    GetKey { key: String, target: Option<String> },
     /// Store a value into a table.
     ///
     /// Stack: `[object, value] -> []`
     SetKey(String),
+    /// Load a value from a list or string at an index.
+    ///
+    /// Negative indices count from the end of the container.
+    ///
+    /// Stack: `[index, object] -> [value]`
+    GetIndex,
+    /// Store a value into a list at an index, or a table at a key.
+    ///
+    /// Negative indices count from the end of the list. Strings are immutable,
+    /// so storing into one is an error.
+    ///
+    /// Stack: `[value, index, object] -> []`
+    SetIndex,
 
     // ====================== Push Operations ======================
     /// Push a nil value onto the stack.
@@ -119,7 +263,32 @@ pub enum OpCode {
     /// Push a function with the given bytecode onto the stack.
     ///
     /// Stack: `[] -> [function]`
-    PushFunction(Bytecode),
+    PushFunction {
+        /// The bytecode of the function.
+        body: Bytecode,
+        /// The names of the locals stored by the function, indexed by their slot.
+        ///
+        /// These are shared with every function created by this opcode.
+        locals: Arc<SlotNames>,
+    },
+    /// Push a list containing the given number of values from the stack.
+    ///
+    /// Stack: `[value n-1, value n-2, ..., value 0] -> [list]`
+    PushList(usize),
+
+    // ====================== Stack Operations ======================
+    /// Push a copy of the value on top of the stack.
+    ///
+    /// Stack: `[value] -> [value, value]`
+    Dup,
+    /// Swap the top two values on the stack.
+    ///
+    /// Stack: `[a, b] -> [b, a]`
+    Swap,
+    /// Move the value on top of the stack below the two values beneath it.
+    ///
+    /// Stack: `[top, second, third] -> [second, third, top]`
+    Rotate,
 
     // ====================== Expressions  ======================
     /// Perform a binary operation on the top two values on the stack.
@@ -132,8 +301,11 @@ pub enum OpCode {
     UnaryOperation(UnaryOperationKind),
     /// Call a function with the given number of arguments.
     ///
+    /// The name is how the function was referred to at the call site, if it had one.
+    /// It is recorded on the new call frame so the call stack can be described.
+    ///
     /// Stack: `[arg n-1, arg n-2, ..., arg0, function] -> [result n-1, result n-2, ..., result0]`
-    Call(usize),
+    Call { args: usize, name: Option<Arc<str>> },
 
     // ====================== Control Flow ======================
     /// Break out of the current loop.
@@ -166,6 +338,8 @@ pub enum OpCode {
         increment: Option<Bytecode>,
         /// Body to execute.
         body: Bytecode,
+        /// Body to execute if the loop finishes without a `break`.
+        else_body: Option<Bytecode>,
     },
     /// While loop.
     While {
@@ -174,10 +348,156 @@ pub enum OpCode {
         condition: Bytecode,
         /// Body to execute.
         body: Bytecode,
+        /// Body to execute if the loop finishes without a `break`.
+        else_body: Option<Bytecode>,
     },
     /// Infinite-ish loop. This can still be exited through `break` and `return` statements.
     Loop {
         /// Body to execute.
         body: Bytecode,
     },
+    /// Loop which runs its body a fixed number of times. A count below one runs it no times.
+    Repeat {
+        /// Number of times to run the body. The bytecode is executed once, before the loop
+        /// starts, and the count is popped from the stack.
+        count: Bytecode,
+        /// Body to execute.
+        body: Bytecode,
+    },
+    /// Block run in a new call frame, which leaves a single value on the stack.
+    ///
+    /// Control flow (e.g. `return`) inside the block applies to the enclosing layer.
+    Do {
+        /// Body to execute. The value on top of the stack when it finishes is the block's value.
+        body: Bytecode,
+    },
+    /// With statement. The resource's `__close__` dunder method is called when
+    /// the body exits, whether normally, through control flow, or with an error.
+    With {
+        /// Resource to close. The bytecode is executed once, and the result is
+        /// popped from the stack.
+        resource: Bytecode,
+        /// Name of the local variable to store the resource in.
+        identifier: String,
+        /// Body to execute.
+        body: Bytecode,
+    },
+    /// Assert statement, which raises an error if its condition is false.
+    ///
+    /// Without a message, the error reports the source of the condition followed by the
+    /// values of the variables it uses, e.g. `x > 10 (x = 5)`.
+    Assert {
+        /// Condition to check. The bytecode is executed once, and the result is popped
+        /// from the stack.
+        condition: Bytecode,
+        /// Message to report. The bytecode is only executed if the condition is false.
+        message: Option<Bytecode>,
+        /// Source text of the condition.
+        source: String,
+        /// Names of the variables used by the condition, in source order.
+        variables: Vec<String>,
+    },
+}
+
+impl OpCode {
+    /// Returns the bytecode nested in this opcode (e.g. conditions and bodies), in execution order.
+    #[must_use]
+    pub fn bodies(&self) -> Vec<&Bytecode> {
+        match self {
+            Self::PushFunction { body, .. } | Self::Loop { body } | Self::Do { body } => {
+                vec![body]
+            }
+            Self::If {
+                condition,
+                body,
+                else_body,
+            }
+            | Self::While {
+                condition,
+                body,
+                else_body,
+            } => [Some(condition), Some(body), else_body.as_ref()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            Self::For {
+                initialization,
+                condition,
+                increment,
+                body,
+                else_body,
+            } => [
+                initialization.as_ref(),
+                condition.as_ref(),
+                Some(body),
+                increment.as_ref(),
+                else_body.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            Self::With { resource, body, .. }
+            | Self::Repeat {
+                count: resource,
+                body,
+            } => vec![resource, body],
+            Self::Assert {
+                condition, message, ..
+            } => [Some(condition), message.as_ref()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the bytecode nested in this opcode mutably, in the same order as [`OpCode::bodies`].
+    pub fn bodies_mut(&mut self) -> Vec<&mut Bytecode> {
+        match self {
+            Self::PushFunction { body, .. } | Self::Loop { body } | Self::Do { body } => {
+                vec![body]
+            }
+            Self::If {
+                condition,
+                body,
+                else_body,
+            }
+            | Self::While {
+                condition,
+                body,
+                else_body,
+            } => [Some(condition), Some(body), else_body.as_mut()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            Self::For {
+                initialization,
+                condition,
+                increment,
+                body,
+                else_body,
+            } => [
+                initialization.as_mut(),
+                condition.as_mut(),
+                Some(body),
+                increment.as_mut(),
+                else_body.as_mut(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            Self::With { resource, body, .. }
+            | Self::Repeat {
+                count: resource,
+                body,
+            } => vec![resource, body],
+            Self::Assert {
+                condition, message, ..
+            } => [Some(condition), message.as_mut()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            _ => vec![],
+        }
+    }
 }
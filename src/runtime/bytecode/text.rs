@@ -0,0 +1,572 @@
+//! A readable text format for [`Bytecode`], with one opcode per line.
+//!
+//! Each line holds an opcode's mnemonic followed by its operands, separated by spaces.
+//! Strings are double-quoted, with `\\`, `\"`, `\n`, `\r`, and `\t` escapes. Operators are
+//! written as they are in source code. Optional operands are left off when absent.
+//!
+//! Nested bytecode (such as the body of a loop) is written as a labelled section below its
+//! opcode, indented by two spaces, and the section's opcodes are indented by two more.
+//! An absent optional section is left off entirely, while an empty one has a label only.
+//!
+//! ```text
+//! push_int 0
+//! store "i"
+//! while
+//!   condition:
+//!     load "i"
+//!     push_int 3
+//!     binary <
+//!   body:
+//!     load "i"
+//!     load "print"
+//!     call 1 "print"
+//! ```
+//!
+//! Blank lines and lines starting with `//` are ignored when parsing.
+
+use std::sync::Arc;
+
+use super::{Bytecode, OpCode};
+use crate::compiler::{BinaryOperationKind, UnaryOperationKind};
+
+/// Number of spaces each level of nesting is indented by.
+const INDENT: usize = 2;
+
+/// An error raised while parsing the text format.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("line {line}: {message}")]
+pub struct TextError {
+    /// The line number the error occurred on, starting at 1.
+    pub line: usize,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl Bytecode {
+    /// Write the bytecode in the [text format](crate::runtime::bytecode::text).
+    #[must_use]
+    pub fn to_text(&self) -> String {
+        self.to_annotated_text(&|_, _| "")
+    }
+
+    /// Write the bytecode in the [text format](crate::runtime::bytecode::text), starting each
+    /// opcode's line with the given marker. Section labels are aligned with the opcodes.
+    pub(crate) fn to_annotated_text(
+        &self,
+        marker: &dyn Fn(&Self, usize) -> &'static str,
+    ) -> String {
+        let mut out = String::new();
+        write_bytecode(&mut out, self, 0, marker);
+        out
+    }
+
+    /// Parse bytecode from the [text format](crate::runtime::bytecode::text).
+    ///
+    /// # Errors
+    /// Returns a [`TextError`] if the text is not valid bytecode.
+    pub fn from_text(text: &str) -> Result<Self, TextError> {
+        let lines: Vec<_> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with("//"))
+            .map(|(i, line)| Line {
+                number: i + 1,
+                indent: line.len() - line.trim_start().len(),
+                content: line.trim(),
+            })
+            .collect();
+        let mut reader = Reader { lines, position: 0 };
+        let bytecode = reader.read_bytecode(0)?;
+        if let Some(line) = reader.peek() {
+            return Err(line.error("unexpected indentation"));
+        }
+        Ok(bytecode)
+    }
+}
+
+/// Write each opcode of the bytecode at the given indentation, followed by its sections.
+fn write_bytecode(
+    out: &mut String,
+    bytecode: &Bytecode,
+    indent: usize,
+    marker: &dyn Fn(&Bytecode, usize) -> &'static str,
+) {
+    for (index, opcode) in bytecode.iter().enumerate() {
+        let prefix = marker(bytecode, index);
+        out.push_str(prefix);
+        out.push_str(&" ".repeat(indent));
+        out.push_str(&describe(opcode));
+        out.push('\n');
+        for (label, body) in sections(opcode) {
+            out.push_str(&" ".repeat(prefix.len() + indent + INDENT));
+            out.push_str(label);
+            out.push_str(":\n");
+            write_bytecode(out, body, indent + 2 * INDENT, marker);
+        }
+    }
+}
+
+/// Describe an opcode's mnemonic and operands, without its sections.
+fn describe(opcode: &OpCode) -> String {
+    match opcode {
+        OpCode::Load(name) => format!("load {}", quote(name)),
+        OpCode::Store(name) => format!("store {}", quote(name)),
+        OpCode::LoadLocal(slot) => format!("load_local {slot}"),
+        OpCode::StoreLocal(slot) => format!("store_local {slot}"),
+        OpCode::GetKey { key, target } => {
+            format!(
+                "get_key {}{}",
+                quote(key),
+                quote_optional(target.as_deref())
+            )
+        }
+        OpCode::SetKey(key) => format!("set_key {}", quote(key)),
+        OpCode::GetIndex => "get_index".to_string(),
+        OpCode::SetIndex => "set_index".to_string(),
+        OpCode::PushNil => "push_nil".to_string(),
+        OpCode::PushString(x) => format!("push_string {}", quote(x)),
+        OpCode::PushInteger(x) => format!("push_int {x}"),
+        OpCode::PushFloat(x) => format!("push_float {x:?}"),
+        OpCode::PushBool(x) => format!("push_bool {x}"),
+        OpCode::PushFunction { locals, .. } => locals
+            .iter()
+            .fold("push_function".to_string(), |line, name| {
+                line + " " + &quote(name)
+            }),
+        OpCode::PushList(n) => format!("push_list {n}"),
+        OpCode::Dup => "dup".to_string(),
+        OpCode::Swap => "swap".to_string(),
+        OpCode::Rotate => "rotate".to_string(),
+        OpCode::BinaryOperation(kind) => format!("binary {}", kind.symbol()),
+        OpCode::UnaryOperation(kind) => format!("unary {}", kind.symbol()),
+        OpCode::Call { args, name } => format!("call {args}{}", quote_optional(name.as_deref())),
+        OpCode::Break => "break".to_string(),
+        OpCode::Continue => "continue".to_string(),
+        OpCode::Return(n) => format!("return {n}"),
+        OpCode::If { .. } => "if".to_string(),
+        OpCode::For { .. } => "for".to_string(),
+        OpCode::While { .. } => "while".to_string(),
+        OpCode::Loop { .. } => "loop".to_string(),
+        OpCode::Do { .. } => "do".to_string(),
+        OpCode::Repeat { .. } => "repeat".to_string(),
+        OpCode::With { identifier, .. } => format!("with {}", quote(identifier)),
+        OpCode::Assert {
+            source, variables, ..
+        } => variables
+            .iter()
+            .fold(format!("assert {}", quote(source)), |line, name| {
+                line + " " + &quote(name)
+            }),
+    }
+}
+
+/// Returns the labelled sections of an opcode, in execution order.
+fn sections(opcode: &OpCode) -> Vec<(&'static str, &Bytecode)> {
+    let sections = match opcode {
+        OpCode::PushFunction { body, .. } | OpCode::Loop { body } | OpCode::Do { body } => {
+            vec![("body", Some(body))]
+        }
+        OpCode::If {
+            condition,
+            body,
+            else_body,
+        }
+        | OpCode::While {
+            condition,
+            body,
+            else_body,
+        } => vec![
+            ("condition", Some(condition)),
+            ("body", Some(body)),
+            ("else", else_body.as_ref()),
+        ],
+        OpCode::For {
+            initialization,
+            condition,
+            increment,
+            body,
+            else_body,
+        } => vec![
+            ("initialization", initialization.as_ref()),
+            ("condition", condition.as_ref()),
+            ("body", Some(body)),
+            ("increment", increment.as_ref()),
+            ("else", else_body.as_ref()),
+        ],
+        OpCode::Repeat { count, body } => vec![("count", Some(count)), ("body", Some(body))],
+        OpCode::With { resource, body, .. } => {
+            vec![("resource", Some(resource)), ("body", Some(body))]
+        }
+        OpCode::Assert {
+            condition, message, ..
+        } => vec![
+            ("condition", Some(condition)),
+            ("message", message.as_ref()),
+        ],
+        _ => vec![],
+    };
+    sections
+        .into_iter()
+        .filter_map(|(label, body)| Some((label, body?)))
+        .collect()
+}
+
+/// Quote a string, escaping characters which would break the line.
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quote an optional trailing operand, preceded by a space, or nothing if it is absent.
+fn quote_optional(s: Option<&str>) -> String {
+    s.map(|s| format!(" {}", quote(s))).unwrap_or_default()
+}
+
+/// A non-empty line of text.
+struct Line<'a> {
+    /// The line number, starting at 1.
+    number: usize,
+    /// The number of leading spaces.
+    indent: usize,
+    /// The line without surrounding whitespace.
+    content: &'a str,
+}
+
+impl Line<'_> {
+    /// Create an error on this line.
+    fn error(&self, message: impl Into<String>) -> TextError {
+        TextError {
+            line: self.number,
+            message: message.into(),
+        }
+    }
+}
+
+/// An operand of an opcode.
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// An unquoted word, such as a number or an operator.
+    Word(String),
+    /// A quoted string, with escapes resolved.
+    String(String),
+}
+
+/// Reads opcodes from lines of text.
+struct Reader<'a> {
+    lines: Vec<Line<'a>>,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Returns the next line, if any, without consuming it.
+    fn peek(&self) -> Option<&Line<'a>> {
+        self.lines.get(self.position)
+    }
+
+    /// Read opcodes at the given indentation, until a line with less indentation.
+    fn read_bytecode(&mut self, indent: usize) -> Result<Bytecode, TextError> {
+        let mut bytecode = Bytecode::new();
+        while let Some(line) = self.peek() {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(line.error("unexpected indentation"));
+            }
+            let number = line.number;
+            let (mnemonic, operands) = tokenize(line)?;
+            self.position += 1;
+            let sections = self.read_sections(indent + INDENT)?;
+            let opcode = build(mnemonic, operands, sections).map_err(|message| TextError {
+                line: number,
+                message,
+            })?;
+            bytecode.push(opcode);
+        }
+        Ok(bytecode)
+    }
+
+    /// Read the labelled sections at the given indentation following an opcode.
+    fn read_sections(&mut self, indent: usize) -> Result<Sections, TextError> {
+        let mut sections = Sections::default();
+        while let Some(line) = self.peek() {
+            if line.indent != indent {
+                break;
+            }
+            let Some(label) = line.content.strip_suffix(':') else {
+                return Err(line.error("expected a section label"));
+            };
+            if sections.0.iter().any(|(existing, _)| existing == label) {
+                return Err(line.error(format!("duplicate section '{label}'")));
+            }
+            let label = label.to_string();
+            self.position += 1;
+            let body = self.read_bytecode(indent + INDENT)?;
+            sections.0.push((label, body));
+        }
+        Ok(sections)
+    }
+}
+
+/// Split a line into its mnemonic and operands.
+fn tokenize<'a>(line: &Line<'a>) -> Result<(&'a str, Vec<Token>), TextError> {
+    let content = line.content;
+    let (mnemonic, mut rest) = content.split_once(' ').unwrap_or((content, ""));
+    let mut tokens = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let (s, remainder) =
+                unquote(quoted).ok_or_else(|| line.error("unterminated string"))?;
+            tokens.push(Token::String(s));
+            rest = remainder;
+        } else {
+            let (word, remainder) = rest.split_once(' ').unwrap_or((rest, ""));
+            tokens.push(Token::Word(word.to_string()));
+            rest = remainder;
+        }
+    }
+    Ok((mnemonic, tokens))
+}
+
+/// Resolve the escapes of a string following its opening quote.
+///
+/// Returns the string and the text after its closing quote, or `None` if it isn't closed.
+fn unquote(s: &str) -> Option<(String, &str)> {
+    let mut out = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                c => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// The labelled sections following an opcode, in the order they were written.
+#[derive(Default)]
+struct Sections(Vec<(String, Bytecode)>);
+
+impl Sections {
+    /// Take a section which may be absent.
+    fn optional(&mut self, label: &str) -> Option<Bytecode> {
+        let index = self.0.iter().position(|(existing, _)| existing == label)?;
+        Some(self.0.remove(index).1)
+    }
+
+    /// Take a section which must be present.
+    fn required(&mut self, label: &str) -> Result<Bytecode, String> {
+        self.optional(label)
+            .ok_or_else(|| format!("missing section '{label}'"))
+    }
+
+    /// Check that every section was taken.
+    fn finish(self) -> Result<(), String> {
+        match self.0.first() {
+            Some((label, _)) => Err(format!("unexpected section '{label}'")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Build an opcode from its mnemonic, operands, and sections.
+fn build(mnemonic: &str, operands: Vec<Token>, mut sections: Sections) -> Result<OpCode, String> {
+    let mut operands = Operands(operands.into_iter());
+    let opcode = match mnemonic {
+        "load" => OpCode::Load(operands.string()?),
+        "store" => OpCode::Store(operands.string()?),
+        "load_local" => OpCode::LoadLocal(operands.parse("slot")?),
+        "store_local" => OpCode::StoreLocal(operands.parse("slot")?),
+        "get_key" => OpCode::GetKey {
+            key: operands.string()?,
+            target: operands.optional_string()?,
+        },
+        "set_key" => OpCode::SetKey(operands.string()?),
+        "get_index" => OpCode::GetIndex,
+        "set_index" => OpCode::SetIndex,
+        "push_nil" => OpCode::PushNil,
+        "push_string" => OpCode::PushString(operands.string()?),
+        "push_int" => OpCode::PushInteger(operands.parse("integer")?),
+        "push_float" => OpCode::PushFloat(operands.parse("float")?),
+        "push_bool" => OpCode::PushBool(operands.parse("bool")?),
+        "push_function" => OpCode::PushFunction {
+            locals: Arc::new(operands.strings()?.into()),
+            body: sections.required("body")?,
+        },
+        "push_list" => OpCode::PushList(operands.parse("count")?),
+        "dup" => OpCode::Dup,
+        "swap" => OpCode::Swap,
+        "rotate" => OpCode::Rotate,
+        "binary" => {
+            let symbol = operands.word("operator")?;
+            OpCode::BinaryOperation(
+                binary_kind(&symbol).ok_or_else(|| format!("unknown operator '{symbol}'"))?,
+            )
+        }
+        "unary" => {
+            let symbol = operands.word("operator")?;
+            OpCode::UnaryOperation(
+                unary_kind(&symbol).ok_or_else(|| format!("unknown operator '{symbol}'"))?,
+            )
+        }
+        "call" => OpCode::Call {
+            args: operands.parse("count")?,
+            name: operands.optional_string()?.map(Arc::from),
+        },
+        "break" => OpCode::Break,
+        "continue" => OpCode::Continue,
+        "return" => OpCode::Return(operands.parse("count")?),
+        "if" => OpCode::If {
+            condition: sections.required("condition")?,
+            body: sections.required("body")?,
+            else_body: sections.optional("else"),
+        },
+        "for" => OpCode::For {
+            initialization: sections.optional("initialization"),
+            condition: sections.optional("condition"),
+            increment: sections.optional("increment"),
+            body: sections.required("body")?,
+            else_body: sections.optional("else"),
+        },
+        "while" => OpCode::While {
+            condition: sections.required("condition")?,
+            body: sections.required("body")?,
+            else_body: sections.optional("else"),
+        },
+        "loop" => OpCode::Loop {
+            body: sections.required("body")?,
+        },
+        "do" => OpCode::Do {
+            body: sections.required("body")?,
+        },
+        "repeat" => OpCode::Repeat {
+            count: sections.required("count")?,
+            body: sections.required("body")?,
+        },
+        "with" => OpCode::With {
+            identifier: operands.string()?,
+            resource: sections.required("resource")?,
+            body: sections.required("body")?,
+        },
+        "assert" => OpCode::Assert {
+            source: operands.string()?,
+            variables: operands.strings()?,
+            condition: sections.required("condition")?,
+            message: sections.optional("message"),
+        },
+        _ => return Err(format!("unknown opcode '{mnemonic}'")),
+    };
+    operands.finish()?;
+    sections.finish()?;
+    Ok(opcode)
+}
+
+/// The remaining operands of an opcode.
+struct Operands(std::vec::IntoIter<Token>);
+
+impl Operands {
+    /// Take a quoted string operand.
+    fn string(&mut self) -> Result<String, String> {
+        self.optional_string()?
+            .ok_or_else(|| "expected a string".to_string())
+    }
+
+    /// Take a quoted string operand, if there are any operands left.
+    fn optional_string(&mut self) -> Result<Option<String>, String> {
+        match self.0.next() {
+            Some(Token::String(s)) => Ok(Some(s)),
+            Some(Token::Word(word)) => Err(format!("expected a string, found '{word}'")),
+            None => Ok(None),
+        }
+    }
+
+    /// Take every remaining operand, which must all be quoted strings.
+    fn strings(&mut self) -> Result<Vec<String>, String> {
+        std::iter::from_fn(|| self.optional_string().transpose()).collect()
+    }
+
+    /// Take an unquoted operand.
+    fn word(&mut self, expected: &str) -> Result<String, String> {
+        match self.0.next() {
+            Some(Token::Word(word)) => Ok(word),
+            _ => Err(format!("expected {expected}")),
+        }
+    }
+
+    /// Take an unquoted operand and parse it.
+    fn parse<T: std::str::FromStr>(&mut self, expected: &str) -> Result<T, String> {
+        let word = self.word(expected)?;
+        word.parse()
+            .map_err(|_| format!("expected {expected}, found '{word}'"))
+    }
+
+    /// Check that every operand was taken.
+    fn finish(mut self) -> Result<(), String> {
+        match self.0.next() {
+            Some(_) => Err("too many operands".to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Find the binary operation written with the given symbol.
+fn binary_kind(symbol: &str) -> Option<BinaryOperationKind> {
+    use BinaryOperationKind::{
+        Add, And, Divide, Equal, FloorDivide, GreaterThan, GreaterThanOrEqual, LessThan,
+        LessThanOrEqual, Modulo, Multiply, NotEqual, Or, Power, Remainder, Subtract, Xor,
+    };
+    [
+        Add,
+        Subtract,
+        Multiply,
+        Divide,
+        FloorDivide,
+        Remainder,
+        Modulo,
+        Power,
+        And,
+        Or,
+        Xor,
+        Equal,
+        NotEqual,
+        GreaterThan,
+        GreaterThanOrEqual,
+        LessThan,
+        LessThanOrEqual,
+    ]
+    .into_iter()
+    .find(|kind| kind.symbol() == symbol)
+}
+
+/// Find the unary operation written with the given symbol.
+fn unary_kind(symbol: &str) -> Option<UnaryOperationKind> {
+    [
+        UnaryOperationKind::Plus,
+        UnaryOperationKind::Negate,
+        UnaryOperationKind::Not,
+    ]
+    .into_iter()
+    .find(|kind| kind.symbol() == symbol)
+}
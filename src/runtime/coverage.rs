@@ -0,0 +1,97 @@
+//! Module containing the opcode coverage recorder.
+//!
+//! Once [enabled](super::state::State::enable_coverage), a state records every opcode it
+//! executes. A [`CoverageReport`] for some bytecode then shows which of its opcodes were
+//! executed, which helps find branches a script's tests never take.
+//!
+//! Opcodes are told apart by the [id](Bytecode::id) of their bytecode and their index
+//! in it. Clones of bytecode share its id, so the report may be made for the bytecode
+//! that was run or any clone of it, such as the body of a function.
+
+use std::{collections::HashSet, fmt::Display};
+
+use super::bytecode::Bytecode;
+
+/// The opcodes executed since coverage was enabled.
+#[derive(Default)]
+pub(crate) struct Coverage {
+    /// The ids of the bytecode of the executed opcodes, and their indices in it.
+    executed: HashSet<(u64, usize)>,
+}
+
+impl Coverage {
+    /// Record the opcode at an index of some bytecode as executed.
+    pub(crate) fn record(&mut self, bytecode: &Bytecode, index: usize) {
+        self.executed.insert((bytecode.id(), index));
+    }
+
+    /// Whether the opcode at an index of some bytecode was executed.
+    fn is_executed(&self, bytecode: &Bytecode, index: usize) -> bool {
+        self.executed.contains(&(bytecode.id(), index))
+    }
+
+    /// Make a report of which opcodes of some bytecode were executed.
+    pub(crate) fn report(&self, bytecode: &Bytecode) -> CoverageReport {
+        let (covered, total) = self.count(bytecode);
+        let text = bytecode.to_annotated_text(&|bytecode, index| {
+            if self.is_executed(bytecode, index) {
+                "+ "
+            } else {
+                "- "
+            }
+        });
+        CoverageReport {
+            covered,
+            total,
+            text,
+        }
+    }
+
+    /// Count the executed opcodes and all opcodes in some bytecode, including nested ones.
+    fn count(&self, bytecode: &Bytecode) -> (usize, usize) {
+        bytecode
+            .iter()
+            .enumerate()
+            .fold((0, 0), |(covered, total), (index, opcode)| {
+                let executed = usize::from(self.is_executed(bytecode, index));
+                opcode
+                    .bodies()
+                    .into_iter()
+                    .map(|body| self.count(body))
+                    .fold((covered + executed, total + 1), |(a, b), (c, d)| {
+                        (a + c, b + d)
+                    })
+            })
+    }
+}
+
+/// Which opcodes of some bytecode were executed.
+///
+/// The report is displayed as the bytecode in the [text format](super::bytecode::text),
+/// with each opcode's line starting with `+` if it was executed, or `-` if not.
+#[derive(Debug, Clone)]
+pub struct CoverageReport {
+    covered: usize,
+    total: usize,
+    text: String,
+}
+
+impl CoverageReport {
+    /// The number of opcodes which were executed, including nested ones.
+    #[must_use]
+    pub const fn covered(&self) -> usize {
+        self.covered
+    }
+
+    /// The total number of opcodes, including nested ones.
+    #[must_use]
+    pub const fn total(&self) -> usize {
+        self.total
+    }
+}
+
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
@@ -0,0 +1,91 @@
+//! Module containing the [`RuntimeError`] type, which represents an error raised
+//! while executing bytecode.
+
+/// An error raised while executing bytecode on a [`State`](crate::runtime::state::State).
+///
+/// Runtime errors propagate out of every execution layer, up to whoever
+/// started the execution.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RuntimeError {
+    /// An operation was applied to a value of an unsupported type.
+    #[error("type error: {0}")]
+    TypeError(String),
+    /// An argument had a supported type, but an invalid value.
+    #[error("value error: {0}")]
+    ValueError(String),
+    /// Source code could not be parsed or compiled.
+    #[error("syntax error: {0}")]
+    SyntaxError(String),
+    /// A key was assigned or deleted on a frozen table.
+    #[error("cannot modify key '{0}' of a frozen table")]
+    FrozenTable(String),
+    /// An index was outside the bounds of the indexed list or string.
+    #[error("index {index} out of range for length {len}")]
+    IndexOutOfRange {
+        /// The index, as given by the script.
+        index: i64,
+        /// The length of the indexed container.
+        len: usize,
+    },
+    /// A function was called with a number of arguments it doesn't accept.
+    #[error("expected {expected}, got {got}")]
+    ArgumentCount {
+        /// The accepted numbers of arguments, e.g. `"1 to 3 arguments"`.
+        expected: String,
+        /// The number of arguments given.
+        got: usize,
+    },
+    /// More values were requested from the operand stack than it holds.
+    #[error("stack underflow: {requested} values requested, but only {available} available")]
+    StackUnderflow {
+        /// The number of values requested.
+        requested: usize,
+        /// The number of values on the operand stack.
+        available: usize,
+    },
+    /// A local variable slot was used which the current call frame doesn't have.
+    #[error("invalid local slot {slot}, the frame has {slots} slots")]
+    InvalidSlot {
+        /// The slot, as given by the bytecode.
+        slot: usize,
+        /// The number of slots of the current call frame.
+        slots: usize,
+    },
+    /// More instructions were executed than allowed by the state's instruction limit.
+    #[error("instruction limit of {0} exceeded")]
+    InstructionLimit(u64),
+    /// Calls were nested deeper than allowed by the state's call depth limit.
+    #[error("call depth limit of {0} exceeded")]
+    CallDepthLimit(usize),
+    /// An `assert` failed.
+    #[error("assertion failed: {0}")]
+    AssertionFailed(String),
+    /// Execution was stopped by the state's step hook.
+    #[error("execution aborted by step hook")]
+    Aborted,
+    /// Reading from or writing to a file or stream failed.
+    #[error("io error: {0}")]
+    IoError(String),
+    /// An error was raised while running another script file.
+    #[error("in file '{path}': {error}")]
+    InFile {
+        /// The path of the script file, as given by the script.
+        path: String,
+        /// The error raised by the script file.
+        error: Box<Self>,
+    },
+}
+
+impl RuntimeError {
+    /// Creates a [`RuntimeError::TypeError`] with the given message.
+    #[must_use]
+    pub fn type_error(message: impl Into<String>) -> Self {
+        Self::TypeError(message.into())
+    }
+
+    /// Creates a [`RuntimeError::ValueError`] with the given message.
+    #[must_use]
+    pub fn value_error(message: impl Into<String>) -> Self {
+        Self::ValueError(message.into())
+    }
+}
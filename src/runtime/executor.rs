@@ -24,13 +24,19 @@ use self::{
 };
 use super::{
     bytecode::{Bytecode, OpCode},
+    error::RuntimeError,
     state::State,
-    types::utilities::{boolean, float, int, nil, scripted_function, string},
+    types::{
+        object::Object,
+        operations,
+        utilities::{boolean, float, int, list, nil, scripted_function, string},
+    },
 };
 use crate::{
     compiler::compile,
     runtime::executor::control_flow::{
-        execute_for_loop, execute_if_statement, execute_infinite_loop, execute_while_loop,
+        execute_assert_statement, execute_do_block, execute_for_loop, execute_if_statement,
+        execute_infinite_loop, execute_repeat_loop, execute_while_loop, execute_with_statement,
         function_layer_control_flow,
     },
 };
@@ -42,45 +48,111 @@ const STACK_DEBUG: bool = false;
 
 /// Parse, compile, and run the input string on the given state.
 ///
-/// Returns the number of objects pushed onto the stack.
+/// Returns the value left on top of the stack by the input, if any.
+/// See [`State::run`] for more information.
 ///
 /// # Errors
-/// `anyhow::Error` if there is a problem parsing or compiling the input.
-pub fn execute_source(state: &mut State, input: &str) -> Result<usize, anyhow::Error> {
+/// `anyhow::Error` if there is a problem parsing or compiling the input,
+/// or a [`RuntimeError`] occurs while running it.
+pub fn execute_source(state: &mut State, input: &str) -> Result<Option<Object>, anyhow::Error> {
     let bytecode = compile(input)?;
-    let pushed_amt = execute(state, &bytecode);
-    Ok(pushed_amt)
+    Ok(state.run(&bytecode)?)
 }
 
 /// Execute the given bytecode on the given state.
 ///
 /// Returns the number of objects pushed onto the stack.
-pub(crate) fn execute(state: &mut State, bytecode: &Bytecode) -> usize {
-    match run_execution_layer(state, bytecode) {
-        ControlFlow::Return(n) => n,
-        _ => 0,
+///
+/// If an error occurs, the operand stack is truncated back to its initial size, so that
+/// the operands of a partially executed statement are not left behind.
+///
+/// # Errors
+/// Returns a [`RuntimeError`] if one occurs during execution, or if a `Return`
+/// returns more objects than are on the stack.
+pub(crate) fn execute(state: &mut State, bytecode: &Bytecode) -> Result<usize, RuntimeError> {
+    let initial_size = state.operand_stack_size();
+    let flow = match run_execution_layer(state, bytecode) {
+        Ok(flow) => flow,
+        Err(e) => {
+            state.truncate_operands(initial_size);
+            return Err(e);
+        }
+    };
+    match flow {
+        ControlFlow::Return(n) if n > state.operand_stack_size() => {
+            let available = state.operand_stack_size();
+            state.truncate_operands(initial_size);
+            Err(RuntimeError::StackUnderflow {
+                requested: n,
+                available,
+            })
+        }
+        ControlFlow::Return(n) => Ok(n),
+        _ => Ok(0),
     }
 }
 
+/// Call a function object with the given arguments.
+///
+/// Returns the number of objects the function pushed onto the stack.
+///
+/// Stack: `[] -> [return n-1, return n-2, ... return 0]`
+///
+/// # Errors
+/// Returns a [`RuntimeError`] if the object is not callable, or if the call fails.
+pub fn call_function(
+    state: &mut State,
+    function: &Object,
+    args: &[Object],
+) -> Result<usize, RuntimeError> {
+    let _heap = state.enter_heap();
+    state.push_all(args);
+    state.push(function);
+    execute_function_call(state, args.len(), None)
+}
+
 /// Run the given bytecode on the given state.
 ///
 /// This serves as running a new execution layer.
 /// See the [`module`](self) documentation for more information.
 ///
 /// Stack: `[*] -> [*]`
-fn run_execution_layer(state: &mut State, bytecode: &Bytecode) -> ControlFlow {
-    for opcode in bytecode.iter() {
+fn run_execution_layer(
+    state: &mut State,
+    bytecode: &Bytecode,
+) -> Result<ControlFlow, RuntimeError> {
+    // Entering a layer counts as an instruction, so that loops with empty bodies are still limited.
+    state.count_instruction()?;
+    for (index, opcode) in bytecode.iter().enumerate() {
         if STACK_DEBUG {
             println!("=================================");
             println!("stack: {:?}", state.operand_stack_size());
             println!("executing opcode: {opcode:?}");
         }
 
+        state.count_instruction()?;
+        state.step(bytecode, index)?;
         // This may exit the current execution layer early.
-        function_layer_control_flow!(execute_operation(state, opcode));
+        function_layer_control_flow!(execute_operation(state, opcode)?);
     }
 
-    ControlFlow::None
+    Ok(ControlFlow::None)
+}
+
+/// Make sure the current call frame's operand stack holds at least `n` objects,
+/// so malformed bytecode results in an error rather than a panic.
+///
+/// # Errors
+/// Returns a [`RuntimeError::StackUnderflow`] if the stack holds fewer than `n` objects.
+fn require_operands(state: &State, n: usize) -> Result<(), RuntimeError> {
+    let available = state.operand_stack_size();
+    if available < n {
+        return Err(RuntimeError::StackUnderflow {
+            requested: n,
+            available,
+        });
+    }
+    Ok(())
 }
 
 /// Execute a single operation on the given state.
@@ -89,20 +161,45 @@ fn run_execution_layer(state: &mut State, bytecode: &Bytecode) -> ControlFlow {
 /// needs to exit early.
 ///
 /// Stack: `[*] -> [*]`
-fn execute_operation(state: &mut State, opcode: &OpCode) -> ControlFlow {
+fn execute_operation(state: &mut State, opcode: &OpCode) -> Result<ControlFlow, RuntimeError> {
     match opcode {
         // ======================== Stack Operations ========================
-        OpCode::Store(identifier) => state.store_local(identifier),
+        OpCode::Store(identifier) => {
+            require_operands(state, 1)?;
+            state.store_local(identifier);
+        }
         OpCode::Load(identifier) => state.load(identifier),
+        OpCode::StoreLocal(slot) => {
+            require_operands(state, 1)?;
+            state.store_slot(*slot)?;
+        }
+        OpCode::LoadLocal(slot) => state.load_slot(*slot)?,
         OpCode::SetKey(key) => {
-            let value = state.pop().unwrap();
-            let mut table_obj = state.pop().unwrap();
-            table_obj.set_key(key, value);
+            let value = state.pop_or_err()?;
+            let mut table_obj = state.pop_or_err()?;
+            table_obj.set_key(key, value)?;
+        }
+        OpCode::GetKey { key, target } => {
+            let table = state.pop_or_err()?;
+            let type_name = table.type_name();
+            if type_name != "table" {
+                return Err(RuntimeError::type_error(target.as_ref().map_or_else(
+                    || format!("attempt to index {type_name} value"),
+                    |target| format!("attempt to index {type_name} value '{target}'"),
+                )));
+            }
+            operations::get_key(state, &table, &string(key))?;
+        }
+        OpCode::SetIndex => {
+            let value = state.pop_or_err()?;
+            let index = state.pop_or_err()?;
+            let target = state.pop_or_err()?;
+            operations::set_index(&target, &index, value)?;
         }
-        OpCode::GetKey(key) => {
-            let table = state.pop().unwrap();
-            let value = table.get_key(key).unwrap_or_else(nil);
-            state.push(&value);
+        OpCode::GetIndex => {
+            let index = state.pop_or_err()?;
+            let target = state.pop_or_err()?;
+            operations::get_index(state, &target, &index)?;
         }
 
         // ======================== Push Operations ========================
@@ -110,44 +207,85 @@ fn execute_operation(state: &mut State, opcode: &OpCode) -> ControlFlow {
         OpCode::PushFloat(x) => state.push(&float(*x)),
         OpCode::PushString(x) => state.push(&string(x)),
         OpCode::PushBool(x) => state.push(&boolean(*x)),
-        OpCode::PushFunction(x) => state.push(&scripted_function(x.clone())),
+        OpCode::PushFunction { body, locals } => {
+            state.push(&scripted_function(body.clone(), locals.clone()));
+        }
         OpCode::PushNil => state.push(&nil()),
+        OpCode::PushList(n) => {
+            let mut elements = state.try_pop_n(*n)?;
+            elements.reverse();
+            state.push(&list(elements));
+        }
+
+        // ======================== Stack Operations ========================
+        OpCode::Dup => {
+            let value = state.pop_or_err()?;
+            state.push_all(&[value.clone(), value]);
+        }
+        OpCode::Swap => {
+            let a = state.pop_or_err()?;
+            let b = state.pop_or_err()?;
+            state.push_all(&[a, b]);
+        }
+        OpCode::Rotate => {
+            let a = state.pop_or_err()?;
+            let b = state.pop_or_err()?;
+            let c = state.pop_or_err()?;
+            state.push_all(&[a, c, b]);
+        }
 
         // ======================== Expressions ========================
-        OpCode::BinaryOperation(op) => execute_binary_operation(state, *op),
-        OpCode::UnaryOperation(op) => execute_unary_operation(state, *op),
-        OpCode::Call(n) => execute_function_call(state, *n),
+        OpCode::BinaryOperation(op) => execute_binary_operation(state, *op)?,
+        OpCode::UnaryOperation(op) => execute_unary_operation(state, *op)?,
+        OpCode::Call { args, name } => {
+            execute_function_call(state, *args, name.as_ref())?;
+        }
 
         // ======================== Control Flow ========================
-        OpCode::Return(n) => return ControlFlow::Return(*n),
-        OpCode::Break => return ControlFlow::Break,
-        OpCode::Continue => return ControlFlow::Continue,
+        OpCode::Return(n) => return Ok(ControlFlow::Return(*n)),
+        OpCode::Break => return Ok(ControlFlow::Break),
+        OpCode::Continue => return Ok(ControlFlow::Continue),
         opcode @ OpCode::If { .. } => {
-            function_layer_control_flow!(execute_if_statement(state, opcode));
+            function_layer_control_flow!(execute_if_statement(state, opcode)?);
         }
         opcode @ OpCode::For { .. } => {
-            function_layer_control_flow!(execute_for_loop(state, opcode));
+            function_layer_control_flow!(execute_for_loop(state, opcode)?);
         }
         opcode @ OpCode::While { .. } => {
-            function_layer_control_flow!(execute_while_loop(state, opcode));
+            function_layer_control_flow!(execute_while_loop(state, opcode)?);
         }
         opcode @ OpCode::Loop { .. } => {
-            function_layer_control_flow!(execute_infinite_loop(state, opcode));
+            function_layer_control_flow!(execute_infinite_loop(state, opcode)?);
+        }
+        opcode @ OpCode::Repeat { .. } => {
+            function_layer_control_flow!(execute_repeat_loop(state, opcode)?);
+        }
+        opcode @ OpCode::With { .. } => {
+            function_layer_control_flow!(execute_with_statement(state, opcode)?);
+        }
+        opcode @ OpCode::Do { .. } => {
+            function_layer_control_flow!(execute_do_block(state, opcode)?);
         }
+        opcode @ OpCode::Assert { .. } => execute_assert_statement(state, opcode)?,
     };
-    ControlFlow::None
+    Ok(ControlFlow::None)
 }
 
 /// Executors for more complex expression operations.
 pub(self) mod expressions {
-    use std::borrow::Borrow;
+    use std::{borrow::Borrow, sync::Arc};
 
     use crate::{
         compiler::{BinaryOperationKind, UnaryOperationKind},
         runtime::{
+            error::RuntimeError,
             executor::execute,
             state::State,
-            types::{function::Function, object::ObjectValue, operations},
+            types::{
+                function::Function,
+                object::{Object, ObjectValue},
+                operations,
+            },
         },
     };
 
@@ -155,15 +293,23 @@ pub(self) mod expressions {
     /// is indicated by the [`BinaryOperationKind`].
     ///
     /// Stack: `[rhs, lhs] -> result`
-    pub fn execute_binary_operation(state: &mut State, kind: BinaryOperationKind) {
-        let right = state.pop().unwrap();
-        let left = state.pop().unwrap();
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operation is unsupported for the operands.
+    pub fn execute_binary_operation(
+        state: &mut State,
+        kind: BinaryOperationKind,
+    ) -> Result<(), RuntimeError> {
+        let right = state.pop_or_err()?;
+        let left = state.pop_or_err()?;
         match kind {
             BinaryOperationKind::Add => operations::add(state, &left, &right),
             BinaryOperationKind::Subtract => operations::subtract(state, &left, &right),
             BinaryOperationKind::Multiply => operations::multiply(state, &left, &right),
             BinaryOperationKind::Divide => operations::divide(state, &left, &right),
+            BinaryOperationKind::FloorDivide => operations::floor_divide(state, &left, &right),
             BinaryOperationKind::Remainder => operations::remainder(state, &left, &right),
+            BinaryOperationKind::Modulo => operations::modulo(state, &left, &right),
             BinaryOperationKind::Equal => operations::equals(state, &left, &right),
             BinaryOperationKind::NotEqual => operations::not_equals(state, &left, &right),
             BinaryOperationKind::GreaterThan => operations::greater_than(state, &left, &right),
@@ -176,64 +322,114 @@ pub(self) mod expressions {
             }
             BinaryOperationKind::And => operations::and(state, &left, &right),
             BinaryOperationKind::Or => operations::or(state, &left, &right),
-            _ => unimplemented!("binary operation is unimplemented: {:?}", kind),
-        };
+            BinaryOperationKind::Xor => operations::xor(state, &left, &right),
+            BinaryOperationKind::Power => Err(RuntimeError::type_error(format!(
+                "unsupported binary operation: {}",
+                kind.symbol()
+            ))),
+        }
     }
 
     /// Execute a unary operation on the given state. The type of operation
     /// is indicated by the [`UnaryOperationKind`].
     ///
     /// Stack: `operand -> result`
-    pub fn execute_unary_operation(state: &mut State, kind: UnaryOperationKind) {
-        let operand = state.pop().unwrap();
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operation is unsupported for the operand.
+    pub fn execute_unary_operation(
+        state: &mut State,
+        kind: UnaryOperationKind,
+    ) -> Result<(), RuntimeError> {
+        let operand = state.pop_or_err()?;
         match kind {
+            UnaryOperationKind::Plus => operations::plus(state, &operand),
             UnaryOperationKind::Negate => operations::negate(state, &operand),
-            _ => unimplemented!("unary operation is unimplemented: {:?}", kind),
-        };
+            UnaryOperationKind::Not => operations::not(state, &operand),
+        }
     }
 
     /// Execute a function call on the given state.
     ///
     /// For scripted functions this will run a new execution layer on the function body.
     /// For wrapped functions this will call the function directly.
+    /// Other objects with a `__call__` dunder method are called through it, with the
+    /// object itself passed as the first argument.
     ///
     /// Stack: `[arg n-1, arg n-2, ... arg 0] -> [return n-1, return n-2, return 0]`
-    pub fn execute_function_call(state: &mut State, n: usize) {
-        let function = {
-            let function = state.pop().unwrap();
-            let function = function.inner.lock().unwrap();
-            match &function.value {
-                Some(ObjectValue::Function(f)) => f.clone(),
-                _ => panic!("Cannot call non-function object"),
-            }
+    ///
+    /// The name, if any, is recorded on the function's call frame.
+    ///
+    /// Returns the number of objects returned by the function.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the object is not callable, or if the call fails.
+    /// The function's call frame is popped either way.
+    pub fn execute_function_call(
+        state: &mut State,
+        n: usize,
+        name: Option<&Arc<str>>,
+    ) -> Result<usize, RuntimeError> {
+        let callee = state.pop_or_err()?;
+        let mut args = state.try_pop_n(n)?;
+        let function = if let Some(function) = as_function(&callee) {
+            function
+        } else {
+            let function = callee
+                .get_dunder("__call__")
+                .as_ref()
+                .and_then(as_function)
+                .ok_or_else(|| RuntimeError::type_error("cannot call non-function object"))?;
+            // The arguments are in reverse order, so the object goes last to come first
+            args.push(callee);
+            function
         };
+        let n = args.len();
 
-        let args = state.pop_n(n);
-        state.push_frame();
+        state.check_call_depth()?;
+        state.push_named_frame(name.cloned());
+        if let Function::Scripted(f) = function.borrow() {
+            state.init_slots(f.locals().clone());
+        }
         state.push_all(&args);
         let push_amt = match function.borrow() {
             Function::Wrapped(f) => f(state, n),
             Function::Scripted(f) => execute(state, f.bytecode()),
         };
-        let returns = state.pop_n(push_amt);
+        let returns = push_amt.and_then(|push_amt| state.try_pop_n(push_amt));
         state.pop_frame();
+        let returns = returns?;
         state.push_all(&returns);
+        Ok(returns.len())
+    }
+
+    /// Get the function held by an object, if it is one.
+    fn as_function(object: &Object) -> Option<Arc<Function>> {
+        match &object.inner.lock().unwrap().value {
+            Some(ObjectValue::Function(f)) => Some(f.clone()),
+            _ => None,
+        }
     }
 }
 
 /// Executors for control flow operations.
 pub(self) mod control_flow {
     use crate::runtime::{
-        bytecode::OpCode,
+        bytecode::{Bytecode, OpCode},
+        error::RuntimeError,
         executor::{execute, run_execution_layer},
         state::State,
+        types::{operations, primitive::Primitive},
     };
 
     /// Executes an if statement, conditionally executing the "then" body or the "else" body.
     /// Note that else-if is implemented as an if statement nested under an else body.
     ///
     /// Stack: `[] -> []`
-    pub fn execute_if_statement(state: &mut State, opcode: &OpCode) -> ControlFlow {
+    pub fn execute_if_statement(
+        state: &mut State,
+        opcode: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
         let (condition, body, else_body) = match opcode {
             OpCode::If {
                 condition,
@@ -242,104 +438,274 @@ pub(self) mod control_flow {
             } => (condition, body, else_body),
             _ => unreachable!(),
         };
-        execute(state, condition);
-        let condition = state.pop().expect("no condition");
-        if let Some(condition) = condition.as_bool() {
-            if condition {
-                function_layer_control_flow!(run_execution_layer(state, body));
-            } else if let Some(else_body) = else_body {
-                function_layer_control_flow!(run_execution_layer(state, else_body));
-            }
-        } else {
-            // TODO: exception handling
-            panic!("expected boolean condition");
+        let condition = evaluate_condition(state, condition)?;
+        if condition {
+            function_layer_control_flow!(run_execution_layer(state, body)?);
+        } else if let Some(else_body) = else_body {
+            function_layer_control_flow!(run_execution_layer(state, else_body)?);
         }
-        ControlFlow::None
+        Ok(ControlFlow::None)
     }
 
     /// Executes a for loop.
     ///
     /// Stack: `[] -> []`
-    pub fn execute_for_loop(state: &mut State, op_code: &OpCode) -> ControlFlow {
-        let (initialization, condition, increment, body) = match op_code {
-            OpCode::For {
-                initialization,
-                condition,
-                increment,
-                body,
-            } => (initialization, condition, increment, body),
-            _ => unreachable!(),
+    pub fn execute_for_loop(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
+        let OpCode::For {
+            initialization,
+            condition,
+            increment,
+            body,
+            else_body,
+        } = op_code
+        else {
+            unreachable!()
         };
         if let Some(initialization) = initialization {
-            execute(state, initialization);
+            execute(state, initialization)?;
         }
         loop {
             let condition_result = match condition {
-                Some(condition) => {
-                    execute(state, condition);
-                    let result = state.pop().expect("no condition");
-                    result.as_bool().expect("expected boolean condition")
-                }
+                Some(condition) => evaluate_condition(state, condition)?,
                 None => true,
             };
             if condition_result {
-                loop_layer_control_flow!(run_execution_layer(state, body));
+                // Unlike other loops, `continue` must still run the increment.
+                match run_execution_layer(state, body)? {
+                    ControlFlow::Return(n) => return Ok(ControlFlow::Return(n)),
+                    ControlFlow::Break => break,
+                    ControlFlow::Continue | ControlFlow::None => {}
+                }
                 if let Some(increment) = increment {
-                    execute(state, increment);
+                    execute(state, increment)?;
                 }
             } else {
+                function_layer_control_flow!(run_else_body(state, else_body.as_ref())?);
                 break;
             }
         }
-        ControlFlow::None
+        Ok(ControlFlow::None)
     }
 
     /// Executes a while loop.
     ///
     /// Stack: `[] -> []`
-    pub fn execute_while_loop(state: &mut State, op_code: &OpCode) -> ControlFlow {
-        let (condition, body) = match op_code {
-            OpCode::While { condition, body } => (condition, body),
-            _ => unreachable!(),
+    pub fn execute_while_loop(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
+        let OpCode::While {
+            condition,
+            body,
+            else_body,
+        } = op_code
+        else {
+            unreachable!()
         };
         loop {
-            execute(state, condition);
-            let condition_result = state.pop().expect("no condition");
-            if let Some(condition_result) = condition_result.as_bool() {
-                if condition_result {
-                    loop_layer_control_flow!(run_execution_layer(state, body));
-                } else {
-                    break;
-                }
+            if evaluate_condition(state, condition)? {
+                loop_layer_control_flow!(run_execution_layer(state, body)?);
+            } else {
+                function_layer_control_flow!(run_else_body(state, else_body.as_ref())?);
+                break;
             }
         }
-        ControlFlow::None
+        Ok(ControlFlow::None)
     }
 
     /// Executes an infinite loop.
     ///
     /// Stack: `[] -> []`
-    pub fn execute_infinite_loop(state: &mut State, op_code: &OpCode) -> ControlFlow {
+    pub fn execute_infinite_loop(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
         let body = match op_code {
             OpCode::Loop { body } => body,
             _ => unreachable!(),
         };
         loop {
-            loop_layer_control_flow!(run_execution_layer(state, body));
+            loop_layer_control_flow!(run_execution_layer(state, body)?);
         }
-        ControlFlow::None
+        Ok(ControlFlow::None)
+    }
+
+    /// Executes a loop which runs its body a fixed number of times.
+    ///
+    /// Stack: `[] -> []`
+    pub fn execute_repeat_loop(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
+        let OpCode::Repeat { count, body } = op_code else {
+            unreachable!()
+        };
+        execute(state, count)?;
+        let count = state.pop_int()?;
+        for _ in 0..count {
+            loop_layer_control_flow!(run_execution_layer(state, body)?);
+        }
+        Ok(ControlFlow::None)
+    }
+
+    /// Executes a with statement, running the body and then closing the resource.
+    ///
+    /// The resource is closed by calling its `__close__` dunder method, even if
+    /// the body exits early or raises an error. An error from the body takes
+    /// precedence over an error from closing the resource.
+    ///
+    /// Stack: `[] -> []`
+    pub fn execute_with_statement(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
+        let OpCode::With {
+            resource,
+            identifier,
+            body,
+        } = op_code
+        else {
+            unreachable!()
+        };
+        execute(state, resource)?;
+        let resource = state.pop_or_err()?;
+        let Some(close) = resource.get_dunder("__close__") else {
+            return Err(RuntimeError::type_error(format!(
+                "'{}' object has no __close__ method",
+                resource.type_name()
+            )));
+        };
+        state.push(&resource);
+        state.store_local(identifier);
+
+        let result = run_execution_layer(state, body);
+        let closed = operations::call_method(state, &close, &[resource]);
+        let control_flow = result?;
+        closed?;
+        // Discard the result of `__close__`.
+        state.pop();
+        Ok(control_flow)
+    }
+
+    /// Executes a `do` block, running the body in a new call frame and pushing its value.
+    ///
+    /// Assigning a variable which already exists outside the block updates it, while new
+    /// locals assigned in the block are discarded along with its call frame. Control flow
+    /// from the block applies to the enclosing layer, so any returned values are moved
+    /// to the enclosing frame.
+    ///
+    /// Stack: `[] -> [value]`
+    pub fn execute_do_block(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<ControlFlow, RuntimeError> {
+        let OpCode::Do { body } = op_code else {
+            unreachable!()
+        };
+        state.push_block_frame(Some("<do>".into()));
+        let result = run_execution_layer(state, body);
+        let values = match &result {
+            Ok(ControlFlow::None) => state.try_pop_n(1),
+            Ok(ControlFlow::Return(n)) => state.try_pop_n(*n),
+            _ => Ok(Vec::new()),
+        };
+        state.pop_frame();
+        let control_flow = result?;
+        state.push_all(&values?);
+        Ok(control_flow)
+    }
+
+    /// Executes an assert statement, raising an error if its condition is false.
+    ///
+    /// Stack: `[] -> []`
+    pub fn execute_assert_statement(
+        state: &mut State,
+        op_code: &OpCode,
+    ) -> Result<(), RuntimeError> {
+        let OpCode::Assert {
+            condition,
+            message,
+            source,
+            variables,
+        } = op_code
+        else {
+            unreachable!()
+        };
+        if evaluate_condition(state, condition)? {
+            return Ok(());
+        }
+        if let Some(message) = message {
+            execute(state, message)?;
+            if let Some(Primitive::String(message)) = state.pop_or_err()?.as_primitive() {
+                return Err(RuntimeError::AssertionFailed(message));
+            }
+        }
+        Err(RuntimeError::AssertionFailed(describe_assertion(
+            state, source, variables,
+        )))
+    }
+
+    /// Describe a failed assertion by its source, followed by the values of the variables
+    /// it uses, e.g. `x > 10 (x = 5)`.
+    ///
+    /// Only variables holding primitive values are shown.
+    fn describe_assertion(state: &mut State, source: &str, variables: &[String]) -> String {
+        let values: Vec<_> = variables
+            .iter()
+            .filter_map(|name| {
+                state.load(name);
+                let value = state.pop()?.as_primitive()?;
+                Some(format!("{name} = {}", value.to_string()))
+            })
+            .collect();
+        if values.is_empty() {
+            source.to_owned()
+        } else {
+            format!("{source} ({})", values.join(", "))
+        }
+    }
+
+    /// Runs the else body of a loop, if any. This is only done once the loop
+    /// condition fails, so a loop exited through `break` skips it.
+    ///
+    /// Control flow from the else body applies to the enclosing layer, not the loop.
+    ///
+    /// Stack: `[] -> []`
+    fn run_else_body(
+        state: &mut State,
+        else_body: Option<&Bytecode>,
+    ) -> Result<ControlFlow, RuntimeError> {
+        else_body.map_or(Ok(ControlFlow::None), |else_body| {
+            run_execution_layer(state, else_body)
+        })
+    }
+
+    /// Executes the bytecode for a condition and pops the resulting boolean.
+    ///
+    /// Stack: `[] -> []`
+    fn evaluate_condition(state: &mut State, condition: &Bytecode) -> Result<bool, RuntimeError> {
+        execute(state, condition)?;
+        let condition = state.pop_or_err()?;
+        condition
+            .as_bool()
+            .ok_or_else(|| RuntimeError::type_error("expected boolean condition"))
     }
 
     /// A macro to propagate control flow out of nested execution layers.
-    /// This macro is used when executing nested layers in a function body
+    /// This macro is used when executing nested layers which aren't loops, such as `if` bodies.
     ///
-    /// This will immediately return control to the appropriate layer.
+    /// This will immediately return the control flow to the enclosing layer. A `break` or
+    /// `continue` inside an `if` within a loop is therefore handed to the loop layer, which
+    /// acts on it with `loop_layer_control_flow`, rather than leaving the function.
     macro_rules! function_layer_control_flow {
         ($cf:expr) => {
             match $cf {
-                ControlFlow::Return(n) => return ControlFlow::Return(n),
-                ControlFlow::Break => return ControlFlow::Break,
-                ControlFlow::Continue => return ControlFlow::Continue,
+                ControlFlow::Return(n) => return Ok(ControlFlow::Return(n)),
+                ControlFlow::Break => return Ok(ControlFlow::Break),
+                ControlFlow::Continue => return Ok(ControlFlow::Continue),
                 ControlFlow::None => {}
             }
         };
@@ -353,7 +719,7 @@ pub(self) mod control_flow {
     macro_rules! loop_layer_control_flow {
         ($cf:expr) => {
             match $cf {
-                ControlFlow::Return(n) => return ControlFlow::Return(n),
+                ControlFlow::Return(n) => return Ok(ControlFlow::Return(n)),
                 ControlFlow::Break => break,
                 ControlFlow::Continue => continue,
                 ControlFlow::None => {}
@@ -366,6 +732,10 @@ pub(self) mod control_flow {
     /// where further action may be taken.
     pub enum ControlFlow {
         /// Causes the control flow to be propagated up to the current function call execution layer.
+        ///
+        /// Holds the number of returned values, which are on top of the current frame's operand
+        /// stack. Layers which tear down a frame of their own on the way up (`do` blocks) pop the
+        /// values first and push them onto the outer frame, so they always reach the call.
         Return(usize),
         /// Causes the control flow to return to the loop execution layer, and break out of the loop.
         Break,
@@ -378,3 +748,89 @@ pub(self) mod control_flow {
     pub(crate) use function_layer_control_flow;
     pub(crate) use loop_layer_control_flow;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::compile,
+        runtime::{
+            bytecode::Bytecode,
+            error::RuntimeError,
+            state::State,
+            types::{primitive::Primitive, utilities::int},
+        },
+    };
+
+    #[test]
+    fn unsupported_binary_operation_is_an_error() {
+        let bytecode = Bytecode::from_text("push_int 2\npush_int 3\nbinary **").unwrap();
+        assert!(matches!(
+            State::new().run(&bytecode),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn errors_in_the_middle_of_an_expression_restore_the_stack() {
+        let mut state = State::new();
+        state.push(&int(42));
+        for source in [
+            "1 + 2 * (3 - \"a\");",
+            "x = [1, 2, [3, 4 + nil]];",
+            "f = fn(a, b) { return a + b; }; 1 + f(2, 3 * nil);",
+        ] {
+            let bytecode = compile(source).unwrap();
+            assert!(state.run(&bytecode).is_err(), "{source}");
+            assert_eq!(state.operand_stack_size(), 1, "{source}");
+        }
+        assert_eq!(
+            state.pop().unwrap().as_primitive(),
+            Some(Primitive::Integer(42))
+        );
+    }
+
+    #[test]
+    fn returning_more_values_than_the_stack_holds_is_an_error() {
+        let bytecode = Bytecode::from_text("push_int 1\nreturn 3").unwrap();
+        let mut state = State::new();
+        assert_eq!(
+            state.run(&bytecode),
+            Err(RuntimeError::StackUnderflow {
+                requested: 3,
+                available: 1,
+            })
+        );
+        assert_eq!(state.operand_stack_size(), 0);
+    }
+
+    #[test]
+    fn using_a_slot_the_frame_does_not_have_is_an_error() {
+        let mut state = State::new();
+        for (text, slot) in [("load_local 5", 5), ("push_int 1\nstore_local 3", 3)] {
+            let bytecode = Bytecode::from_text(text).unwrap();
+            assert_eq!(
+                state.run(&bytecode),
+                Err(RuntimeError::InvalidSlot { slot, slots: 0 })
+            );
+            assert_eq!(state.operand_stack_size(), 0);
+        }
+
+        // A function only has slots for its own locals
+        let text = r#"
+push_function "a"
+  body:
+    store_local 0
+    load_local 1
+    return 1
+store "f"
+push_int 7
+load "f"
+call 1
+"#;
+        let bytecode = Bytecode::from_text(text).unwrap();
+        assert!(matches!(
+            state.run(&bytecode),
+            Err(RuntimeError::InvalidSlot { slot: 1, slots: 1 })
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+//! Module containing the cycle collector.
+//!
+//! Objects are reference counted, so most of them are freed as soon as the last reference
+//! to them is dropped. Containers (tables, lists and objects with a metatable) can however
+//! reference each other in a cycle, which keeps every object in the cycle alive forever.
+//!
+//! To find such cycles, every container is tracked when it is created. A collection then
+//! works like the one in Python: for each tracked container, the references coming from other
+//! tracked containers are subtracted from its reference count. Whatever is left over must
+//! come from outside the containers (a call frame, the operand stack, Rust code, ...), so
+//! the container is reachable, along with everything it references. The remaining
+//! containers are only kept alive by each other, and are cleared to break the cycles.
+//!
+//! Each [state](super::state::State) has its own [`Heap`] of tracked containers, which
+//! [forked](super::state::State::fork) states share. Containers are tracked in the heap of
+//! the state running on the current thread when they are created, so objects created while
+//! no state is running are only tracked once they are stored as a global of a state.
+//!
+//! Since no roots are needed, objects shared between several heaps are never freed
+//! while they are referenced from another heap. Untracked containers and containers
+//! which are locked during a collection are treated as reachable, for the same reason.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex, Weak},
+};
+
+use super::types::object::{Object, ObjectInner, ObjectValue};
+
+/// Number of tracked objects below which dead entries are never pruned.
+const MIN_PRUNE_SIZE: usize = 1024;
+
+thread_local! {
+    /// The heap of the state running on this thread, if any.
+    static CURRENT: RefCell<Option<Heap>> = const { RefCell::new(None) };
+}
+
+/// The containers tracked for a state, which are collected together.
+#[derive(Clone, Default)]
+pub struct Heap {
+    registry: Arc<Mutex<Registry>>,
+}
+
+/// Every tracked container of a heap, keyed by its address.
+struct Registry {
+    objects: HashMap<usize, Weak<Mutex<ObjectInner>>>,
+    /// Size at which dead entries are next pruned, so the registry doesn't grow forever
+    /// between collections.
+    prune_at: usize,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            objects: HashMap::new(),
+            prune_at: MIN_PRUNE_SIZE,
+        }
+    }
+}
+
+impl Registry {
+    fn prune(&mut self) {
+        self.objects.retain(|_, x| x.strong_count() > 0);
+        self.prune_at = (self.objects.len() * 2).max(MIN_PRUNE_SIZE);
+    }
+}
+
+/// Restores the previously current heap of the thread when dropped.
+pub(crate) struct HeapGuard {
+    previous: Option<Heap>,
+}
+
+impl Drop for HeapGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = self.previous.take());
+    }
+}
+
+impl Heap {
+    /// Track an object in this heap so the collector can find cycles through it.
+    ///
+    /// Tracking an object more than once has no effect.
+    ///
+    /// # Panics
+    /// Panics if the heap's registry lock is poisoned.
+    pub fn track(&self, object: &Object) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.objects.insert(
+            Arc::as_ptr(&object.inner) as usize,
+            Arc::downgrade(&object.inner),
+        );
+        if registry.objects.len() >= registry.prune_at {
+            registry.prune();
+        }
+    }
+
+    /// Make this the heap new containers are tracked in on the current thread,
+    /// until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> HeapGuard {
+        let previous = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+        HeapGuard { previous }
+    }
+
+    /// Free every container of this heap which is only kept alive by reference cycles.
+    ///
+    /// Returns the number of objects freed.
+    ///
+    /// # Panics
+    /// Panics if a thread panicked while holding the registry lock or the lock of a
+    /// tracked object.
+    pub fn collect(&self) -> usize {
+        let objects: Vec<_> = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.prune();
+            registry
+                .objects
+                .values()
+                .filter_map(Weak::upgrade)
+                .collect()
+        };
+        collect(&objects)
+    }
+}
+
+/// Track an object in the heap of the state running on the current thread, if any,
+/// so the collector can find cycles through it.
+///
+/// Containers are tracked automatically when they are created. Objects which become
+/// containers later (e.g. by being given a metatable) must be tracked explicitly.
+pub fn track(object: &Object) {
+    CURRENT.with(|current| {
+        if let Some(heap) = current.borrow().as_ref() {
+            heap.track(object);
+        }
+    });
+}
+
+/// Whether an object with the given contents can reference other objects.
+pub(crate) const fn is_container(value: Option<&ObjectValue>, metatable: Option<&Object>) -> bool {
+    matches!(value, Some(ObjectValue::Table(_) | ObjectValue::List(_))) || metatable.is_some()
+}
+
+/// Free every one of the given containers which is only kept alive by reference cycles.
+///
+/// Returns the number of objects freed.
+fn collect(objects: &[Arc<Mutex<ObjectInner>>]) -> usize {
+    let index: HashMap<_, _> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (Arc::as_ptr(x) as usize, i))
+        .collect();
+
+    // References held by this function don't count
+    let mut external: Vec<_> = objects.iter().map(|x| Arc::strong_count(x) - 1).collect();
+    let mut edges = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        // A locked object is in use, so it and everything it references are reachable
+        let Ok(inner) = object.try_lock() else {
+            external[i] = usize::MAX;
+            edges.push(Vec::new());
+            continue;
+        };
+        let children: Vec<_> = referents(&inner)
+            .filter_map(|x| index.get(&(Arc::as_ptr(&x.inner) as usize)).copied())
+            .collect();
+        drop(inner);
+        for &child in &children {
+            external[child] = external[child].saturating_sub(1);
+        }
+        edges.push(children);
+    }
+
+    let mut reachable = vec![false; objects.len()];
+    let mut pending: Vec<_> = (0..objects.len()).filter(|&i| external[i] > 0).collect();
+    while let Some(i) = pending.pop() {
+        if !mem::replace(&mut reachable[i], true) {
+            pending.extend(&edges[i]);
+        }
+    }
+
+    // Clearing an object drops its references, so the contents are only dropped once
+    // every unreachable object has been cleared
+    let mut garbage = Vec::new();
+    for (object, reachable) in objects.iter().zip(reachable) {
+        if !reachable {
+            let mut inner = object.lock().unwrap();
+            garbage.push((inner.value.take(), inner.metatable.take()));
+        }
+    }
+    garbage.len()
+}
+
+/// The objects directly referenced by an object.
+fn referents(inner: &ObjectInner) -> impl Iterator<Item = &Object> {
+    let children: Box<dyn Iterator<Item = &Object>> = match &inner.value {
+        Some(ObjectValue::Table(table)) => Box::new(table.iter().map(|(_, x)| x)),
+        Some(ObjectValue::List(list)) => Box::new(list.iter()),
+        _ => Box::new(std::iter::empty()),
+    };
+    children.chain(&inner.metatable)
+}
@@ -1,4 +1,8 @@
 pub mod bytecode;
+pub mod coverage;
+pub mod error;
 pub mod executor;
+pub mod gc;
 pub mod state;
+pub mod streams;
 pub mod types;
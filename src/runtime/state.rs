@@ -8,10 +8,24 @@
 
 use std::{
     collections::HashMap,
+    ops::{Bound, ControlFlow, RangeBounds},
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
-use super::types::{object::Object, utilities::nil};
+use super::{
+    bytecode::{Bytecode, OpCode, SlotNames},
+    coverage::{Coverage, CoverageReport},
+    error::RuntimeError,
+    executor::execute,
+    gc::{Heap, HeapGuard},
+    streams::{InputSource, OutputSink, Streams},
+    types::{
+        object::Object,
+        primitive::{to_float, Primitive},
+        utilities::{boolean, float, int, list, nil, string},
+    },
+};
 use crate::stdlib;
 
 /// Representation of the memory portion of the program;
@@ -25,6 +39,40 @@ pub struct State {
     /// Call stack. The last element is the current frame, which the
     /// executor primarily operates on.
     stack: Vec<Arc<Mutex<CallFrame>>>,
+    /// Values of imported modules, keyed by the canonical path of the module file.
+    modules: HashMap<PathBuf, Object>,
+    /// Execution limits, and the work counted against them.
+    budget: Budget,
+    /// Hook called before each opcode is executed, if any.
+    step_hook: Option<StepHook>,
+    /// Opcodes executed since coverage was enabled, if it is.
+    coverage: Option<Coverage>,
+    /// Containers created by this state, which the cycle collector can free.
+    heap: Heap,
+    /// Streams which builtins such as `print` and `input` write to and read from.
+    streams: Streams,
+}
+
+/// A hook called with each opcode before it is executed, and the state it will be executed on.
+///
+/// Returning [`ControlFlow::Break`] aborts execution with [`RuntimeError::Aborted`].
+/// The hook must be `Send` so that the state it is set on stays `Send`.
+pub type StepHook = Box<dyn FnMut(&OpCode, &State) -> ControlFlow<()> + Send>;
+
+/// Limits on how much work a run may do, and the work done so far.
+///
+/// Nested runs (e.g. through `exec`) count against the budget of the outermost run,
+/// so a script cannot escape the limits by running more code.
+#[derive(Default)]
+struct Budget {
+    /// Maximum number of instructions per outermost run, or `None` for no limit.
+    max_instructions: Option<u64>,
+    /// Maximum depth of nested function calls and runs, or `None` for no limit.
+    max_depth: Option<usize>,
+    /// Number of instructions executed by the outermost run so far.
+    instructions: u64,
+    /// Number of runs in progress.
+    runs: usize,
 }
 
 impl State {
@@ -34,26 +82,434 @@ impl State {
     /// The [`stdlib`](crate::stdlib) will be registered in the global frame.
     #[must_use]
     pub fn new() -> Self {
-        let mut result = Self { stack: Vec::new() };
+        Self::with_modules(&stdlib::Module::ALL)
+    }
+
+    /// Create a fresh state with only the given [modules](stdlib::Module) of the
+    /// [`stdlib`](crate::stdlib) registered in the global frame.
+    ///
+    /// ```
+    /// use scriptyscript::{runtime::{executor::execute_source, state::State}, stdlib::Module};
+    ///
+    /// let mut state = State::with_modules(&[Module::Core]);
+    /// assert!(execute_source(&mut state, "print(\"hello\");").is_ok());
+    /// assert!(execute_source(&mut state, "max(1, 2);").is_err());
+    /// ```
+    #[must_use]
+    pub fn with_modules(modules: &[stdlib::Module]) -> Self {
+        let mut result = Self {
+            stack: Vec::new(),
+            modules: HashMap::new(),
+            budget: Budget::default(),
+            step_hook: None,
+            coverage: None,
+            heap: Heap::default(),
+            streams: Streams::default(),
+        };
+        result.push_frame();
+        stdlib::register_modules(&mut result, modules);
+        result
+    }
+
+    /// Create a new state with a fresh call stack, starting with a copy of this
+    /// state's global variables (including the [`stdlib`](crate::stdlib)).
+    ///
+    /// This is much cheaper than [`State::new`], since the stdlib is not registered
+    /// again, which makes it useful for running many independent scripts.
+    ///
+    /// Assigning a global in either state does not affect the other, but the objects
+    /// held by globals are shared, so e.g. assigning a key of a global table is visible
+    /// in both. Execution limits are kept, but imported modules are not. The forked state
+    /// shares this state's [heap](Heap), so cycles between objects created by either
+    /// state are freed by [`State::collect_garbage`] on either of them. It also shares
+    /// the [streams](State::set_output) of this state.
+    ///
+    /// # Panics
+    /// Panics if the lock of the global frame is poisoned.
+    #[must_use]
+    pub fn fork(&self) -> Self {
+        let globals = self
+            .stack
+            .first()
+            .expect("no global frame")
+            .lock()
+            .unwrap()
+            .locals
+            .clone();
+        let mut result = Self {
+            stack: Vec::new(),
+            modules: HashMap::new(),
+            budget: Budget {
+                max_instructions: self.budget.max_instructions,
+                max_depth: self.budget.max_depth,
+                ..Budget::default()
+            },
+            step_hook: None,
+            coverage: None,
+            heap: self.heap.clone(),
+            streams: self.streams.clone(),
+        };
         result.push_frame();
-        stdlib::register(&mut result);
+        result.current_frame().unwrap().lock().unwrap().locals = globals;
         result
     }
 
+    /// Run compiled bytecode on this state.
+    ///
+    /// The same bytecode may be run any number of times, so a source string
+    /// only needs to be [compiled](crate::compiler::compile) once.
+    ///
+    /// Returns the value left on top of the operand stack by the bytecode, if any.
+    /// The operand stack is restored to its original size afterwards, even if an error occurs:
+    ///
+    /// ```
+    /// use scriptyscript::{compiler::compile, runtime::state::State};
+    ///
+    /// let mut state = State::new();
+    /// let size = state.operand_stack_size();
+    /// // The list's first two elements are pushed before the error
+    /// assert!(state.run(&compile("x = [1, 2, nil + 1];").unwrap()).is_err());
+    /// assert_eq!(state.operand_stack_size(), size);
+    /// ```
+    ///
+    /// A top-level `return` stops the run, skipping the remaining statements, and its
+    /// value becomes the result:
+    ///
+    /// ```
+    /// use scriptyscript::{
+    ///     compiler::compile,
+    ///     runtime::{state::State, types::primitive::Primitive},
+    /// };
+    ///
+    /// let mut state = State::new();
+    /// let result = state.run(&compile("x = 1; return x + 1; x = 5;").unwrap()).unwrap();
+    /// assert_eq!(result.unwrap().as_primitive(), Some(Primitive::Integer(2)));
+    /// let x = state.run(&compile("x;").unwrap()).unwrap();
+    /// assert_eq!(x.unwrap().as_primitive(), Some(Primitive::Integer(1)));
+    /// ```
+    ///
+    /// Runs started while another run is in progress (e.g. through `exec`) are
+    /// nested; they count as a level of call depth and share the instruction
+    /// budget of the outermost run.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if one occurs during execution, or if
+    /// an execution limit is exceeded.
+    pub fn run(&mut self, bytecode: &Bytecode) -> Result<Option<Object>, RuntimeError> {
+        if self.budget.runs == 0 {
+            self.budget.instructions = 0;
+        } else {
+            self.check_call_depth()?;
+        }
+        self.budget.runs += 1;
+        let _heap = self.enter_heap();
+        let result = self.run_layer(bytecode);
+        self.budget.runs -= 1;
+        result
+    }
+
+    /// Make this state's [heap](Heap) the one new containers are tracked in on the
+    /// current thread, until the returned guard is dropped.
+    pub(crate) fn enter_heap(&self) -> HeapGuard {
+        self.heap.enter()
+    }
+
+    /// Run compiled bytecode, taking the value left on top of the operand stack.
+    fn run_layer(&mut self, bytecode: &Bytecode) -> Result<Option<Object>, RuntimeError> {
+        let initial_size = self.operand_stack_size();
+        execute(self, bytecode)?;
+        let result = if self.operand_stack_size() > initial_size {
+            self.pop()
+        } else {
+            None
+        };
+        self.truncate_operands(initial_size);
+        Ok(result)
+    }
+
+    /// Limit the number of instructions a run may execute, including nested runs.
+    ///
+    /// `None` removes the limit.
+    pub const fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.budget.max_instructions = limit;
+    }
+
+    /// Limit the depth of nested function calls and runs.
+    ///
+    /// `None` removes the limit.
+    pub const fn set_call_depth_limit(&mut self, limit: Option<usize>) {
+        self.budget.max_depth = limit;
+    }
+
+    /// Set the hook called before each opcode is executed, replacing any previous hook.
+    ///
+    /// This is intended for debugging tools such as breakpoints, tracers, and coverage.
+    /// The hook is not copied by [`State::fork`].
+    pub fn set_step_hook(&mut self, hook: StepHook) {
+        self.step_hook = Some(hook);
+    }
+
+    /// Remove the step hook, if any.
+    pub fn clear_step_hook(&mut self) {
+        self.step_hook = None;
+    }
+
+    /// Write the output of scripts, e.g. from `print`, to a sink rather than stdout.
+    ///
+    /// ```
+    /// use std::{io::Write, sync::{Arc, Mutex}};
+    /// use scriptyscript::runtime::{executor::execute_source, state::State};
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct Capture(Arc<Mutex<Vec<u8>>>);
+    ///
+    /// impl Write for Capture {
+    ///     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> std::io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let capture = Capture::default();
+    /// let mut state = State::new();
+    /// state.set_output(Box::new(capture.clone()));
+    /// execute_source(&mut state, "print(\"hello\", 1);").unwrap();
+    /// assert_eq!(*capture.0.lock().unwrap(), b"hello1\n");
+    /// ```
+    pub fn set_output(&mut self, sink: OutputSink) {
+        self.streams.set_output(sink);
+    }
+
+    /// Read the input of scripts, e.g. for `input`, from a source rather than stdin.
+    pub fn set_input(&mut self, source: InputSource) {
+        self.streams.set_input(source);
+    }
+
+    /// Choose whether output is left buffered, rather than flushed after each write.
+    ///
+    /// Buffered output is still flushed by the `flush` builtin and before reading input,
+    /// so prompts are always shown. Output is not buffered by default.
+    pub const fn set_output_buffered(&mut self, buffered: bool) {
+        self.streams.set_buffered(buffered);
+    }
+
+    /// The streams which builtins write to and read from.
+    pub(crate) const fn streams(&self) -> &Streams {
+        &self.streams
+    }
+
+    /// Start recording which opcodes are executed, discarding anything recorded before.
+    ///
+    /// Coverage is not copied by [`State::fork`].
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    /// Stop recording which opcodes are executed.
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    /// Report which opcodes of some bytecode were executed since coverage was enabled.
+    ///
+    /// The bytecode must be the bytecode that was run, or a clone of it.
+    /// Returns `None` if coverage is not enabled.
+    ///
+    /// ```
+    /// use scriptyscript::{compiler::compile, runtime::state::State};
+    ///
+    /// let bytecode = compile(r#"n = 1; if n > 0 { x = "yes"; } else { x = "no"; }"#).unwrap();
+    /// let mut state = State::new();
+    /// state.enable_coverage();
+    /// state.run(&bytecode).unwrap();
+    ///
+    /// let report = state.coverage_report(&bytecode).unwrap();
+    /// assert!(report.covered() < report.total());
+    /// let text = report.to_string();
+    /// let line = |opcode: &str| text.lines().find(|x| x.ends_with(opcode)).unwrap();
+    /// assert!(line(r#"push_string "yes""#).starts_with('+'));
+    /// assert!(line(r#"push_string "no""#).starts_with('-'));
+    /// ```
+    #[must_use]
+    pub fn coverage_report(&self, bytecode: &Bytecode) -> Option<CoverageReport> {
+        self.coverage.as_ref().map(|x| x.report(bytecode))
+    }
+
+    /// Record an opcode about to be executed for coverage, and call the step hook, if any.
+    ///
+    /// # Errors
+    /// Returns [`RuntimeError::Aborted`] if the hook asks for execution to stop.
+    pub(crate) fn step(&mut self, bytecode: &Bytecode, index: usize) -> Result<(), RuntimeError> {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(bytecode, index);
+        }
+        // The hook is taken out while it runs, since it needs to borrow the state
+        let Some(mut hook) = self.step_hook.take() else {
+            return Ok(());
+        };
+        let flow = hook(&bytecode.inner()[index], self);
+        self.step_hook = Some(hook);
+        match flow {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(RuntimeError::Aborted),
+        }
+    }
+
+    /// Count an instruction against the instruction limit.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the instruction limit is exceeded.
+    pub(crate) const fn count_instruction(&mut self) -> Result<(), RuntimeError> {
+        self.budget.instructions += 1;
+        match self.budget.max_instructions {
+            Some(limit) if self.budget.instructions > limit => {
+                Err(RuntimeError::InstructionLimit(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that one more level of calls can be entered without exceeding the call depth limit.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the call depth limit would be exceeded.
+    pub(crate) const fn check_call_depth(&self) -> Result<(), RuntimeError> {
+        // Function calls each have a frame above the global frame,
+        // and nested runs are counted on top of the outermost run.
+        let depth = (self.stack.len() - 1) + self.budget.runs.saturating_sub(1);
+        match self.budget.max_depth {
+            Some(limit) if depth >= limit => Err(RuntimeError::CallDepthLimit(limit)),
+            _ => Ok(()),
+        }
+    }
+
     /// Push a new call frame onto the stack.
     ///
     /// The new frame will have no locals.
     pub fn push_frame(&mut self) {
-        let frame = match self.current_frame() {
-            Some(parent) => CallFrame::with_parent(parent),
-            None => CallFrame::new(),
+        let frame = self.new_frame();
+        self.stack.push(Arc::new(Mutex::new(frame)));
+    }
+
+    /// Push a new call frame onto the stack, recording the name it was called through.
+    ///
+    /// The name is only used to describe the call stack (see [`State::traceback`]).
+    pub fn push_named_frame(&mut self, name: Option<Arc<str>>) {
+        let frame = CallFrame {
+            name,
+            ..self.new_frame()
         };
         self.stack.push(Arc::new(Mutex::new(frame)));
     }
 
+    /// Create a call frame without locals, whose parent is the current frame (if any).
+    fn new_frame(&self) -> CallFrame {
+        self.current_frame()
+            .map_or_else(CallFrame::new, CallFrame::with_parent)
+    }
+
+    /// Push a new call frame for a block scope onto the stack, such as a `do` block.
+    ///
+    /// Storing a variable which already exists in an enclosing frame updates that
+    /// variable, so only new variables are local to the block. The enclosing frames
+    /// are the parent frames up to and including the first one which isn't a block scope.
+    pub fn push_block_frame(&mut self, name: Option<Arc<str>>) {
+        let frame = CallFrame {
+            name,
+            block: true,
+            ..self.new_frame()
+        };
+        self.stack.push(Arc::new(Mutex::new(frame)));
+    }
+
+    /// Give the current call frame a slot for each of the given local names, which
+    /// replaces any slots it already had.
+    ///
+    /// The slots start out unassigned. See [`OpCode::LoadLocal`] for how they are used.
+    ///
+    /// # Panics
+    /// Panics if the current frame's lock is poisoned.
+    pub fn init_slots(&mut self, names: Arc<SlotNames>) {
+        let frame = self.current_frame().expect("no call frame");
+        let mut frame = frame.lock().unwrap();
+        frame.slots = vec![None; names.len()];
+        frame.slot_names = names;
+    }
+
+    /// Describe the call stack, starting with the global frame and ending with the current frame.
+    ///
+    /// The global frame is described as `<main>`, and frames without a recorded name
+    /// as `<anonymous>`.
+    ///
+    /// # Panics
+    /// Panics if the lock of any frame on the stack is poisoned.
+    #[must_use]
+    pub fn traceback(&self) -> Vec<String> {
+        self.stack
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| match &frame.lock().unwrap().name {
+                Some(name) => name.to_string(),
+                None if i == 0 => "<main>".to_string(),
+                None => "<anonymous>".to_string(),
+            })
+            .collect()
+    }
+
+    /// Get the depth of the call stack, counting the global frame.
+    ///
+    /// A script running at the top level has a depth of 1.
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Take a snapshot of the current call frame's local variables, sorted by name.
+    ///
+    /// Variables of parent frames are not included.
+    ///
+    /// # Panics
+    /// Panics if the current frame's lock is poisoned.
+    #[must_use]
+    pub fn current_locals(&self) -> Vec<(String, Object)> {
+        locals_of(&self.current_frame().expect("no call frame"))
+    }
+
+    /// Take a snapshot of the calling frame's local variables, in the same way as
+    /// [`State::current_locals`].
+    ///
+    /// This is the frame which called the function running in the current frame, e.g.
+    /// the script calling a wrapped function. Returns `None` in the global frame.
+    #[must_use]
+    pub fn caller_locals(&self) -> Option<Vec<(String, Object)>> {
+        let caller = self.stack.len().checked_sub(2)?;
+        Some(locals_of(&self.stack[caller]))
+    }
+
+    /// Take a snapshot of the current call frame's operand stack, from bottom to top.
+    ///
+    /// # Panics
+    /// Panics if another thread panicked while holding the current frame's lock.
+    #[must_use]
+    pub fn operand_stack_snapshot(&self) -> Vec<Object> {
+        self.current_frame()
+            .expect("no call frame")
+            .lock()
+            .unwrap()
+            .operands
+            .clone()
+    }
+
     /// Pop the current call frame off the stack.
+    ///
+    /// The global frame is never popped, so a state always has a current frame.
+    /// Popping when only the global frame is left does nothing.
     pub fn pop_frame(&mut self) {
-        self.stack.pop().expect("no call frame to pop");
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
     }
 
     /// Get a mutable reference to the current call frame.
@@ -89,6 +545,29 @@ impl State {
             .pop()
     }
 
+    /// Pop an object off the current call frame's operand stack.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::StackUnderflow`] if the stack is empty.
+    ///
+    /// The executor uses this for its operands, so malformed bytecode fails cleanly:
+    /// ```
+    /// use scriptyscript::runtime::{bytecode::{Bytecode, OpCode}, state::State};
+    ///
+    /// let mut bytecode = Bytecode::new();
+    /// bytecode.push(OpCode::GetKey { key: "x".to_owned(), target: None });
+    ///
+    /// let mut state = State::new();
+    /// assert!(state.run(&bytecode).is_err());
+    /// assert!(state.run(&scriptyscript::compiler::compile("x = 1;").unwrap()).is_ok());
+    /// ```
+    pub fn pop_or_err(&mut self) -> Result<Object, RuntimeError> {
+        self.pop().ok_or(RuntimeError::StackUnderflow {
+            requested: 1,
+            available: 0,
+        })
+    }
+
     /// Pop multiple objects off the current call frame's operand stack.
     ///
     /// The returned vector will contain the objects in the same order
@@ -102,6 +581,61 @@ impl State {
         result
     }
 
+    /// Pop multiple objects off the current call frame's operand stack,
+    /// in the same order as [`State::pop_n`].
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::StackUnderflow`] if the stack holds fewer than `n` objects,
+    /// in which case nothing is popped.
+    pub fn try_pop_n(&mut self, n: usize) -> Result<Vec<Object>, RuntimeError> {
+        let available = self.operand_stack_size();
+        if available < n {
+            return Err(RuntimeError::StackUnderflow {
+                requested: n,
+                available,
+            });
+        }
+        Ok(self.pop_n(n))
+    }
+
+    /// Check the number of arguments a wrapped function was called with.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::ArgumentCount`] if `n` is outside the accepted range.
+    ///
+    /// ```
+    /// use scriptyscript::runtime::{error::RuntimeError, state::State};
+    ///
+    /// assert!(State::expect_args(2, 1..=3).is_ok());
+    /// assert_eq!(
+    ///     State::expect_args(0, 1..).unwrap_err().to_string(),
+    ///     "expected at least 1 argument, got 0"
+    /// );
+    /// ```
+    pub fn expect_args(n: usize, accepted: impl RangeBounds<usize>) -> Result<(), RuntimeError> {
+        if accepted.contains(&n) {
+            return Ok(());
+        }
+        let min = match accepted.start_bound() {
+            Bound::Included(&min) => min,
+            Bound::Excluded(&min) => min + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match accepted.end_bound() {
+            Bound::Included(&max) => Some(max),
+            Bound::Excluded(&max) => Some(max.saturating_sub(1)),
+            Bound::Unbounded => None,
+        };
+        let plural = |count: usize| if count == 1 { "argument" } else { "arguments" };
+        let expected = match max {
+            Some(max) if max == min => format!("{min} {}", plural(min)),
+            Some(max) if min == 0 => format!("at most {max} {}", plural(max)),
+            Some(max) => format!("{min} to {max} arguments"),
+            None => format!("at least {min} {}", plural(min)),
+        };
+        Err(RuntimeError::ArgumentCount { expected, got: n })
+    }
+
     /// Peek at the top of the current call frame's operand stack.
     ///
     /// Returns `None` if the stack is empty.
@@ -113,11 +647,97 @@ impl State {
             .peek()
     }
 
+    /// Pop an integer off the current call frame's operand stack.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the stack is empty or the popped object is not an integer.
+    pub fn pop_int(&mut self) -> Result<i64, RuntimeError> {
+        match self.pop_primitive("int")? {
+            Primitive::Integer(x) => Ok(x),
+            other => Err(mismatch("int", other.type_name())),
+        }
+    }
+
+    /// Pop a number off the current call frame's operand stack as a float.
+    ///
+    /// Integers are converted to floats.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the stack is empty or the popped object is not a number.
+    pub fn pop_float(&mut self) -> Result<f64, RuntimeError> {
+        match self.pop_primitive("number")? {
+            Primitive::Float(x) => Ok(x),
+            Primitive::Integer(x) => Ok(to_float(x)),
+            other => Err(mismatch("number", other.type_name())),
+        }
+    }
+
+    /// Pop a string off the current call frame's operand stack.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the stack is empty or the popped object is not a string.
+    pub fn pop_string(&mut self) -> Result<String, RuntimeError> {
+        match self.pop_primitive("string")? {
+            Primitive::String(x) => Ok(x),
+            other => Err(mismatch("string", other.type_name())),
+        }
+    }
+
+    /// Pop a boolean off the current call frame's operand stack.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the stack is empty or the popped object is not a boolean.
+    pub fn pop_bool(&mut self) -> Result<bool, RuntimeError> {
+        match self.pop_primitive("bool")? {
+            Primitive::Boolean(x) => Ok(x),
+            other => Err(mismatch("bool", other.type_name())),
+        }
+    }
+
+    /// Pop a primitive off the current call frame's operand stack.
+    ///
+    /// The expected type name is only used for the error message if the popped
+    /// object is not a primitive.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::StackUnderflow`] if the stack is empty, or a
+    /// [`RuntimeError::TypeError`] if the popped object is not a primitive.
+    fn pop_primitive(&mut self, expected: &str) -> Result<Primitive, RuntimeError> {
+        let object = self.pop_or_err()?;
+        object
+            .as_primitive()
+            .ok_or_else(|| mismatch(expected, object.type_name()))
+    }
+
+    /// Push an integer onto the current call frame's operand stack.
+    pub fn push_int(&mut self, x: i64) {
+        self.push(&int(x));
+    }
+
+    /// Push a float onto the current call frame's operand stack.
+    pub fn push_float(&mut self, x: f64) {
+        self.push(&float(x));
+    }
+
+    /// Push a string onto the current call frame's operand stack.
+    pub fn push_string(&mut self, x: impl AsRef<str>) {
+        self.push(&string(x));
+    }
+
+    /// Push a boolean onto the current call frame's operand stack.
+    pub fn push_bool(&mut self, x: bool) {
+        self.push(&boolean(x));
+    }
+
     /// Set a global variable.
     ///
     /// Internally this stores the object as a local in the
-    /// bottom-most call frame.
+    /// bottom-most call frame. Containers are tracked in this state's [heap](Heap),
+    /// since they may have been created while the state wasn't running.
     pub fn set_global(&mut self, name: &str, obj: Object) {
+        if obj.is_container() {
+            self.heap.track(&obj);
+        }
         self.stack
             .get(0)
             .expect("no global frame")
@@ -127,6 +747,54 @@ impl State {
             .insert(name.to_string(), obj);
     }
 
+    /// Set the `args` global to a list of the given command-line arguments.
+    pub fn set_args<S: AsRef<str>>(&mut self, args: impl IntoIterator<Item = S>) {
+        let args = args.into_iter().map(string).collect();
+        self.set_global("args", list(args));
+    }
+
+    /// Get the cached value of an imported module.
+    ///
+    /// Returns `None` if the module has not been imported yet.
+    #[must_use]
+    pub fn get_module(&self, path: &Path) -> Option<Object> {
+        self.modules.get(path).cloned()
+    }
+
+    /// Cache the value of an imported module, so it is not run again.
+    pub fn cache_module(&mut self, path: &Path, module: Object) {
+        self.modules.insert(path.to_owned(), module);
+    }
+
+    /// Free objects which are only kept alive by reference cycles,
+    /// such as a table which contains itself.
+    ///
+    /// Objects without cycles are freed as soon as they are unused, so this only needs
+    /// to be called occasionally, e.g. between runs. Only objects in this state's
+    /// [heap](Heap) are freed. See the [`gc`](super::gc) module for how the cycles
+    /// are found.
+    ///
+    /// Returns the number of objects freed.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use scriptyscript::runtime::{executor::execute_source, state::State};
+    ///
+    /// let mut state = State::new();
+    /// let a = execute_source(&mut state, "a = table(); a.me = a; a;").unwrap().unwrap();
+    /// let cycle = Arc::downgrade(&a.inner);
+    /// drop(a);
+    /// execute_source(&mut state, "a = nil;").unwrap();
+    ///
+    /// assert!(cycle.upgrade().is_some());
+    /// assert_eq!(state.collect_garbage(), 1);
+    /// assert!(cycle.upgrade().is_none());
+    /// ```
+    #[must_use]
+    pub fn collect_garbage(&self) -> usize {
+        self.heap.collect()
+    }
+
     /// Store a local variable into the current call frame.
     ///
     /// Stack: `[value] -> []`
@@ -138,6 +806,40 @@ impl State {
             .store_local(name);
     }
 
+    /// Store a local variable into the given slot of the current call frame.
+    ///
+    /// Stack: `[value] -> []`
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the frame has no such slot, or if the stack is empty.
+    ///
+    /// # Panics
+    /// Panics if the current frame's lock is poisoned.
+    pub fn store_slot(&mut self, slot: usize) -> Result<(), RuntimeError> {
+        self.current_frame()
+            .expect("no call frame")
+            .lock()
+            .unwrap()
+            .store_slot(slot)
+    }
+
+    /// Load a local variable from the given slot of the current call frame.
+    ///
+    /// Stack: `[] -> [value]`
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the frame has no such slot.
+    ///
+    /// # Panics
+    /// Panics if another thread panicked while holding the current frame's lock.
+    pub fn load_slot(&mut self, slot: usize) -> Result<(), RuntimeError> {
+        self.current_frame()
+            .expect("no call frame")
+            .lock()
+            .unwrap()
+            .load_slot(slot)
+    }
+
     /// Load a local variable from the current call frame.
     ///
     /// Stack: `[] -> [value]`
@@ -149,6 +851,18 @@ impl State {
             .load(name);
     }
 
+    /// Shrink the operand stack of the current call frame to the given size.
+    ///
+    /// Has no effect if the stack is already smaller.
+    pub(crate) fn truncate_operands(&self, size: usize) {
+        self.current_frame()
+            .expect("no call frame")
+            .lock()
+            .unwrap()
+            .operands
+            .truncate(size);
+    }
+
     /// Get the size of the operand stack of the current call frame.
     #[must_use]
     pub fn operand_stack_size(&self) -> usize {
@@ -161,6 +875,25 @@ impl State {
     }
 }
 
+/// Take a snapshot of a call frame's local variables, sorted by name.
+fn locals_of(frame: &Mutex<CallFrame>) -> Vec<(String, Object)> {
+    let frame = frame.lock().unwrap();
+    let slots = frame
+        .slot_names
+        .iter()
+        .zip(&frame.slots)
+        .filter_map(|(name, value)| Some((name.clone(), value.clone()?)));
+    let mut result: Vec<_> = frame.locals.clone().into_iter().chain(slots).collect();
+    drop(frame);
+    result.sort_by(|(a, _), (b, _)| a.cmp(b));
+    result
+}
+
+/// Create the error for an object of the wrong type being popped.
+fn mismatch(expected: &str, found: &str) -> RuntimeError {
+    RuntimeError::type_error(format!("expected {expected}, got {found}"))
+}
+
 impl Default for State {
     /// Same as `State::new()`
     fn default() -> Self {
@@ -178,8 +911,16 @@ pub struct CallFrame {
     pub parent: Option<Arc<Mutex<CallFrame>>>,
     /// The operand stack.
     pub operands: Vec<Object>,
-    /// The local variables.
+    /// The local variables which are not stored in slots.
     pub locals: HashMap<String, Object>,
+    /// The values of the local variables stored in slots, or `None` if unassigned.
+    pub slots: Vec<Option<Object>>,
+    /// The names of the local variables stored in slots, indexed by their slot.
+    pub slot_names: Arc<SlotNames>,
+    /// The name the frame's function was called through, if any.
+    pub name: Option<Arc<str>>,
+    /// Whether the frame is a block scope (see [`State::push_block_frame`]).
+    pub block: bool,
 }
 
 impl CallFrame {
@@ -197,6 +938,10 @@ impl CallFrame {
             parent: None,
             operands: Vec::new(),
             locals: HashMap::new(),
+            slots: Vec::new(),
+            slot_names: Arc::default(),
+            name: None,
+            block: false,
         }
     }
 
@@ -229,11 +974,36 @@ impl CallFrame {
     /// Load a local variable from the current frame. If the variable is not
     /// found in the current frame, the parent frames will be searched recursively.
     pub fn load(&mut self, name: &str) {
-        let local_value = self.locals.get(name).cloned();
-        if let Some(x) = local_value {
+        let local_value = self.load_local(name).cloned();
+        match local_value {
+            Some(x) => self.push(&x),
+            None => self.load_from_parent(name),
+        }
+    }
+
+    /// Load a local variable from the given slot of the current frame. If the slot
+    /// is unassigned, the parent frames will be searched recursively by name.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the frame has no such slot.
+    pub fn load_slot(&mut self, slot: usize) -> Result<(), RuntimeError> {
+        if let Some(x) = self
+            .slots
+            .get(slot)
+            .ok_or_else(|| self.invalid_slot(slot))?
+        {
+            let x = x.clone();
             self.push(&x);
-        } else if self.parent.is_some() {
-            let parent = self.parent.clone().unwrap();
+        } else {
+            let names = self.slot_names.clone();
+            self.load_from_parent(&names[slot]);
+        }
+        Ok(())
+    }
+
+    /// Load a variable from the parent frames, or `nil` if there is no such variable.
+    fn load_from_parent(&mut self, name: &str) {
+        if let Some(parent) = self.parent.clone() {
             let mut parent = parent.lock().unwrap();
             parent.load(name);
             self.push(&parent.pop().unwrap());
@@ -247,15 +1017,77 @@ impl CallFrame {
     /// Returns `None` if the variable is not found.
     #[must_use]
     pub fn load_local(&self, name: &str) -> Option<&Object> {
-        self.locals.get(name)
+        self.slot(name)
+            .map_or_else(|| self.locals.get(name), |slot| self.slots[slot].as_ref())
     }
 
-    /// Store a local variable into the current frame.
+    /// Store a local variable into the current frame, in its slot if it has one.
+    ///
+    /// If this frame is a block scope, a variable which already exists in an enclosing
+    /// frame is updated there instead.
     ///
     /// Stack: `[value] -> []`
     pub fn store_local(&mut self, name: &str) {
         let value = self.pop().unwrap();
-        self.locals.insert(name.to_string(), value);
+        if let Err(value) = self.store_existing(name, value) {
+            self.assign(name, value);
+        }
+    }
+
+    /// Store a variable into this frame or an enclosing frame of a block scope,
+    /// wherever it already exists.
+    ///
+    /// Gives the value back if the variable doesn't exist in any of them.
+    fn store_existing(&mut self, name: &str, value: Object) -> Result<(), Object> {
+        if self.load_local(name).is_some() {
+            self.assign(name, value);
+            return Ok(());
+        }
+        match &self.parent {
+            Some(parent) if self.block => parent.lock().unwrap().store_existing(name, value),
+            _ => Err(value),
+        }
+    }
+
+    /// Assign a variable in this frame, in its slot if it has one.
+    fn assign(&mut self, name: &str, value: Object) {
+        match self.slot(name) {
+            Some(slot) => self.slots[slot] = Some(value),
+            None => {
+                self.locals.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    /// Store a local variable into the given slot of the current frame.
+    ///
+    /// Stack: `[value] -> []`
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the frame has no such slot, or if the stack is empty.
+    pub fn store_slot(&mut self, slot: usize) -> Result<(), RuntimeError> {
+        if slot >= self.slots.len() {
+            return Err(self.invalid_slot(slot));
+        }
+        let value = self.pop().ok_or(RuntimeError::StackUnderflow {
+            requested: 1,
+            available: 0,
+        })?;
+        self.slots[slot] = Some(value);
+        Ok(())
+    }
+
+    /// Create the error for using a slot which this frame doesn't have.
+    const fn invalid_slot(&self, slot: usize) -> RuntimeError {
+        RuntimeError::InvalidSlot {
+            slot,
+            slots: self.slots.len(),
+        }
+    }
+
+    /// Find the slot of a local variable, if it is stored in one.
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.slot_names.slot(name)
     }
 }
 
@@ -0,0 +1,114 @@
+//! Module containing the input and output streams of a state.
+//!
+//! Builtins such as `print` and `input` don't use stdout and stdin directly, but the
+//! [streams](super::state::State::set_output) of the state they run on, so that an
+//! embedder can capture the output of a script or feed it input.
+//!
+//! Output is flushed after each write by default, so that it never lags behind an
+//! `input` prompt or output written by other means. [Buffered](Streams::set_buffered)
+//! output is only flushed by the `flush` builtin, before reading input, and whenever
+//! the sink decides to, e.g. at the end of each line for stdout.
+
+use std::{
+    io::{BufRead, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+use super::error::RuntimeError;
+
+/// A sink for the output of a state, e.g. a file or a buffer.
+///
+/// The sink must be `Send` so that the state it is set on stays `Send`.
+pub type OutputSink = Box<dyn Write + Send>;
+
+/// A source for the input of a state, e.g. a file or a buffer.
+///
+/// The source must be `Send` so that the state it is set on stays `Send`.
+pub type InputSource = Box<dyn BufRead + Send>;
+
+/// The input and output streams of a state, which are shared with its forks.
+///
+/// Streams which aren't set are stdin and stdout.
+#[derive(Clone, Default)]
+pub(crate) struct Streams {
+    output: Arc<Mutex<Option<OutputSink>>>,
+    input: Arc<Mutex<Option<InputSource>>>,
+    buffered: bool,
+}
+
+impl Streams {
+    /// Replace the output sink.
+    pub(crate) fn set_output(&self, sink: OutputSink) {
+        *self.output.lock().unwrap() = Some(sink);
+    }
+
+    /// Replace the input source.
+    pub(crate) fn set_input(&self, source: InputSource) {
+        *self.input.lock().unwrap() = Some(source);
+    }
+
+    /// Choose whether output is left buffered rather than flushed after each write.
+    pub(crate) const fn set_buffered(&mut self, buffered: bool) {
+        self.buffered = buffered;
+    }
+
+    /// Write text to the output, flushing it unless output is buffered.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::IoError`] if writing fails.
+    pub(crate) fn write(&self, text: &str) -> Result<(), RuntimeError> {
+        self.with_output(|sink| {
+            sink.write_all(text.as_bytes())?;
+            if !self.buffered {
+                sink.flush()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Flush the output, writing anything which is still buffered.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::IoError`] if flushing fails.
+    pub(crate) fn flush(&self) -> Result<(), RuntimeError> {
+        self.with_output(|sink| sink.flush())
+    }
+
+    /// Read a line from the input into a string, including its line ending.
+    ///
+    /// Returns the number of bytes read, which is 0 at the end of the input.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::IoError`] if reading fails.
+    pub(crate) fn read_line(&self, line: &mut String) -> Result<usize, RuntimeError> {
+        match self.input.lock().unwrap().as_mut() {
+            Some(source) => source.read_line(line),
+            None => std::io::stdin().read_line(line),
+        }
+        .map_err(|e| RuntimeError::IoError(e.to_string()))
+    }
+
+    /// Read everything remaining on the input into a string.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError::IoError`] if reading fails.
+    pub(crate) fn read_to_string(&self, input: &mut String) -> Result<usize, RuntimeError> {
+        match self.input.lock().unwrap().as_mut() {
+            Some(source) => source.read_to_string(input),
+            None => std::io::stdin().read_to_string(input),
+        }
+        .map_err(|e| RuntimeError::IoError(e.to_string()))
+    }
+
+    /// Run a function on the output sink, or on stdout if it isn't set.
+    fn with_output(
+        &self,
+        f: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    ) -> Result<(), RuntimeError> {
+        match self.output.lock().unwrap().as_mut() {
+            Some(sink) => f(sink),
+            None => f(&mut std::io::stdout().lock()),
+        }
+        .map_err(|e| RuntimeError::IoError(e.to_string()))
+    }
+}
@@ -1,19 +1,27 @@
 /// Module containing the [`Function`] enum, which is used to represent a callable function.
 /// The function may either be a scripted or a wrapped (Rust-side).
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
-use crate::runtime::{bytecode::Bytecode, state::State};
+use crate::runtime::{
+    bytecode::{Bytecode, SlotNames},
+    error::RuntimeError,
+    state::State,
+};
 
 /// A function pointer to a native function.
 ///
 /// The first argument is the state the function was called by.
 /// The second argument is the number of arguments passed to the function
 /// which the native function may pop from the state.
-/// The return value is the number of values pushed back onto the stack.
+/// The return value is the number of values pushed back onto the stack,
+/// or a [`RuntimeError`] if the function failed.
 ///
 /// Currently, the wrapped function is in charge of keeping the stack balanced
 /// to ensure stability. This may change in the future.
-pub type WrappedFunction = fn(state: &mut State, n_args: usize) -> usize;
+pub type WrappedFunction = fn(state: &mut State, n_args: usize) -> Result<usize, RuntimeError>;
 
 /// An enum wrapping either a scripted function (containing bytecode) or a wrapped function
 /// (a function pointer to a native function)
@@ -41,8 +49,13 @@ impl Debug for Function {
 }
 
 impl Display for Function {
+    /// Formats the function as `function #<id>` for scripted functions, where the id
+    /// identifies the function's bytecode, or `wrapped function` for wrapped functions.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(&self, f)
+        match self {
+            Self::Scripted(func) => write!(f, "function #{}", func.bytecode().id()),
+            Self::Wrapped(_) => write!(f, "wrapped function"),
+        }
     }
 }
 
@@ -61,13 +74,22 @@ impl PartialEq for Function {
 pub struct ScriptedFunction {
     /// The bytecode of the function.
     bytecode: Bytecode,
+    /// The names of the locals stored by the function, indexed by their slot.
+    locals: Arc<SlotNames>,
 }
 
 impl ScriptedFunction {
-    /// Creates a new scripted function from the given bytecode.
+    /// Creates a new scripted function from the given bytecode, whose locals are
+    /// stored in slots with the given names.
+    #[must_use]
+    pub const fn new(bytecode: Bytecode, locals: Arc<SlotNames>) -> Self {
+        Self { bytecode, locals }
+    }
+
+    /// Returns the names of the locals stored by the function, indexed by their slot.
     #[must_use]
-    pub fn new(bytecode: Bytecode) -> Self {
-        Self { bytecode }
+    pub const fn locals(&self) -> &Arc<SlotNames> {
+        &self.locals
     }
 
     /// Returns the bytecode of the function.
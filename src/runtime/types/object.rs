@@ -1,21 +1,30 @@
 use std::{
+    cell::RefCell,
     fmt::{Debug, Formatter},
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
 };
 
 use super::{function::Function, primitive::Primitive, table::Table};
+use crate::runtime::{error::RuntimeError, gc};
 
 #[derive(Debug, Clone)]
 pub enum ObjectValue {
     Primitive(Primitive),
     Function(Arc<Function>),
     Table(Table),
+    List(Vec<Object>),
+    /// A reference to an object which doesn't keep it alive.
+    Weak(Weak<Mutex<ObjectInner>>),
+    /// The buffer of a string builder, which grows in place as strings are appended.
+    ///
+    /// Scripts can only create one through `string_builder.new`, so no other string
+    /// is ever mutated by appending.
+    StringBuilder(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct ObjectInner {
     pub value: Option<ObjectValue>,
-    #[allow(unused)]
     pub metatable: Option<Object>,
 }
 
@@ -52,9 +61,20 @@ pub struct Object {
 impl Object {
     #[must_use]
     pub fn new(value: Option<ObjectValue>, metatable: Option<Self>) -> Self {
-        Self {
+        let container = gc::is_container(value.as_ref(), metatable.as_ref());
+        let result = Self {
             inner: Arc::new(Mutex::new(ObjectInner { value, metatable })),
+        };
+        if container {
+            gc::track(&result);
         }
+        result
+    }
+
+    /// Whether the object can reference other objects, and so be part of a cycle.
+    pub(crate) fn is_container(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        gc::is_container(inner.value.as_ref(), inner.metatable.as_ref())
     }
 
     #[must_use]
@@ -62,6 +82,12 @@ impl Object {
         self.inner.clone()
     }
 
+    /// Whether two objects are the same object, rather than equal values.
+    #[must_use]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
     #[must_use]
     pub fn as_primitive(&self) -> Option<Primitive> {
         match &self.inner.lock().unwrap().value {
@@ -78,30 +104,197 @@ impl Object {
         }
     }
 
-    pub fn set_key(&mut self, key: &str, value: Self) {
+    /// Assign a value to a key of a table.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the object is not a table, or if the table is frozen.
+    ///
+    /// # Panics
+    /// Panics if the object's lock is poisoned.
+    pub fn set_key(&mut self, key: &str, value: Self) -> Result<(), RuntimeError> {
         match &mut self.inner.lock().unwrap().value {
-            Some(ObjectValue::Table(table)) => table.set(key.to_owned(), value),
-            _ => panic!("Cannot set key on non-table object"),
+            Some(ObjectValue::Table(table)) if table.is_frozen() => {
+                Err(RuntimeError::FrozenTable(key.to_owned()))
+            }
+            Some(ObjectValue::Table(table)) => {
+                table.set(key, value);
+                Ok(())
+            }
+            _ => Err(RuntimeError::type_error(
+                "cannot set key on non-table object",
+            )),
         }
     }
 
+    /// Get the value of a key of a table.
+    ///
+    /// Returns `None` if the object is not a table, or if the key is not present.
     #[must_use]
     pub fn get_key(&self, key: &str) -> Option<Self> {
         match &self.inner.lock().unwrap().value {
             Some(ObjectValue::Table(table)) => table.get(key).cloned(),
-            _ => panic!("Cannot get key on non-table object"),
+            _ => None,
+        }
+    }
+
+    /// Look up a dunder method (e.g. `__add__`) for this object.
+    ///
+    /// The method is looked up in the object's metatable. A table without a
+    /// metatable acts as its own metatable.
+    ///
+    /// # Panics
+    /// Panics if the lock of the object or its metatable is poisoned.
+    #[must_use]
+    pub fn get_dunder(&self, name: &str) -> Option<Self> {
+        // The lock must be released before the metatable is locked, since
+        // an object may be its own metatable.
+        let metatable = {
+            let inner = self.inner.lock().unwrap();
+            match (&inner.metatable, &inner.value) {
+                (Some(metatable), _) => metatable.clone(),
+                (None, Some(ObjectValue::Table(table))) => return table.get(name).cloned(),
+                (None, _) => return None,
+            }
+        };
+        let inner = metatable.inner.lock().unwrap();
+        match &inner.value {
+            Some(ObjectValue::Table(table)) => table.get(name).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Compare two objects structurally, recursing into lists and tables.
+    ///
+    /// Unlike `==`, this is safe for cyclic lists and tables: a pair of objects
+    /// which is already being compared further up is assumed to be equal.
+    /// Primitives are compared as by `==`, so `NaN` is never equal to anything,
+    /// including itself. Functions are equal only if they are the same function.
+    #[must_use]
+    pub fn deep_eq(&self, other: &Self) -> bool {
+        deep_eq(self, other, &mut Vec::new())
+    }
+
+    /// Hash an object's contents, recursing into lists and tables.
+    ///
+    /// The hash doesn't depend on the run or platform, so it may be stored. Values which
+    /// are equal by [`Object::deep_eq`] hash equally, except for some cyclic values: an
+    /// object which is already being hashed further up contributes a fixed marker.
+    ///
+    /// Returns `None` if the object contains a function or a weak reference, which have
+    /// no contents to hash.
+    ///
+    /// ```
+    /// use scriptyscript::runtime::types::utilities::string;
+    ///
+    /// assert_eq!(string("hello").stable_hash(), Some(3_908_592_602_279_379_923));
+    /// ```
+    #[must_use]
+    pub fn stable_hash(&self) -> Option<u64> {
+        let mut hasher = StableHasher::new();
+        stable_hash(self, &mut hasher, &mut Vec::new())?;
+        Some(hasher.finish())
+    }
+
+    /// Returns the name of this object's type, as shown in error messages.
+    ///
+    /// # Panics
+    /// Panics if another thread panicked while holding the object's lock.
+    #[must_use]
+    pub fn type_name(&self) -> &'static str {
+        match &self.inner.lock().unwrap().value {
+            None => "nil",
+            Some(ObjectValue::Primitive(p)) => p.type_name(),
+            Some(ObjectValue::Function(_)) => "function",
+            Some(ObjectValue::Table(_)) => "table",
+            Some(ObjectValue::List(_)) => "list",
+            Some(ObjectValue::Weak(_)) => "weak",
+            Some(ObjectValue::StringBuilder(_)) => "string_builder",
         }
     }
 }
 
 impl Debug for Object {
+    /// Formats the object, recursing into lists and tables.
+    ///
+    /// Objects which contain themselves (directly or indirectly) are
+    /// printed as `<cycle>` where the back-reference occurs.
+    ///
+    /// The alternate form (`{:#?}`) spreads lists and tables over indented lines.
+    ///
+    /// Table entries are sorted by key, so the output is the same every time. Booleans
+    /// come first, then integers, then strings, each in their natural order:
+    ///
+    /// ```
+    /// use scriptyscript::runtime::{executor::execute_source, state::State};
+    ///
+    /// let mut state = State::new();
+    /// let source = "t = table(); t.b = 2; t[10] = 0; t.a = 1; t[true] = 3; t[-1] = 4; t;";
+    /// let table = execute_source(&mut state, source).unwrap().unwrap();
+    /// assert_eq!(
+    ///     format!("{table:?}"),
+    ///     r#"table: {true: 3, -1: 4, 10: 0, "a": 1, "b": 2}"#
+    /// );
+    /// ```
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &self.inner.lock().unwrap().value {
+        let visited = RefCell::new(Vec::new());
+        Repr {
+            object: self,
+            visited: &visited,
+        }
+        .fmt(f)
+    }
+}
+
+/// Helper for formatting an object while keeping track of the
+/// containers currently being formatted, so that cycles can be detected.
+struct Repr<'a> {
+    /// The object to format.
+    object: &'a Object,
+    /// Pointers to the inner values of the containers being formatted.
+    visited: &'a RefCell<Vec<*const Mutex<ObjectInner>>>,
+}
+
+impl<'a> Repr<'a> {
+    /// Create a formatter for an object contained in this one.
+    const fn child(&self, object: &'a Object) -> Self {
+        Self {
+            object,
+            visited: self.visited,
+        }
+    }
+}
+
+impl Debug for Repr<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ptr = Arc::as_ptr(&self.object.inner);
+        if self.visited.borrow().contains(&ptr) {
+            return write!(f, "<cycle>");
+        }
+
+        self.visited.borrow_mut().push(ptr);
+        let result = match &self.object.inner.lock().unwrap().value {
             Some(ObjectValue::Primitive(p)) => write!(f, "{}", p.to_string()),
             Some(ObjectValue::Function(function)) => write!(f, "{function}"),
-            Some(ObjectValue::Table(t)) => write!(f, "table: {t:?}"),
+            Some(ObjectValue::Table(t)) => {
+                write!(f, "table: ")?;
+                f.debug_map()
+                    .entries(
+                        t.sorted()
+                            .into_iter()
+                            .map(|(key, value)| (key, self.child(value))),
+                    )
+                    .finish()
+            }
+            Some(ObjectValue::List(l)) => f
+                .debug_list()
+                .entries(l.iter().map(|value| self.child(value)))
+                .finish(),
+            Some(ObjectValue::Weak(_)) => write!(f, "<weak>"),
+            Some(ObjectValue::StringBuilder(_)) => write!(f, "<string builder>"),
             None => write!(f, "nil"),
-        }
+        };
+        self.visited.borrow_mut().pop();
+        result
     }
 }
 
@@ -109,14 +302,147 @@ impl Eq for Object {}
 
 impl PartialEq for Object {
     fn eq(&self, other: &Self) -> bool {
-        match (
-            &self.inner.lock().unwrap().value,
-            &other.inner.lock().unwrap().value,
-        ) {
-            (Some(ObjectValue::Primitive(a)), Some(ObjectValue::Primitive(b))) => a == b,
-            (Some(ObjectValue::Table(a)), Some(ObjectValue::Table(b))) => a == b,
-            (Some(ObjectValue::Function(a)), Some(ObjectValue::Function(b))) => a == b,
-            _ => false,
+        if self.ptr_eq(other) {
+            // Locking the same mutex twice would deadlock, so compare the value to itself.
+            // This is not always true, e.g. for NaN.
+            let value = &self.inner.lock().unwrap().value;
+            return values_equal(value.as_ref(), value.as_ref());
+        }
+        values_equal(
+            self.inner.lock().unwrap().value.as_ref(),
+            other.inner.lock().unwrap().value.as_ref(),
+        )
+    }
+}
+
+/// Pair of objects currently being compared by [`deep_eq`].
+type ComparedPair = (*const Mutex<ObjectInner>, *const Mutex<ObjectInner>);
+
+/// Implementation of [`Object::deep_eq`], tracking the pairs of objects being compared.
+fn deep_eq(a: &Object, b: &Object, comparing: &mut Vec<ComparedPair>) -> bool {
+    let pair = (Arc::as_ptr(&a.inner), Arc::as_ptr(&b.inner));
+    if comparing.contains(&pair) {
+        return true;
+    }
+
+    // Values are cloned so that no lock is held while recursing.
+    let a_value = a.inner.lock().unwrap().value.clone();
+    let b_value = b.inner.lock().unwrap().value.clone();
+
+    comparing.push(pair);
+    let result = match (&a_value, &b_value) {
+        (None, None) => true,
+        (Some(ObjectValue::Primitive(a)), Some(ObjectValue::Primitive(b))) => a == b,
+        (Some(ObjectValue::Function(a)), Some(ObjectValue::Function(b))) => Arc::ptr_eq(a, b),
+        (Some(ObjectValue::Weak(a)), Some(ObjectValue::Weak(b))) => a.ptr_eq(b),
+        (Some(ObjectValue::StringBuilder(a)), Some(ObjectValue::StringBuilder(b))) => a == b,
+        (Some(ObjectValue::List(a)), Some(ObjectValue::List(b))) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| deep_eq(a, b, comparing))
+        }
+        (Some(ObjectValue::Table(a)), Some(ObjectValue::Table(b))) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key.clone()).is_some_and(|b| deep_eq(a, b, comparing)))
+        }
+        _ => false,
+    };
+    comparing.pop();
+    result
+}
+
+/// Implementation of [`Object::stable_hash`], tracking the objects being hashed.
+fn stable_hash(
+    object: &Object,
+    hasher: &mut StableHasher,
+    hashing: &mut Vec<*const Mutex<ObjectInner>>,
+) -> Option<()> {
+    let pointer = Arc::as_ptr(&object.inner);
+    if hashing.contains(&pointer) {
+        hasher.write(b"cycle");
+        return Some(());
+    }
+
+    // The value is cloned so that no lock is held while recursing.
+    let value = object.inner.lock().unwrap().value.clone();
+    hashing.push(pointer);
+    match &value {
+        None | Some(ObjectValue::Primitive(Primitive::Nil)) => hasher.write(b"nil"),
+        Some(ObjectValue::Primitive(Primitive::Boolean(x))) => {
+            hasher.write(b"bool");
+            hasher.write(&[u8::from(*x)]);
+        }
+        Some(ObjectValue::Primitive(Primitive::Integer(x))) => {
+            hasher.write(b"int");
+            hasher.write(&x.to_le_bytes());
+        }
+        Some(ObjectValue::Primitive(Primitive::Float(x))) => {
+            hasher.write(b"float");
+            // Zero and negative zero are equal, so they must hash equally
+            let x = if *x == 0.0 { 0.0 } else { *x };
+            hasher.write(&x.to_bits().to_le_bytes());
         }
+        Some(ObjectValue::Primitive(Primitive::String(x))) => {
+            hasher.write(b"string");
+            hasher.write_str(x);
+        }
+        Some(ObjectValue::List(list)) => {
+            hasher.write(b"list");
+            hasher.write(&(list.len() as u64).to_le_bytes());
+            for element in list {
+                stable_hash(element, hasher, hashing)?;
+            }
+        }
+        Some(ObjectValue::Table(table)) => {
+            hasher.write(b"table");
+            hasher.write(&(table.len() as u64).to_le_bytes());
+            // Keys are hashed in sorted order, so the insertion order doesn't matter
+            for (key, value) in table.sorted() {
+                hasher.write_str(&format!("{key:?}"));
+                stable_hash(value, hasher, hashing)?;
+            }
+        }
+        Some(ObjectValue::Function(_) | ObjectValue::Weak(_) | ObjectValue::StringBuilder(_)) => {
+            return None
+        }
+    }
+    hashing.pop();
+    Some(())
+}
+
+/// The 64-bit FNV-1a hash, which is simple and has no random seed.
+struct StableHasher(u64);
+
+impl StableHasher {
+    const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Write a string, preceded by its length so that adjacent strings can't run together.
+    fn write_str(&mut self, x: &str) {
+        self.write(&(x.len() as u64).to_le_bytes());
+        self.write(x.as_bytes());
+    }
+
+    const fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Compare the values of two objects.
+fn values_equal(a: Option<&ObjectValue>, b: Option<&ObjectValue>) -> bool {
+    match (a, b) {
+        (Some(ObjectValue::Primitive(a)), Some(ObjectValue::Primitive(b))) => a == b,
+        (Some(ObjectValue::Table(a)), Some(ObjectValue::Table(b))) => a == b,
+        (Some(ObjectValue::Function(a)), Some(ObjectValue::Function(b))) => a == b,
+        (Some(ObjectValue::List(a)), Some(ObjectValue::List(b))) => a == b,
+        (Some(ObjectValue::Weak(a)), Some(ObjectValue::Weak(b))) => a.ptr_eq(b),
+        (Some(ObjectValue::StringBuilder(a)), Some(ObjectValue::StringBuilder(b))) => a == b,
+        _ => false,
     }
 }
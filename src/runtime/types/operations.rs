@@ -3,206 +3,738 @@
 /// These functions are used when operators are encountered during execution,
 /// such as `+`, `/`, `==`, `or`, etc. They may also be called directly
 /// from elsewhere as needed.
-
 pub use arithmetic::*;
 pub use comparison::*;
+pub use dispatch::*;
+pub use indexing::*;
 pub use logical::*;
 
+/// Dispatching of operators to their implementation for the operand types
+pub mod dispatch {
+    use crate::{
+        compiler::{BinaryOperationKind, UnaryOperationKind},
+        runtime::{
+            error::RuntimeError,
+            executor::call_function,
+            state::State,
+            types::{
+                object::{Object, ObjectValue},
+                primitive::Primitive,
+                utilities::{boolean, nil},
+            },
+        },
+    };
+
+    /// Perform a binary operation on two objects, pushing the result onto the stack.
+    ///
+    /// The operation is resolved in order:
+    /// 1. If both operands are primitives, the primitive operation is attempted.
+    /// 2. The operation is dispatched to a dunder method (see [`dispatch_dunder`]).
+    /// 3. Otherwise, a [`RuntimeError::TypeError`] naming the operation and the
+    ///    operand types is returned.
+    ///
+    /// Stack: `[] -> [result]`
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operation is unsupported for the operands,
+    /// or if the dunder method fails.
+    pub fn dispatch_binary(
+        state: &mut State,
+        kind: BinaryOperationKind,
+        lhs: &Object,
+        rhs: &Object,
+        primitive_op: fn(Primitive, Primitive) -> Option<Primitive>,
+    ) -> Result<(), RuntimeError> {
+        if let (Some(a), Some(b)) = (lhs.as_primitive(), rhs.as_primitive()) {
+            if let Some(result) = primitive_op(a, b) {
+                state.push(&Object::new(Some(ObjectValue::Primitive(result)), None));
+                return Ok(());
+            }
+        }
+
+        if dispatch_dunder(state, kind, lhs, rhs)? {
+            return Ok(());
+        }
+
+        Err(RuntimeError::type_error(format!(
+            "unsupported operand types for {}: '{}' and '{}'",
+            kind.symbol(),
+            lhs.type_name(),
+            rhs.type_name()
+        )))
+    }
+
+    /// Create the error for a unary operation which doesn't support the type of its operand.
+    #[must_use]
+    pub fn unsupported_unary_operand(kind: UnaryOperationKind, operand: &Object) -> RuntimeError {
+        RuntimeError::type_error(format!(
+            "unsupported operand type for unary {}: '{}'",
+            kind.symbol(),
+            operand.type_name()
+        ))
+    }
+
+    /// Perform a binary operation on two objects using a dunder method, pushing the result
+    /// onto the stack.
+    ///
+    /// The dunder method for the operation (e.g. `__add__`) is looked up on the left
+    /// operand, and then the right operand. It is called with both operands.
+    ///
+    /// If neither operand has the method, comparisons are derived from `__lt__` and
+    /// `__eq__` where possible:
+    /// - `a > b` is `b < a`
+    /// - `a >= b` is `not (a < b)`
+    /// - `a <= b` is `not (b < a)`
+    /// - `a != b` is `not (a == b)`
+    ///
+    /// Stack: `[] -> [result]` if a method was found, otherwise `[] -> []`
+    ///
+    /// Returns whether a method was found.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the method fails, or if a derived comparison's
+    /// method does not return a boolean.
+    pub fn dispatch_dunder(
+        state: &mut State,
+        kind: BinaryOperationKind,
+        lhs: &Object,
+        rhs: &Object,
+    ) -> Result<bool, RuntimeError> {
+        if let Some(method) = find_dunder(&kind.dunder(), lhs, rhs) {
+            call_method(state, &method, &[lhs.clone(), rhs.clone()])?;
+            return Ok(true);
+        }
+
+        let (base, swap, negate) = match kind {
+            BinaryOperationKind::GreaterThan => (BinaryOperationKind::LessThan, true, false),
+            BinaryOperationKind::GreaterThanOrEqual => (BinaryOperationKind::LessThan, false, true),
+            BinaryOperationKind::LessThanOrEqual => (BinaryOperationKind::LessThan, true, true),
+            BinaryOperationKind::NotEqual => (BinaryOperationKind::Equal, false, true),
+            _ => return Ok(false),
+        };
+        let Some(method) = find_dunder(&base.dunder(), lhs, rhs) else {
+            return Ok(false);
+        };
+        let args = if swap {
+            [rhs.clone(), lhs.clone()]
+        } else {
+            [lhs.clone(), rhs.clone()]
+        };
+        call_method(state, &method, &args)?;
+        if negate {
+            let result = state.pop_or_err()?.as_bool().ok_or_else(|| {
+                RuntimeError::type_error(format!("{} must return a bool", base.dunder()))
+            })?;
+            state.push(&boolean(!result));
+        }
+        Ok(true)
+    }
+
+    /// Look up a dunder method on the left operand, and then the right operand.
+    fn find_dunder(name: &str, lhs: &Object, rhs: &Object) -> Option<Object> {
+        lhs.get_dunder(name).or_else(|| rhs.get_dunder(name))
+    }
+
+    /// Call a dunder method with the given arguments, pushing exactly one result.
+    ///
+    /// Extra return values are discarded, and `nil` is pushed if the method
+    /// returns nothing.
+    ///
+    /// Stack: `[] -> [result]`
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the method is not callable, or if the call fails.
+    pub fn call_method(
+        state: &mut State,
+        method: &Object,
+        args: &[Object],
+    ) -> Result<(), RuntimeError> {
+        let pushed = call_function(state, method, args)?;
+        let result = state.pop_n(pushed).pop();
+        state.push(&result.unwrap_or_else(nil));
+        Ok(())
+    }
+}
+
 /// Arithmetic operators for primitive types
 pub mod arithmetic {
-    use crate::runtime::{
-        state::State,
-        types::{
-            object::{Object, ObjectValue},
-            primitive::Primitive,
-            utilities::{float, int, nil},
+    use crate::{
+        compiler::{BinaryOperationKind, UnaryOperationKind},
+        runtime::{
+            error::RuntimeError,
+            state::State,
+            types::{
+                object::Object,
+                operations::{dispatch_binary, unsupported_unary_operand},
+                primitive::{to_float, Primitive},
+                utilities::{float, int},
+            },
         },
     };
 
-    pub fn add(state: &mut State, lhs: &Object, rhs: &Object) {
-        binary_arithmetic(state, lhs, rhs, std::ops::Add::add);
+    /// Add two numbers, or concatenate two strings.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and neither has `__add__`,
+    /// or if the method fails.
+    pub fn add(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(
+            state,
+            BinaryOperationKind::Add,
+            lhs,
+            rhs,
+            std::ops::Add::add,
+        )
     }
 
-    pub fn subtract(state: &mut State, lhs: &Object, rhs: &Object) {
-        binary_arithmetic(state, lhs, rhs, std::ops::Sub::sub);
+    /// Subtract one number from another.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and neither has `__sub__`,
+    /// or if the method fails.
+    pub fn subtract(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(
+            state,
+            BinaryOperationKind::Subtract,
+            lhs,
+            rhs,
+            std::ops::Sub::sub,
+        )
     }
 
-    pub fn multiply(state: &mut State, lhs: &Object, rhs: &Object) {
-        binary_arithmetic(state, lhs, rhs, std::ops::Mul::mul);
+    /// Multiply two numbers.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and neither has `__mul__`,
+    /// or if the method fails.
+    pub fn multiply(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(
+            state,
+            BinaryOperationKind::Multiply,
+            lhs,
+            rhs,
+            std::ops::Mul::mul,
+        )
     }
 
-    pub fn divide(state: &mut State, lhs: &Object, rhs: &Object) {
-        binary_arithmetic(state, lhs, rhs, std::ops::Div::div);
+    /// Divide two numbers, which always gives a float.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and neither has `__div__`,
+    /// or if the method fails.
+    pub fn divide(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(
+            state,
+            BinaryOperationKind::Divide,
+            lhs,
+            rhs,
+            std::ops::Div::div,
+        )
     }
 
-    pub fn remainder(state: &mut State, lhs: &Object, rhs: &Object) {
-        binary_arithmetic(state, lhs, rhs, std::ops::Rem::rem);
+    /// Floor divide two objects, see [`Primitive::floor_divide`].
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] for integer division by zero, or if the operation is
+    /// unsupported for the operands.
+    pub fn floor_divide(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        check_divisor(BinaryOperationKind::FloorDivide, lhs, rhs)?;
+        dispatch_binary(
+            state,
+            BinaryOperationKind::FloorDivide,
+            lhs,
+            rhs,
+            Primitive::floor_divide,
+        )
     }
 
-    fn binary_arithmetic(
-        state: &mut State,
+    /// Compute the remainder of a division, which takes the sign of the dividend.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] for an integer remainder by zero, or if the operation is
+    /// unsupported for the operands.
+    pub fn remainder(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        check_divisor(BinaryOperationKind::Remainder, lhs, rhs)?;
+        dispatch_binary(
+            state,
+            BinaryOperationKind::Remainder,
+            lhs,
+            rhs,
+            std::ops::Rem::rem,
+        )
+    }
+
+    /// Compute the floored modulo of a division, which takes the sign of the divisor.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] for an integer modulo by zero, or if the operation is
+    /// unsupported for the operands.
+    pub fn modulo(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        check_divisor(BinaryOperationKind::Modulo, lhs, rhs)?;
+        dispatch_binary(
+            state,
+            BinaryOperationKind::Modulo,
+            lhs,
+            rhs,
+            Primitive::modulo,
+        )
+    }
+
+    /// Check that an integer isn't divided by zero, which has no integer result (or remainder).
+    ///
+    /// Floats divided by zero follow IEEE 754 instead, giving an infinity or `NaN`.
+    fn check_divisor(
+        kind: BinaryOperationKind,
         lhs: &Object,
         rhs: &Object,
-        primitive_op: fn(Primitive, Primitive) -> Option<Primitive>,
-    ) {
+    ) -> Result<(), RuntimeError> {
         match (lhs.as_primitive(), rhs.as_primitive()) {
-            (Some(a), Some(b)) => {
-                let result = if let Some(result) = primitive_op(a, b) {
-                    Object::new(Some(ObjectValue::Primitive(result)), None)
-                } else {
-                    nil()
-                };
-                state.push(&result);
+            (Some(Primitive::Integer(_)), Some(Primitive::Integer(0))) => {
+                Err(RuntimeError::value_error(format!(
+                    "integer division by zero in '{}'",
+                    kind.symbol()
+                )))
             }
-            _ => todo!(),
+            _ => Ok(()),
         }
     }
 
-    pub fn negate(state: &mut State, obj: &Object) {
+    /// Apply unary `+` to a number, which leaves it unchanged.
+    ///
+    /// Non-numbers are rejected so that `+x` can be used to assert that `x` is a number.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operand is not a number.
+    pub fn plus(state: &mut State, obj: &Object) -> Result<(), RuntimeError> {
         match obj.as_primitive() {
-            Some(Primitive::Integer(i)) => state.push(&int(-i)),
-            Some(Primitive::Float(f)) => state.push(&float(-f)),
-            _ => state.push(&nil()),
+            Some(Primitive::Integer(_) | Primitive::Float(_)) => {
+                state.push(obj);
+                Ok(())
+            }
+            _ => Err(unsupported_unary_operand(UnaryOperationKind::Plus, obj)),
         }
     }
+
+    /// Apply unary `-` to a number.
+    ///
+    /// Negating `int_min` overflows an integer, so it gives a float instead.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operand is not a number.
+    pub fn negate(state: &mut State, obj: &Object) -> Result<(), RuntimeError> {
+        let result = match obj.as_primitive() {
+            Some(Primitive::Integer(i)) => i.checked_neg().map_or_else(|| float(-to_float(i)), int),
+            Some(Primitive::Float(f)) => float(-f),
+            _ => return Err(unsupported_unary_operand(UnaryOperationKind::Negate, obj)),
+        };
+        state.push(&result);
+        Ok(())
+    }
 }
 
 /// Comparison operators for primitive types
 pub mod comparison {
+    use crate::{
+        compiler::BinaryOperationKind,
+        runtime::{
+            error::RuntimeError,
+            state::State,
+            types::{
+                object::Object,
+                operations::{dispatch_binary, dispatch_dunder},
+                primitive::{to_float, Primitive},
+                utilities::boolean,
+            },
+        },
+    };
+
+    /// Compare two objects for equality using `__eq__` if either has it,
+    /// or by value otherwise.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if `__eq__` fails.
+    pub fn equals(state: &mut State, a: &Object, b: &Object) -> Result<(), RuntimeError> {
+        if !dispatch_dunder(state, BinaryOperationKind::Equal, a, b)? {
+            state.push(&boolean(a == b));
+        }
+        Ok(())
+    }
+
+    /// Compare two objects for inequality using `__ne__` or `__eq__` if either has them,
+    /// or by value otherwise.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if `__ne__` or `__eq__` fails, or if `__eq__` does not
+    /// return a boolean.
+    pub fn not_equals(state: &mut State, a: &Object, b: &Object) -> Result<(), RuntimeError> {
+        if !dispatch_dunder(state, BinaryOperationKind::NotEqual, a, b)? {
+            state.push(&boolean(a != b));
+        }
+        Ok(())
+    }
+
+    /// Compare two numbers with `>`, or call `__gt__`.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and the comparison can't
+    /// be made with a dunder method, or if the method fails.
+    pub fn greater_than(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(state, BinaryOperationKind::GreaterThan, lhs, rhs, |a, b| {
+            compare_numbers(a, b, i64::gt, f64::gt)
+        })
+    }
+
+    /// Compare two numbers with `<`, or call `__lt__`.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and the comparison can't
+    /// be made with a dunder method, or if the method fails.
+    pub fn less_than(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(state, BinaryOperationKind::LessThan, lhs, rhs, |a, b| {
+            compare_numbers(a, b, i64::lt, f64::lt)
+        })
+    }
+
+    /// Compare two numbers with `>=`, or call `__ge__`.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and the comparison can't
+    /// be made with a dunder method, or if the method fails.
+    pub fn greater_than_or_equal(
+        state: &mut State,
+        lhs: &Object,
+        rhs: &Object,
+    ) -> Result<(), RuntimeError> {
+        dispatch_binary(
+            state,
+            BinaryOperationKind::GreaterThanOrEqual,
+            lhs,
+            rhs,
+            |a, b| compare_numbers(a, b, i64::ge, f64::ge),
+        )
+    }
+
+    /// Compare two numbers with `<=`, or call `__le__`.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not numbers and the comparison can't
+    /// be made with a dunder method, or if the method fails.
+    pub fn less_than_or_equal(
+        state: &mut State,
+        lhs: &Object,
+        rhs: &Object,
+    ) -> Result<(), RuntimeError> {
+        dispatch_binary(
+            state,
+            BinaryOperationKind::LessThanOrEqual,
+            lhs,
+            rhs,
+            |a, b| compare_numbers(a, b, i64::le, f64::le),
+        )
+    }
+
+    /// Compare two numeric primitives. Mixed integer and float operands are compared as floats.
+    ///
+    /// Returns `None` if either primitive is not a number.
+    fn compare_numbers(
+        lhs: Primitive,
+        rhs: Primitive,
+        int_op: fn(&i64, &i64) -> bool,
+        float_op: fn(&f64, &f64) -> bool,
+    ) -> Option<Primitive> {
+        let result = match (lhs, rhs) {
+            (Primitive::Integer(lhs), Primitive::Integer(rhs)) => int_op(&lhs, &rhs),
+            (Primitive::Integer(lhs), Primitive::Float(rhs)) => float_op(&to_float(lhs), &rhs),
+            (Primitive::Float(lhs), Primitive::Integer(rhs)) => float_op(&lhs, &to_float(rhs)),
+            (Primitive::Float(lhs), Primitive::Float(rhs)) => float_op(&lhs, &rhs),
+            _ => return None,
+        };
+        Some(Primitive::Boolean(result))
+    }
+}
+
+/// Index operators for lists, strings, and tables
+pub mod indexing {
+    use super::call_method;
     use crate::runtime::{
+        error::RuntimeError,
         state::State,
         types::{
             object::{Object, ObjectValue},
             primitive::Primitive,
-            utilities::boolean,
+            table::Key,
+            utilities::{nil, string},
         },
     };
 
-    pub fn equals(state: &mut State, a: &Object, b: &Object) {
-        let a = a.inner.lock().unwrap();
-        let b = b.inner.lock().unwrap();
-        match (&a.value, &b.value) {
-            (Some(ObjectValue::Primitive(a)), Some(ObjectValue::Primitive(b))) => {
-                state.push(&boolean(a == b))
+    /// Index a list, string, or table, pushing the element onto the stack.
+    ///
+    /// Strings are indexed by character rather than by byte, and each element is
+    /// a string holding a single character. Tables are indexed as with [`get_key`].
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the object can't be indexed, or if the index is not
+    /// an integer within bounds.
+    ///
+    /// # Panics
+    /// Panics if the object's lock is poisoned.
+    pub fn get_index(state: &mut State, obj: &Object, index: &Object) -> Result<(), RuntimeError> {
+        // The index is converted up front, since it may be the same object as `obj`.
+        let position = expect_index(index);
+        let result = match &obj.inner.lock().unwrap().value {
+            Some(ObjectValue::List(l)) => Some(l[normalize_index(position?, l.len())?].clone()),
+            Some(ObjectValue::Primitive(Primitive::String(s))) => {
+                let i = normalize_index(position?, s.chars().count())?;
+                Some(string(s.chars().nth(i).unwrap().to_string()))
             }
-            (Some(ObjectValue::Table(a)), Some(ObjectValue::Table(b))) => {
-                state.push(&boolean(a == b))
+            // The lock must be released first, since looking up a key may call a method
+            Some(ObjectValue::Table(_)) => None,
+            _ => {
+                return Err(RuntimeError::type_error(
+                    "cannot index non-container object",
+                ))
             }
-            (Some(ObjectValue::Function(a)), Some(ObjectValue::Function(b))) => {
-                state.push(&boolean(a == b))
-            }
-            _ => state.push(&boolean(false)),
+        };
+        match result {
+            Some(result) => state.push(&result),
+            None => get_key(state, obj, index)?,
         }
+        Ok(())
     }
 
-    pub fn not_equals(state: &mut State, a: &Object, b: &Object) {
-        let a = a.inner.lock().unwrap();
-        let b = b.inner.lock().unwrap();
-        match (&a.value, &b.value) {
-            (Some(ObjectValue::Primitive(a)), Some(ObjectValue::Primitive(b))) => {
-                state.push(&boolean(a != b))
-            }
-            (Some(ObjectValue::Table(a)), Some(ObjectValue::Table(b))) => {
-                state.push(&boolean(a != b))
-            }
-            (Some(ObjectValue::Function(a)), Some(ObjectValue::Function(b))) => {
-                state.push(&boolean(a != b))
-            }
-            _ => state.push(&boolean(true)),
+    /// Look up a key of a table, pushing its value onto the stack.
+    ///
+    /// A key which is present gives its value, even if that value is nil. A key which
+    /// is absent is passed to the table's `__missing__` dunder method if it has one,
+    /// which receives the table and the key and gives the value. Otherwise it is nil.
+    ///
+    /// Stack: `[] -> [value]`
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the key can't be a table key, or if `__missing__` fails.
+    ///
+    /// # Panics
+    /// Panics if the table's lock is poisoned.
+    pub fn get_key(state: &mut State, table: &Object, key: &Object) -> Result<(), RuntimeError> {
+        let key_value = Key::try_from(key)?;
+        let value = match &table.inner.lock().unwrap().value {
+            Some(ObjectValue::Table(t)) => t.get(key_value).cloned(),
+            _ => None,
+        };
+        if let Some(value) = value {
+            state.push(&value);
+            return Ok(());
+        }
+        if let Some(method) = table.get_dunder("__missing__") {
+            return call_method(state, &method, &[table.clone(), key.clone()]);
         }
+        state.push(&nil());
+        Ok(())
     }
 
-    pub fn greater_than(state: &mut State, lhs: &Object, rhs: &Object) {
-        match (lhs.as_primitive(), rhs.as_primitive()) {
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs > rhs))
+    /// Assign an element of a list or table.
+    ///
+    /// Strings are values, so they can't be assigned by index. Every alias of a string
+    /// would see the change otherwise.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the object is not a list or table, if a list index
+    /// is out of bounds, or if the table is frozen.
+    ///
+    /// # Panics
+    /// Panics if another thread panicked while holding the object's lock.
+    pub fn set_index(obj: &Object, index: &Object, value: Object) -> Result<(), RuntimeError> {
+        // The index is converted up front, since it may be the same object as `obj`.
+        let (position, key) = (expect_index(index), Key::try_from(index));
+        match &mut obj.inner.lock().unwrap().value {
+            Some(ObjectValue::List(l)) => {
+                let i = normalize_index(position?, l.len())?;
+                l[i] = value;
             }
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs as f64 > rhs))
+            Some(ObjectValue::Table(t)) if t.is_frozen() => {
+                return Err(RuntimeError::FrozenTable(key?.to_string()));
             }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs > rhs as f64))
+            Some(ObjectValue::Table(t)) => t.set(key?, value),
+            Some(ObjectValue::Primitive(Primitive::String(_))) => {
+                return Err(RuntimeError::type_error(
+                    "cannot assign to an index of a string, strings are immutable",
+                ))
             }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs > rhs))
+            _ => {
+                return Err(RuntimeError::type_error(
+                    "cannot index non-container object",
+                ))
             }
-            _ => todo!("error handling"),
         }
+        Ok(())
     }
 
-    pub fn less_than(state: &mut State, lhs: &Object, rhs: &Object) {
-        match (lhs.as_primitive(), rhs.as_primitive()) {
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs < rhs))
-            }
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean((lhs as f64) < rhs))
-            }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs < rhs as f64))
-            }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs < rhs))
-            }
-            _ => todo!("error handling"),
+    /// Get the integer value of an index object.
+    fn expect_index(index: &Object) -> Result<i64, RuntimeError> {
+        match index.as_primitive() {
+            Some(Primitive::Integer(i)) => Ok(i),
+            _ => Err(RuntimeError::type_error("expected integer index")),
         }
     }
 
-    pub fn greater_than_or_equal(state: &mut State, lhs: &Object, rhs: &Object) {
-        match (lhs.as_primitive(), rhs.as_primitive()) {
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs >= rhs))
-            }
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs as f64 >= rhs))
-            }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs >= rhs as f64))
-            }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs >= rhs))
-            }
-            _ => todo!("error handling"),
-        }
+    /// Convert a possibly-negative index into an offset into a container of the given length.
+    ///
+    /// Negative indices count from the end, so `-1` refers to the last element.
+    fn normalize_index(index: i64, len: usize) -> Result<usize, RuntimeError> {
+        let normalized = if index < 0 {
+            usize::try_from(index.unsigned_abs())
+                .ok()
+                .and_then(|i| len.checked_sub(i))
+        } else {
+            usize::try_from(index).ok().filter(|&i| i < len)
+        };
+        normalized.ok_or(RuntimeError::IndexOutOfRange { index, len })
     }
+}
 
-    pub fn less_than_or_equal(state: &mut State, lhs: &Object, rhs: &Object) {
-        match (lhs.as_primitive(), rhs.as_primitive()) {
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs <= rhs))
+/// Logical operators for primitive types
+pub mod logical {
+    use crate::{
+        compiler::{BinaryOperationKind, UnaryOperationKind},
+        runtime::{
+            error::RuntimeError,
+            state::State,
+            types::{
+                object::Object,
+                operations::{dispatch_binary, unsupported_unary_operand},
+                primitive::Primitive,
+                utilities::boolean,
+            },
+        },
+    };
+
+    /// Logical and of two booleans, or call `__and__`.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not booleans and neither has `__and__`,
+    /// or if the method fails.
+    pub fn and(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(state, BinaryOperationKind::And, lhs, rhs, |a, b| {
+            match (a, b) {
+                (Primitive::Boolean(a), Primitive::Boolean(b)) => Some(Primitive::Boolean(a && b)),
+                _ => None,
             }
-            (Some(Primitive::Integer(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs as f64 <= rhs))
+        })
+    }
+
+    /// Logical or of two booleans, or call `__or__`.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are not booleans and neither has `__or__`,
+    /// or if the method fails.
+    pub fn or(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(state, BinaryOperationKind::Or, lhs, rhs, |a, b| {
+            match (a, b) {
+                (Primitive::Boolean(a), Primitive::Boolean(b)) => Some(Primitive::Boolean(a || b)),
+                _ => None,
             }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Integer(rhs))) => {
-                state.push(&boolean(lhs <= rhs as f64))
+        })
+    }
+
+    /// Exclusive or, which is logical for booleans and bitwise for integers.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operands are neither both booleans nor both
+    /// integers and neither has `__xor__`, or if the method fails.
+    pub fn xor(state: &mut State, lhs: &Object, rhs: &Object) -> Result<(), RuntimeError> {
+        dispatch_binary(state, BinaryOperationKind::Xor, lhs, rhs, |a, b| {
+            match (a, b) {
+                (Primitive::Boolean(a), Primitive::Boolean(b)) => Some(Primitive::Boolean(a ^ b)),
+                (Primitive::Integer(a), Primitive::Integer(b)) => Some(Primitive::Integer(a ^ b)),
+                _ => None,
             }
-            (Some(Primitive::Float(lhs)), Some(Primitive::Float(rhs))) => {
-                state.push(&boolean(lhs <= rhs))
+        })
+    }
+
+    /// Logical not of a boolean.
+    ///
+    /// # Errors
+    /// Returns a [`RuntimeError`] if the operand is not a boolean.
+    pub fn not(state: &mut State, obj: &Object) -> Result<(), RuntimeError> {
+        match obj.as_primitive() {
+            Some(Primitive::Boolean(x)) => {
+                state.push(&boolean(!x));
+                Ok(())
             }
-            _ => todo!("error handling"),
+            _ => Err(unsupported_unary_operand(UnaryOperationKind::Not, obj)),
         }
     }
 }
 
-/// Logical operators for primitive types
-pub mod logical {
-    use crate::runtime::{
-        state::State,
-        types::{object::Object, utilities::boolean},
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::compile,
+        runtime::{error::RuntimeError, state::State, types::primitive::Primitive},
     };
 
-    pub fn and(state: &mut State, lhs: &Object, rhs: &Object) {
-        match (lhs.as_bool(), rhs.as_bool()) {
-            (Some(a), Some(b)) => state.push(&boolean(a && b)),
-            _ => todo!("error handling"),
-        }
+    /// Run a script, returning the value of its top-level `return`.
+    fn run(source: &str) -> Result<Option<Primitive>, RuntimeError> {
+        let result = State::new().run(&compile(source).unwrap())?;
+        Ok(result.and_then(|x| x.as_primitive()))
+    }
+
+    #[test]
+    fn floor_divide() {
+        assert_eq!(run("return 5 / 2;"), Ok(Some(Primitive::Float(2.5))));
+        assert_eq!(run("return 5 // 2;"), Ok(Some(Primitive::Integer(2))));
+        assert_eq!(run("return -5 // 2;"), Ok(Some(Primitive::Integer(-3))));
+    }
+
+    #[test]
+    fn floor_divide_by_zero_is_an_error() {
+        assert!(matches!(
+            run("return 5 // 0;"),
+            Err(RuntimeError::ValueError(_))
+        ));
+        assert_eq!(
+            run("return 5.0 // 0;"),
+            Ok(Some(Primitive::Float(f64::INFINITY)))
+        );
+    }
+
+    #[test]
+    fn floor_divide_overflow_gives_float() {
+        assert_eq!(
+            run("return math.int_min // -1;"),
+            Ok(Some(Primitive::Float(2f64.powi(63))))
+        );
     }
 
-    pub fn or(state: &mut State, lhs: &Object, rhs: &Object) {
-        match (lhs.as_bool(), rhs.as_bool()) {
-            (Some(a), Some(b)) => state.push(&boolean(a || b)),
-            _ => todo!("error handling"),
+    #[test]
+    fn remainder_and_modulo_by_zero_are_errors() {
+        for source in [
+            "return 5 % 0;",
+            "return 5 mod 0;",
+            "return math.int_min % 0;",
+        ] {
+            assert!(matches!(run(source), Err(RuntimeError::ValueError(_))));
         }
+        assert!(matches!(run("return 5.0 % 0;"), Ok(Some(Primitive::Float(x))) if x.is_nan()));
+    }
+
+    #[test]
+    fn remainder_and_modulo_of_int_min_by_minus_one() {
+        assert_eq!(
+            run("return math.int_min % -1;"),
+            Ok(Some(Primitive::Integer(0)))
+        );
+        assert_eq!(
+            run("return math.int_min mod -1;"),
+            Ok(Some(Primitive::Integer(0)))
+        );
+    }
+
+    #[test]
+    fn div_is_an_identifier() {
+        assert_eq!(
+            run("div = 7; return div // 2;"),
+            Ok(Some(Primitive::Integer(3)))
+        );
     }
 }
@@ -9,6 +9,8 @@
 /// by the interpreter.
 ///
 /// The `Primitive` type implements traits for certain operators.
+/// Integer addition, subtraction, and multiplication which would overflow
+/// produce a float instead.
 #[derive(Debug, Clone)]
 pub enum Primitive {
     /// Represents the absence of a value.
@@ -27,6 +29,94 @@ pub enum Primitive {
     Boolean(bool),
 }
 
+impl Primitive {
+    /// Returns the name of this primitive's type, as shown in error messages.
+    #[must_use]
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Integer(_) => "int",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+            Self::Boolean(_) => "bool",
+        }
+    }
+
+    /// Floor division, which rounds the quotient towards negative infinity.
+    ///
+    /// The result is an integer if both operands are integers, so `7 // 2` is `3`
+    /// and `-7 // 2` is `-4`. Otherwise it is a float with no fractional part.
+    ///
+    /// This differs from `/`, which always gives a float:
+    ///
+    /// ```
+    /// use scriptyscript::runtime::types::primitive::Primitive;
+    ///
+    /// let (a, b) = (Primitive::Integer(5), Primitive::Integer(2));
+    /// assert_eq!(a.clone() / b.clone(), Some(Primitive::Float(2.5)));
+    /// assert_eq!(a.floor_divide(b), Some(Primitive::Integer(2)));
+    /// ```
+    ///
+    /// Returns `None` if either operand isn't a number, or for integer division by zero.
+    /// A quotient which overflows an integer (`int_min // -1`) gives a float.
+    #[must_use]
+    pub fn floor_divide(self, rhs: Self) -> Option<Self> {
+        match (self, rhs) {
+            (Self::Integer(_), Self::Integer(0)) => None,
+            (Self::Integer(a), Self::Integer(b)) => match (a.checked_div(b), a.checked_rem(b)) {
+                // Integer division truncates, which rounds up for a negative inexact quotient
+                (Some(quotient), Some(remainder)) if remainder != 0 && (a < 0) != (b < 0) => {
+                    Some(Self::Integer(quotient - 1))
+                }
+                (Some(quotient), Some(_)) => Some(Self::Integer(quotient)),
+                _ => Some(Self::Float((to_float(a) / to_float(b)).floor())),
+            },
+            (Self::Integer(a), Self::Float(b)) => Some(Self::Float((to_float(a) / b).floor())),
+            (Self::Float(a), Self::Integer(b)) => Some(Self::Float((a / to_float(b)).floor())),
+            (Self::Float(a), Self::Float(b)) => Some(Self::Float((a / b).floor())),
+            _ => None,
+        }
+    }
+
+    /// Floored modulo, the result of which takes the sign of the divisor.
+    ///
+    /// Unlike the remainder operator (`%`), whose result takes the sign of the dividend,
+    /// `-7 mod 3` is `2` rather than `-1`.
+    ///
+    /// Returns `None` if either operand isn't a number, or for integer modulo by zero.
+    #[must_use]
+    pub fn modulo(self, rhs: Self) -> Option<Self> {
+        /// Adjust a truncated remainder to take the sign of the divisor.
+        fn floored<T>(remainder: T, b: T) -> T
+        where
+            T: Copy + PartialOrd + Default + std::ops::Add<Output = T>,
+        {
+            let zero = T::default();
+            if remainder != zero && (remainder < zero) != (b < zero) {
+                remainder + b
+            } else {
+                remainder
+            }
+        }
+
+        match (self, rhs) {
+            (Self::Integer(_), Self::Integer(0)) => None,
+            (Self::Integer(a), Self::Integer(b)) => {
+                // Only `int_min mod -1` overflows, and any integer divides evenly by -1
+                let remainder = a.checked_rem(b).unwrap_or(0);
+                Some(Self::Integer(floored(remainder, b)))
+            }
+            (Self::Integer(a), Self::Float(b)) => Some(Self::Float(floored(to_float(a) % b, b))),
+            (Self::Float(a), Self::Integer(b)) => {
+                let b = to_float(b);
+                Some(Self::Float(floored(a % b, b)))
+            }
+            (Self::Float(a), Self::Float(b)) => Some(Self::Float(floored(a % b, b))),
+            _ => None,
+        }
+    }
+}
+
 impl Eq for Primitive {}
 impl PartialEq for Primitive {
     fn eq(&self, other: &Self) -> bool {
@@ -41,12 +131,24 @@ impl PartialEq for Primitive {
     }
 }
 
+/// Convert an integer to the nearest float, as when an integer operation overflows
+/// or an integer is compared with a float.
+///
+/// Integers beyond 2^53 lose their lowest bits, which is expected of a float.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) const fn to_float(x: i64) -> f64 {
+    x as f64
+}
+
 impl std::ops::Add for Primitive {
     type Output = Option<Self>;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Integer(a), Self::Integer(b)) => Some(Self::Integer(a + b)),
+            (Self::Integer(a), Self::Integer(b)) => Some(
+                a.checked_add(b)
+                    .map_or_else(|| Self::Float(to_float(a) + to_float(b)), Self::Integer),
+            ),
             (Self::Integer(a), Self::Float(b)) => Some(Self::Float(a as f64 + b)),
             (Self::Float(a), Self::Integer(b)) => Some(Self::Float(a + b as f64)),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a + b)),
@@ -61,7 +163,10 @@ impl std::ops::Sub for Primitive {
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Integer(a), Self::Integer(b)) => Some(Self::Integer(a - b)),
+            (Self::Integer(a), Self::Integer(b)) => Some(
+                a.checked_sub(b)
+                    .map_or_else(|| Self::Float(to_float(a) - to_float(b)), Self::Integer),
+            ),
             (Self::Integer(a), Self::Float(b)) => Some(Self::Float(a as f64 - b)),
             (Self::Float(a), Self::Integer(b)) => Some(Self::Float(a - b as f64)),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a - b)),
@@ -75,7 +180,10 @@ impl std::ops::Mul for Primitive {
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Integer(a), Self::Integer(b)) => Some(Self::Integer(a * b)),
+            (Self::Integer(a), Self::Integer(b)) => Some(
+                a.checked_mul(b)
+                    .map_or_else(|| Self::Float(to_float(a) * to_float(b)), Self::Integer),
+            ),
             (Self::Integer(a), Self::Float(b)) => Some(Self::Float(a as f64 * b)),
             (Self::Float(a), Self::Integer(b)) => Some(Self::Float(a * b as f64)),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a * b)),
@@ -89,7 +197,8 @@ impl std::ops::Div for Primitive {
 
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Integer(a), Self::Integer(b)) => Some(Self::Integer(a / b)),
+            // Dividing integers gives a float, use floor division (`//`) for an integer
+            (Self::Integer(a), Self::Integer(b)) => Some(Self::Float(to_float(a) / to_float(b))),
             (Self::Integer(a), Self::Float(b)) => Some(Self::Float(a as f64 / b)),
             (Self::Float(a), Self::Integer(b)) => Some(Self::Float(a / b as f64)),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a / b)),
@@ -103,7 +212,11 @@ impl std::ops::Rem for Primitive {
 
     fn rem(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::Integer(a), Self::Integer(b)) => Some(Self::Integer(a % b)),
+            (Self::Integer(_), Self::Integer(0)) => None,
+            // Only `int_min % -1` overflows, and any integer divides evenly by -1
+            (Self::Integer(a), Self::Integer(b)) => {
+                Some(Self::Integer(a.checked_rem(b).unwrap_or(0)))
+            }
             (Self::Integer(a), Self::Float(b)) => Some(Self::Float(a as f64 % b)),
             (Self::Float(a), Self::Integer(b)) => Some(Self::Float(a % b as f64)),
             (Self::Float(a), Self::Float(b)) => Some(Self::Float(a % b)),
@@ -123,3 +236,59 @@ impl ToString for Primitive {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Primitive::{self, Float, Integer};
+
+    #[test]
+    fn divide_gives_float() {
+        assert_eq!(Integer(5) / Integer(2), Some(Float(2.5)));
+    }
+
+    #[test]
+    fn floor_divide_rounds_down() {
+        assert_eq!(Integer(5).floor_divide(Integer(2)), Some(Integer(2)));
+        assert_eq!(Integer(-5).floor_divide(Integer(2)), Some(Integer(-3)));
+        assert_eq!(Integer(5).floor_divide(Integer(-2)), Some(Integer(-3)));
+        assert_eq!(Integer(-6).floor_divide(Integer(2)), Some(Integer(-3)));
+        assert_eq!(Float(-5.0).floor_divide(Integer(2)), Some(Float(-3.0)));
+    }
+
+    #[test]
+    fn floor_divide_by_zero_is_unsupported() {
+        assert_eq!(Integer(5).floor_divide(Integer(0)), None);
+    }
+
+    #[test]
+    fn floor_divide_overflow_gives_float() {
+        assert_eq!(
+            Integer(i64::MIN).floor_divide(Integer(-1)),
+            Some(Float(2f64.powi(63)))
+        );
+    }
+
+    #[test]
+    fn floor_divide_rejects_non_numbers() {
+        assert_eq!(Primitive::Nil.floor_divide(Integer(1)), None);
+    }
+
+    #[test]
+    fn modulo_takes_the_sign_of_the_divisor() {
+        assert_eq!(Integer(-7).modulo(Integer(3)), Some(Integer(2)));
+        assert_eq!(Integer(7).modulo(Integer(-3)), Some(Integer(-2)));
+        assert_eq!(Integer(6).modulo(Integer(-3)), Some(Integer(0)));
+        assert_eq!(Float(-7.5).modulo(Integer(2)), Some(Float(0.5)));
+    }
+
+    #[test]
+    fn modulo_by_zero_is_unsupported() {
+        assert_eq!(Integer(5).modulo(Integer(0)), None);
+        assert!(matches!(Float(5.0).modulo(Integer(0)), Some(Float(x)) if x.is_nan()));
+    }
+
+    #[test]
+    fn modulo_of_int_min_by_negative_one_is_zero() {
+        assert_eq!(Integer(i64::MIN).modulo(Integer(-1)), Some(Integer(0)));
+    }
+}
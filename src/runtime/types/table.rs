@@ -1,16 +1,106 @@
-//! Module containing a currently-unused [`Table`] type.
+//! Module containing the [`Table`] type.
 //!
 //! This type is planned to be used to add support for complex user-defined
 //! data structures, OOP, operator overloading, and more.
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+};
 
-use super::object::Object;
+use super::{object::Object, primitive::Primitive};
+use crate::runtime::error::RuntimeError;
 
-/// Currently unused. See the [module](self) documentation for more information.
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A key of a [`Table`].
+///
+/// Only primitives which can be hashed consistently with their equality may be
+/// used as keys. Floats are not allowed, since `NaN` is not equal to itself.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Boolean(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl Key {
+    /// Create a key from a primitive value.
+    ///
+    /// Returns `None` if the primitive cannot be used as a key.
+    #[must_use]
+    pub fn from_primitive(primitive: Primitive) -> Option<Self> {
+        match primitive {
+            Primitive::Boolean(x) => Some(Self::Boolean(x)),
+            Primitive::Integer(x) => Some(Self::Integer(x)),
+            Primitive::String(x) => Some(Self::String(x)),
+            Primitive::Nil | Primitive::Float(_) => None,
+        }
+    }
+}
+
+impl TryFrom<&Object> for Key {
+    type Error = RuntimeError;
+
+    fn try_from(object: &Object) -> Result<Self, Self::Error> {
+        object
+            .as_primitive()
+            .and_then(Self::from_primitive)
+            .ok_or_else(|| {
+                RuntimeError::type_error(format!("unhashable key type '{}'", object.type_name()))
+            })
+    }
+}
+
+impl From<&str> for Key {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+
+impl From<String> for Key {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<i64> for Key {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<bool> for Key {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Boolean(x) => write!(f, "{x}"),
+            Self::Integer(x) => write!(f, "{x}"),
+            Self::String(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+impl Debug for Key {
+    /// Keys are shown the way they would be written in source code.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::String(x) => write!(f, "{x:?}"),
+            _ => write!(f, "{self}"),
+        }
+    }
+}
+
+/// A mapping from [keys](Key) to objects.
+/// See the [module](self) documentation for more information.
+#[derive(Clone)]
 pub struct Table {
-    inner: HashMap<String, Object>,
+    inner: HashMap<Key, Object>,
+    /// Whether the keys of the table can no longer be assigned.
+    frozen: bool,
 }
 
 impl Table {
@@ -18,16 +108,81 @@ impl Table {
     pub fn new() -> Self {
         Self {
             inner: HashMap::new(),
+            frozen: false,
         }
     }
 
+    /// Prevent any further assignments to the table's keys.
+    ///
+    /// A frozen table cannot be unfrozen.
+    pub const fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Returns whether the table has been [frozen](Self::freeze).
+    #[must_use]
+    pub const fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
     #[must_use]
-    pub fn get(&self, key: &str) -> Option<&Object> {
-        self.inner.get(key)
+    pub fn get(&self, key: impl Into<Key>) -> Option<&Object> {
+        self.inner.get(&key.into())
+    }
+
+    pub fn set(&mut self, key: impl Into<Key>, value: Object) {
+        self.inner.insert(key.into(), value);
     }
 
-    pub fn set(&mut self, key: String, value: Object) {
-        self.inner.insert(key, value);
+    /// Remove a key from the table, returning its value if it was present.
+    pub fn remove(&mut self, key: impl Into<Key>) -> Option<Object> {
+        self.inner.remove(&key.into())
+    }
+
+    /// Returns whether the table has a value for the key, even if that value is nil.
+    #[must_use]
+    pub fn contains_key(&self, key: impl Into<Key>) -> bool {
+        self.inner.contains_key(&key.into())
+    }
+
+    /// Returns the number of key-value pairs in the table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns whether the table has no key-value pairs.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns an iterator over the key-value pairs of the table, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Object)> {
+        self.inner.iter()
+    }
+
+    /// Returns the key-value pairs of the table, ordered by key.
+    #[must_use]
+    pub fn sorted(&self) -> Vec<(&Key, &Object)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by_key(|(key, _)| *key);
+        entries
+    }
+}
+
+impl Eq for Table {}
+
+impl PartialEq for Table {
+    /// Tables are equal if they have the same contents, regardless of whether they are frozen.
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Debug for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.sorted()).finish()
     }
 }
 
@@ -1,21 +1,49 @@
 //! Utilities for creating objects from Rust types.
+//!
+//! Small integers and booleans are interned: creating one returns a clone of a shared
+//! object rather than allocating a new one. Interned objects must never be mutated in place,
+//! since the change would be seen by every use of the same value.
 
-use std::sync::Arc;
+use std::{ops::RangeInclusive, sync::Arc};
+
+use once_cell::sync::Lazy;
 
 use super::{
     function::{Function, ScriptedFunction, WrappedFunction},
     object::{Object, ObjectValue},
     primitive::Primitive,
+    table::Table,
 };
-use crate::runtime::bytecode::Bytecode;
+use crate::runtime::bytecode::{Bytecode, SlotNames};
+
+/// The range of integers which are interned.
+pub const INTERNED_INTS: RangeInclusive<i64> = -128..=255;
+
+/// The shared objects for interned integers, in order.
+static SMALL_INTS: Lazy<Vec<Object>> = Lazy::new(|| {
+    INTERNED_INTS
+        .map(|x| Object::new(Some(ObjectValue::Primitive(Primitive::Integer(x))), None))
+        .collect()
+});
+
+/// The shared objects for `false` and `true`, in that order.
+static BOOLEANS: Lazy<[Object; 2]> = Lazy::new(|| {
+    [false, true].map(|x| Object::new(Some(ObjectValue::Primitive(Primitive::Boolean(x))), None))
+});
 
 /// Creates an integer object from an integral value.
+///
+/// Integers in [`INTERNED_INTS`] are shared rather than allocated.
 #[must_use]
 pub fn int<T: num_traits::PrimInt>(value: T) -> Object {
+    let value = value.to_i64().unwrap();
+    if INTERNED_INTS.contains(&value) {
+        // The range starts below zero, so the offset is always in bounds
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        return SMALL_INTS[(value - INTERNED_INTS.start()) as usize].clone();
+    }
     Object::new(
-        Some(ObjectValue::Primitive(Primitive::Integer(
-            value.to_i64().unwrap(),
-        ))),
+        Some(ObjectValue::Primitive(Primitive::Integer(value))),
         None,
     )
 }
@@ -56,25 +84,40 @@ pub fn wrapped_function(func: WrappedFunction) -> Object {
     )
 }
 
-/// Creates a function object from the given bytecode.
+/// Creates a function object from the given bytecode, whose locals are stored in
+/// slots with the given names.
 #[must_use]
-pub fn scripted_function(bytecode: Bytecode) -> Object {
+pub fn scripted_function(bytecode: Bytecode, locals: Arc<SlotNames>) -> Object {
     Object::new(
         Some(ObjectValue::Function(Arc::new(Function::Scripted(
-            ScriptedFunction::new(bytecode),
+            ScriptedFunction::new(bytecode, locals),
         )))),
         None,
     )
 }
 
-/// Creates a table object.
+/// Creates an empty table object.
 #[must_use]
 pub fn table() -> Object {
-    todo!("tables are unsupported");
+    Object::new(Some(ObjectValue::Table(Table::new())), None)
+}
+
+/// Creates a list object from the given elements.
+#[must_use]
+pub fn list(elements: Vec<Object>) -> Object {
+    Object::new(Some(ObjectValue::List(elements)), None)
+}
+
+/// Creates a weak reference to an object, which doesn't keep the object alive.
+#[must_use]
+pub fn weak(object: &Object) -> Object {
+    Object::new(Some(ObjectValue::Weak(Arc::downgrade(&object.inner))), None)
 }
 
 /// Creates a boolean object from the given value.
+///
+/// Booleans are always shared rather than allocated.
 #[must_use]
 pub fn boolean(x: bool) -> Object {
-    Object::new(Some(ObjectValue::Primitive(Primitive::Boolean(x))), None)
+    BOOLEANS[usize::from(x)].clone()
 }
@@ -0,0 +1,243 @@
+//! Conversion of an [AST](crate::compiler::ast) into nested tables, which
+//! allows scripts to inspect parsed source code.
+//!
+//! Every node is converted into a table with a `type` field holding the name of the
+//! node's variant (e.g. `"BinaryOperation"`). The remaining fields hold the node's
+//! children, using the same names as the [`AstNode`] variant fields.
+//! Lists of nodes (such as the statements of a block) are converted into lists.
+
+use crate::{
+    compiler::{AstNode, Number},
+    runtime::types::{
+        object::{Object, ObjectValue},
+        table::Table,
+        utilities::{boolean, float, int, list, nil, string},
+    },
+};
+
+/// Convert an AST node (and all of its children) into a table.
+#[must_use]
+pub fn to_table(node: &AstNode) -> Object {
+    let (node_type, fields) = expression_fields(node);
+
+    let mut result = Table::new();
+    result.set("type", string(node_type));
+    for (key, value) in fields {
+        result.set(key, value);
+    }
+    Object::new(Some(ObjectValue::Table(result)), None)
+}
+
+/// The name of a node's variant, and the fields of its table other than `type`.
+type Fields = (&'static str, Vec<(&'static str, Object)>);
+
+/// Get the fields of an expression node, deferring to [`statement_fields`] for other nodes.
+fn expression_fields(node: &AstNode) -> Fields {
+    match node {
+        AstNode::Identifier(name) => ("Identifier", vec![("name", string(name))]),
+        AstNode::NumberLiteral(Number::Integer(x)) => ("NumberLiteral", vec![("value", int(*x))]),
+        AstNode::NumberLiteral(Number::Float(x)) => ("NumberLiteral", vec![("value", float(*x))]),
+        AstNode::NilLiteral => ("NilLiteral", vec![]),
+        AstNode::StringLiteral(x) => ("StringLiteral", vec![("value", string(x))]),
+        AstNode::BooleanLiteral(x) => ("BooleanLiteral", vec![("value", boolean(*x))]),
+        AstNode::ListLiteral(elements) => ("ListLiteral", vec![("elements", to_list(elements))]),
+        AstNode::UnaryOperation { kind, operand } => (
+            "UnaryOperation",
+            vec![
+                ("kind", string(format!("{kind:?}"))),
+                ("operand", to_table(operand)),
+            ],
+        ),
+        AstNode::BinaryOperation { kind, left, right } => (
+            "BinaryOperation",
+            vec![
+                ("kind", string(format!("{kind:?}"))),
+                ("left", to_table(left)),
+                ("right", to_table(right)),
+            ],
+        ),
+        AstNode::ComparisonChain { operands, kinds } => (
+            "ComparisonChain",
+            vec![
+                ("operands", to_list(operands)),
+                (
+                    "kinds",
+                    list(
+                        kinds
+                            .iter()
+                            .map(|kind| string(format!("{kind:?}")))
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        AstNode::Index { target, index } => (
+            "Index",
+            vec![("target", to_table(target)), ("index", to_table(index))],
+        ),
+        AstNode::Field { target, key } => (
+            "Field",
+            vec![("target", to_table(target)), ("key", string(key))],
+        ),
+        AstNode::FunctionCall { identifier, args } => (
+            "FunctionCall",
+            vec![("identifier", string(identifier)), ("args", to_list(args))],
+        ),
+        AstNode::Call { target, args } => (
+            "Call",
+            vec![("target", to_table(target)), ("args", to_list(args))],
+        ),
+        AstNode::FunctionDef { args, body } => (
+            "FunctionDef",
+            vec![
+                ("args", list(args.iter().map(string).collect())),
+                ("body", to_table(body)),
+            ],
+        ),
+        _ => statement_fields(node),
+    }
+}
+
+/// Get the fields of a statement node, deferring to [`control_flow_fields`] for loops and
+/// conditionals.
+fn statement_fields(node: &AstNode) -> Fields {
+    match node {
+        AstNode::Assignment { identifier, value } => (
+            "Assignment",
+            vec![
+                ("identifier", string(identifier)),
+                ("value", to_table(value)),
+            ],
+        ),
+        AstNode::IndexAssignment {
+            target,
+            index,
+            value,
+        } => (
+            "IndexAssignment",
+            vec![
+                ("target", to_table(target)),
+                ("index", to_table(index)),
+                ("value", to_table(value)),
+            ],
+        ),
+        AstNode::FieldAssignment { target, key, value } => (
+            "FieldAssignment",
+            vec![
+                ("target", to_table(target)),
+                ("key", string(key)),
+                ("value", to_table(value)),
+            ],
+        ),
+        AstNode::Increment { identifier } => {
+            ("Increment", vec![("identifier", string(identifier))])
+        }
+        AstNode::Decrement { identifier } => {
+            ("Decrement", vec![("identifier", string(identifier))])
+        }
+        AstNode::Block(statements) => ("Block", vec![("statements", to_list(statements))]),
+        AstNode::Do { body, value } => (
+            "Do",
+            vec![
+                ("body", to_table(body)),
+                ("value", to_optional_table(value.as_deref())),
+            ],
+        ),
+        AstNode::Break => ("Break", vec![]),
+        AstNode::Continue => ("Continue", vec![]),
+        AstNode::Return { value } => (
+            "Return",
+            vec![("value", to_optional_table(value.as_deref()))],
+        ),
+        _ => control_flow_fields(node),
+    }
+}
+
+/// Get the fields of a loop or conditional node.
+fn control_flow_fields(node: &AstNode) -> Fields {
+    match node {
+        AstNode::If {
+            condition,
+            body,
+            else_body,
+        } => (
+            "If",
+            vec![
+                ("condition", to_table(condition)),
+                ("body", to_table(body)),
+                ("else_body", to_optional_table(else_body.as_deref())),
+            ],
+        ),
+        AstNode::For {
+            initialization,
+            condition,
+            increment,
+            body,
+            else_body,
+        } => (
+            "For",
+            vec![
+                (
+                    "initialization",
+                    to_optional_table(initialization.as_deref()),
+                ),
+                ("condition", to_optional_table(condition.as_deref())),
+                ("increment", to_optional_table(increment.as_deref())),
+                ("body", to_table(body)),
+                ("else_body", to_optional_table(else_body.as_deref())),
+            ],
+        ),
+        AstNode::While {
+            condition,
+            body,
+            else_body,
+        } => (
+            "While",
+            vec![
+                ("condition", to_table(condition)),
+                ("body", to_table(body)),
+                ("else_body", to_optional_table(else_body.as_deref())),
+            ],
+        ),
+        AstNode::Loop { body } => ("Loop", vec![("body", to_table(body))]),
+        AstNode::Assert {
+            condition,
+            message,
+            source,
+        } => (
+            "Assert",
+            vec![
+                ("condition", to_table(condition)),
+                ("message", to_optional_table(message.as_deref())),
+                ("source", string(source)),
+            ],
+        ),
+        AstNode::Repeat { count, body } => (
+            "Repeat",
+            vec![("count", to_table(count)), ("body", to_table(body))],
+        ),
+        AstNode::With {
+            resource,
+            identifier,
+            body,
+        } => (
+            "With",
+            vec![
+                ("resource", to_table(resource)),
+                ("identifier", string(identifier)),
+                ("body", to_table(body)),
+            ],
+        ),
+        _ => unreachable!("not a control flow node"),
+    }
+}
+
+/// Convert a list of AST nodes into a list of tables.
+fn to_list(nodes: &[AstNode]) -> Object {
+    list(nodes.iter().map(to_table).collect())
+}
+
+/// Convert an optional AST node into a table, or nil if absent.
+fn to_optional_table(node: Option<&AstNode>) -> Object {
+    node.map_or_else(nil, to_table)
+}
@@ -1,147 +1,771 @@
 //! Contains the standard library for the `ScriptyScript` language.
 //!
 //! These functions may be bound to a [`State`] and called from within a script.
+//!
+//! Every builtin returns a [`RuntimeError`] when it is given the wrong number of
+//! arguments, or arguments of the wrong type or value, so the individual functions
+//! only document what they pop and push. They panic only if an object's lock was
+//! poisoned by an earlier panic.
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
 
-use std::io::Write;
-
-use crate::runtime::{
-    executor::execute_source,
-    state::State,
-    types::{
-        function::Function,
-        object::ObjectValue,
-        operations,
-        primitive::Primitive,
-        utilities::{float, int, nil, string, wrapped_function},
+use crate::{
+    compiler,
+    runtime::{
+        error::RuntimeError,
+        executor::{call_function, execute_source},
+        gc,
+        state::State,
+        types::{
+            object::{Object, ObjectValue},
+            operations,
+            primitive::Primitive,
+            table::{Key, Table},
+            utilities::{float, int, list, nil, string, table, weak, wrapped_function},
+        },
     },
 };
 
+pub mod ast_table;
+
+/// A group of related builtins, which can be registered on its own.
+///
+/// Embedders can use this to leave out builtins a script shouldn't have,
+/// e.g. to run untrusted scripts without access to files (see [`State::with_modules`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Module {
+    /// Printing, conversions, lists, tables, metatables, and running code (e.g. `print`, `len`).
+    Core,
+    /// Numeric functions and the `math` table (e.g. `abs`, `max`).
+    Math,
+    /// String manipulation and formatting (e.g. `split`, `format`).
+    String,
+    /// Reading input and files, including modules (e.g. `input`, `import`).
+    Io,
+    /// The process and its environment (e.g. `args`, `exit`, `clock`).
+    Os,
+}
+
+impl Module {
+    /// Every module, which together make up the whole standard library.
+    pub const ALL: [Self; 5] = [Self::Core, Self::Math, Self::String, Self::Io, Self::Os];
+}
+
+/// Register the whole standard library in the global frame of a state.
 pub fn register(state: &mut State) {
+    register_modules(state, &Module::ALL);
+}
+
+/// Register the builtins of the given modules in the global frame of a state.
+pub fn register_modules(state: &mut State, modules: &[Module]) {
+    for module in modules {
+        match module {
+            Module::Core => register_core(state),
+            Module::Math => register_math(state),
+            Module::String => register_string(state),
+            Module::Io => register_io(state),
+            Module::Os => register_os(state),
+        }
+    }
+}
+
+fn register_core(state: &mut State) {
     state.set_global("print", wrapped_function(print));
+    state.set_global("pprint", wrapped_function(pretty_print));
     state.set_global("string", wrapped_function(to_string));
-    state.set_global("max", wrapped_function(max));
-    state.set_global("min", wrapped_function(min));
     state.set_global("int", wrapped_function(to_int));
     state.set_global("float", wrapped_function(to_float));
+    state.set_global("tonumber", wrapped_function(to_number));
+    state.set_global("min_by", wrapped_function(min_by));
+    state.set_global("max_by", wrapped_function(max_by));
+    state.set_global("memoize", wrapped_function(memoize));
+    state.set_global("apply", wrapped_function(apply));
+    state.set_global("len", wrapped_function(len));
+    state.set_global("all", wrapped_function(all));
+    state.set_global("any", wrapped_function(any));
+    state.set_global("count", wrapped_function(count));
+    state.set_global("index_of", wrapped_function(index_of));
+    state.set_global("table", wrapped_function(new_table));
+    state.set_global("list", wrapped_function(new_list));
+    state.set_global("freeze", wrapped_function(freeze));
+    state.set_global("is_frozen", wrapped_function(is_frozen));
+    state.set_global("has", wrapped_function(has));
+    state.set_global("delete", wrapped_function(delete));
+    state.set_global("deepequal", wrapped_function(deep_equal));
+    state.set_global("is", wrapped_function(is));
+    state.set_global("hash", wrapped_function(hash));
+    state.set_global("assert", wrapped_function(assert));
+    state.set_global("setmetatable", wrapped_function(set_metatable));
+    state.set_global("getmetatable", wrapped_function(get_metatable));
+    state.set_global("weak", wrapped_function(new_weak));
+    state.set_global("deref", wrapped_function(deref));
+    state.set_global("debug", debug_functions());
+    state.set_global("locals", wrapped_function(locals));
+    state.set_global("exec", wrapped_function(exec));
+    state.set_global("parse", wrapped_function(parse));
+}
+
+fn register_math(state: &mut State) {
+    state.set_global("max", wrapped_function(max));
+    state.set_global("min", wrapped_function(min));
+    state.set_global("clamp", wrapped_function(clamp));
+    state.set_global("approx_eq", wrapped_function(approx_eq));
+    state.set_global("sum", wrapped_function(sum));
+    state.set_global("product", wrapped_function(product));
     state.set_global("round", wrapped_function(round));
+    state.set_global("floor", wrapped_function(floor));
     state.set_global("abs", wrapped_function(abs));
-    state.set_global("exec", wrapped_function(exec));
-    state.set_global("exit", wrapped_function(exit));
+    state.set_global("gcd", wrapped_function(gcd));
+    state.set_global("lcm", wrapped_function(lcm));
+    state.set_global("sat_add", wrapped_function(sat_add));
+    state.set_global("sat_sub", wrapped_function(sat_sub));
+    state.set_global("sat_mul", wrapped_function(sat_mul));
+    state.set_global("math", math_constants());
+}
+
+fn register_string(state: &mut State) {
+    state.set_global("split", wrapped_function(split));
+    state.set_global("lines", wrapped_function(lines));
+    state.set_global("shlex", wrapped_function(shlex));
+    state.set_global("chars", wrapped_function(chars));
+    state.set_global("bytes", wrapped_function(bytes));
+    state.set_global("from_bytes", wrapped_function(from_bytes));
+    state.set_global("base64_encode", wrapped_function(base64_encode));
+    state.set_global("base64_decode", wrapped_function(base64_decode));
+    state.set_global("trim", wrapped_function(trim));
+    state.set_global("trim_start", wrapped_function(trim_start));
+    state.set_global("trim_end", wrapped_function(trim_end));
+    state.set_global("repeat", wrapped_function(repeat));
+    state.set_global("pad_left", wrapped_function(pad_left));
+    state.set_global("pad_right", wrapped_function(pad_right));
+    state.set_global("format", wrapped_function(format));
+    state.set_global("concat", wrapped_function(concat));
+    state.set_global("group", wrapped_function(group));
+    state.set_global("numfmt", wrapped_function(numfmt));
+    state.set_global("string_builder", string_builder_functions());
+}
+
+/// Call a unary dunder method (e.g. `__abs__`) of an object, if it has one,
+/// pushing the result.
+///
+/// Returns whether the method was found.
+fn call_dunder(state: &mut State, object: &Object, name: &str) -> Result<bool, RuntimeError> {
+    match object.get_dunder(name) {
+        Some(method) => {
+            operations::call_method(state, &method, std::slice::from_ref(object))?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Create the frozen `math` table, which holds numeric constants.
+fn math_constants() -> Object {
+    let mut math = Table::new();
+    math.set("int_max", int(i64::MAX));
+    math.set("int_min", int(i64::MIN));
+    math.set("float_max", float(f64::MAX));
+    math.set("inf", float(f64::INFINITY));
+    math.set("nan", float(f64::NAN));
+    math.freeze();
+    Object::new(Some(ObjectValue::Table(math)), None)
+}
+
+/// Create the frozen `debug` table, which holds functions for introspecting the running script.
+fn debug_functions() -> Object {
+    let mut debug = Table::new();
+    debug.set("traceback", wrapped_function(traceback));
+    debug.freeze();
+    Object::new(Some(ObjectValue::Table(debug)), None)
+}
+
+/// Create the frozen `string_builder` table, which holds the functions for building strings.
+fn string_builder_functions() -> Object {
+    let mut builder = Table::new();
+    builder.set("new", wrapped_function(new_string_builder));
+    builder.set("append", wrapped_function(string_builder_append));
+    builder.set("build", wrapped_function(string_builder_build));
+    builder.freeze();
+    Object::new(Some(ObjectValue::Table(builder)), None)
+}
+
+/// Register the functions which interact with the process or the outside world,
+/// such as reading input or blocking the thread.
+fn register_io(state: &mut State) {
     state.set_global("input", wrapped_function(input));
+    state.set_global("flush", wrapped_function(flush));
+    state.set_global("read_line", wrapped_function(read_line));
+    state.set_global("read_all", wrapped_function(read_all));
+    state.set_global("dofile", wrapped_function(dofile));
+    state.set_global("import", wrapped_function(import));
+}
+
+fn register_os(state: &mut State) {
+    state.set_global("args", list(Vec::new()));
+    state.set_global("exit", wrapped_function(exit));
+    state.set_global("env", wrapped_function(env));
+    state.set_global("sleep", wrapped_function(sleep));
+    state.set_global("clock", wrapped_function(clock));
 }
 
 /// Convert an object to its string representation.
 ///
 /// Pops 1 argument, the object.
 /// Pushes 1 result, the string representation of the object.
-pub fn to_string(state: &mut State, n: usize) -> usize {
-    assert_eq!(n, 1);
-    let object = state.pop().unwrap();
-    let inner = object.inner();
-    let value = inner.lock().unwrap();
-    let value = value.value();
-    let result = match value {
-        Some(ObjectValue::Primitive(x)) => string(x.to_string()),
-        Some(ObjectValue::Function(x)) => match x.as_ref() {
-            Function::Scripted(x) => string(format!("scripted function: {:?}", x.bytecode())),
-            Function::Wrapped(_) => string("wrapped function"),
-        },
-        Some(ObjectValue::Table(_)) => {
-            todo!(); // need to invoke __str__
-        }
-        None => string("nil"),
+pub fn to_string(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+    let object = state.pop_or_err()?;
+    let result = match object.inner().lock().unwrap().value() {
+        Some(ObjectValue::Primitive(x)) => Some(x.to_string()),
+        Some(ObjectValue::Function(x)) => Some(x.to_string()),
+        // TODO: tables need to invoke __str__
+        Some(
+            ObjectValue::Table(_)
+            | ObjectValue::List(_)
+            | ObjectValue::Weak(_)
+            | ObjectValue::StringBuilder(_),
+        ) => None,
+        None => Some("nil".to_owned()),
     };
-    state.push(&result);
-    1
+    // Containers are formatted after releasing the lock, since they may contain themselves.
+    let result = result.unwrap_or_else(|| format!("{object:?}"));
+    state.push(&string(result));
+    Ok(1)
 }
 
 /// Print the string representation for one or more objects.
 ///
 /// Pops `n` arguments, the objects to print.
 /// Pushes no results.
-pub fn print(state: &mut State, n: usize) -> usize {
+pub fn print(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    let mut output = String::new();
     for _ in 0..n {
-        let pushed = to_string(state, 1);
-        assert_eq!(pushed, 1);
-        let primitive = state.pop().unwrap().as_primitive();
-        match primitive {
-            Some(Primitive::String(s)) => print!("{s}"),
-            _ => panic!("unsupported type"),
-        }
+        to_string(state, 1)?;
+        output.push_str(&state.pop_string()?);
     }
     // Add the final newline character
     if n != 0 {
-        println!();
+        output.push('\n');
     }
-    0
+    state.streams().write(&output)?;
+    Ok(0)
+}
+
+/// Print an object, with nested lists and tables spread over indented lines.
+///
+/// Like `print`, objects which contain themselves are printed as `<cycle>`
+/// where the back-reference occurs.
+///
+/// Pops 1 argument, the object to print.
+/// Pushes 0 results.
+pub fn pretty_print(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    state.streams().write(&format!("{object:#?}\n"))?;
+    Ok(0)
 }
 
 /// Compute the maximum of two or more numbers.
 ///
 /// Pops `n` arguments, the numbers to compare. Takes at least two args.
 /// Pushes 1 result, the maximum of the numbers.
-pub fn max(state: &mut State, n: usize) -> usize {
-    assert!(n >= 2);
+pub fn max(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..)?;
 
-    let mut max = state.pop().unwrap();
+    let mut max = state.pop_or_err()?;
     for _ in 1..n {
-        let current = state.pop().unwrap();
-        operations::greater_than(state, &current, &max);
+        let current = state.pop_or_err()?;
+        operations::greater_than(state, &current, &max)?;
 
-        match state.pop().unwrap().as_bool() {
+        match state.pop_or_err()?.as_bool() {
             Some(true) => max = current,
             Some(false) => (),
-            None => panic!("unsupported type"),
+            None => return Err(RuntimeError::type_error("unsupported type")),
         }
     }
     state.push(&max);
-    1
+    Ok(1)
 }
 
 /// Compute the minimum of two or more numbers.
 ///
 /// Pops `n` arguments, the numbers to compare. Takes at least two args.
 /// Pushes 1 result, the minimum of the numbers.
-pub fn min(state: &mut State, n: usize) -> usize {
-    assert!(n >= 2);
+pub fn min(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..)?;
 
-    let mut min = state.pop().unwrap();
+    let mut min = state.pop_or_err()?;
     for _ in 1..n {
-        let current = state.pop().unwrap();
-        operations::less_than(state, &current, &min);
+        let current = state.pop_or_err()?;
+        operations::less_than(state, &current, &min)?;
 
-        match state.pop().unwrap().as_bool() {
+        match state.pop_or_err()?.as_bool() {
             Some(true) => min = current,
             Some(false) => (),
-            None => panic!("unsupported type"),
+            None => return Err(RuntimeError::type_error("unsupported type")),
         }
     }
     state.push(&min);
-    1
+    Ok(1)
+}
+
+/// Find the element of a list with the smallest key, as computed by a key function.
+///
+/// Keys are compared with `<`. If several elements share the smallest key, the first is chosen.
+///
+/// Pops 2 arguments, the list and the key function.
+/// Pushes 1 result, the element with the smallest key, or nil for an empty list.
+pub fn min_by(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    select_by_key(state, n, operations::less_than)
+}
+
+/// Call a function with the elements of a list as its arguments.
+///
+/// This is useful for forwarding arguments, e.g. `apply(max, [3, 9, 2])` is `max(3, 9, 2)`.
+///
+/// Pops 2 arguments, the function and the list of arguments.
+/// Pushes the results of the call.
+pub fn apply(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let function = state.pop_or_err()?;
+    let args = list_elements(&state.pop_or_err()?)?;
+    call_function(state, &function, &args)
+}
+
+/// Find the element of a list with the largest key, as computed by a key function.
+///
+/// Keys are compared with `>`. If several elements share the largest key, the first is chosen.
+///
+/// Pops 2 arguments, the list and the key function.
+/// Pushes 1 result, the element with the largest key, or nil for an empty list.
+pub fn max_by(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    select_by_key(state, n, operations::greater_than)
+}
+
+/// Shared implementation of `min_by` and `max_by`, selecting the element whose key
+/// is preferred over every other key by a comparison.
+fn select_by_key(
+    state: &mut State,
+    n: usize,
+    prefer: fn(&mut State, &Object, &Object) -> Result<(), RuntimeError>,
+) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let elements = list_elements(&state.pop_or_err()?)?;
+    let key_function = state.pop_or_err()?;
+    let mut best: Option<(Object, Object)> = None;
+    for element in elements {
+        let key = call_with_element(state, &key_function, &element)?;
+        let preferred = match &best {
+            Some((_, best_key)) => {
+                prefer(state, &key, best_key)?;
+                state.pop_or_err()?.as_bool().ok_or_else(|| {
+                    RuntimeError::type_error("expected comparison to return a bool")
+                })?
+            }
+            None => true,
+        };
+        if preferred {
+            best = Some((element, key));
+        }
+    }
+    state.push(&best.map_or_else(nil, |(element, _)| element));
+    Ok(1)
+}
+
+/// Call a function on a single list element, returning its first result.
+fn call_with_element(
+    state: &mut State,
+    function: &Object,
+    element: &Object,
+) -> Result<Object, RuntimeError> {
+    let pushed = call_function(state, function, std::slice::from_ref(element))?;
+    state
+        .try_pop_n(pushed)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| RuntimeError::value_error("function returned no value"))
+}
+
+/// Wrap a function so that its results are cached, keyed by its arguments.
+///
+/// The wrapper is a frozen table holding the function and its cache, which is called
+/// through its `__call__` dunder method. Calling the wrapper with arguments it has seen
+/// before returns the cached results without calling the function again.
+///
+/// Pops 1 argument, the function.
+/// Pushes 1 result, the wrapper.
+pub fn memoize(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let function = state.pop_or_err()?;
+    if function.type_name() != "function" {
+        return Err(RuntimeError::type_error(format!(
+            "expected function, got '{}'",
+            function.type_name()
+        )));
+    }
+    let mut metatable = Table::new();
+    metatable.set("__call__", wrapped_function(call_memoized));
+    metatable.freeze();
+    let mut wrapper = Table::new();
+    wrapper.set("function", function);
+    wrapper.set("cache", table());
+    wrapper.freeze();
+    state.push(&Object::new(
+        Some(ObjectValue::Table(wrapper)),
+        Some(Object::new(Some(ObjectValue::Table(metatable)), None)),
+    ));
+    Ok(1)
+}
+
+/// Call a function wrapped by [`memoize`], using its cached results if there are any.
+///
+/// Pops 1 or more arguments, the wrapper followed by the arguments to the function,
+/// which must be usable as table keys.
+/// Pushes the results of the function.
+fn call_memoized(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..)?;
+
+    let wrapper = state.pop_or_err()?;
+    let args = state.try_pop_n(n - 1)?;
+    let (Some(function), Some(mut cache)) = (wrapper.get_key("function"), wrapper.get_key("cache"))
+    else {
+        return Err(RuntimeError::type_error("expected memoized function"));
+    };
+
+    let key = memo_key(&args)?;
+    if let Some(results) = cache.get_key(&key) {
+        let results = list_elements(&results)?;
+        state.push_all(&results);
+        return Ok(results.len());
+    }
+
+    let pushed = call_function(state, &function, &args)?;
+    let results = state.try_pop_n(pushed)?;
+    cache.set_key(&key, list(results.clone()))?;
+    state.push_all(&results);
+    Ok(pushed)
+}
+
+/// Build the cache key of a memoized call from its arguments.
+///
+/// Each argument is tagged with its type and strings with their length, so that
+/// e.g. `f(1)` and `f("1")` have different keys.
+fn memo_key(args: &[Object]) -> Result<String, RuntimeError> {
+    args.iter()
+        .map(|arg| {
+            Ok(match Key::try_from(arg)? {
+                Key::Boolean(x) => format!("b{x}"),
+                Key::Integer(x) => format!("i{x}"),
+                Key::String(x) => format!("s{}:{x}", x.len()),
+            })
+        })
+        .collect::<Result<Vec<_>, RuntimeError>>()
+        .map(|keys| keys.join(","))
+}
+
+/// Compute the sum of a list of numbers.
+///
+/// The sum is an integer if every element is an integer, and a float otherwise.
+///
+/// Pops 1 argument, the list.
+/// Pushes 1 result, the sum, or 0 for an empty list.
+pub fn sum(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    reduce_numbers(state, n, Primitive::Integer(0), |a, b| a + b)
+}
+
+/// Compute the product of a list of numbers.
+///
+/// The product is an integer if every element is an integer, and a float otherwise.
+///
+/// Pops 1 argument, the list.
+/// Pushes 1 result, the product, or 1 for an empty list.
+pub fn product(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    reduce_numbers(state, n, Primitive::Integer(1), |a, b| a * b)
+}
+
+/// Shared implementation of `sum` and `product`, folding a list of numbers with an operation.
+fn reduce_numbers(
+    state: &mut State,
+    n: usize,
+    initial: Primitive,
+    op: fn(Primitive, Primitive) -> Option<Primitive>,
+) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let elements = list_elements(&state.pop_or_err()?)?;
+    let mut result = initial;
+    for element in &elements {
+        result = match element.as_primitive() {
+            Some(x @ (Primitive::Integer(_) | Primitive::Float(_))) => op(result, x).unwrap(),
+            _ => {
+                return Err(RuntimeError::type_error(format!(
+                    "expected list of numbers, got '{}' element",
+                    element.type_name()
+                )))
+            }
+        };
+    }
+    state.push(&Object::new(Some(ObjectValue::Primitive(result)), None));
+    Ok(1)
+}
+
+/// Get a copy of the elements of a list, so no lock is held while working on them.
+fn list_elements(object: &Object) -> Result<Vec<Object>, RuntimeError> {
+    let elements = match object.inner().lock().unwrap().value() {
+        Some(ObjectValue::List(x)) => Some(x.clone()),
+        _ => None,
+    };
+    // The type name is looked up after releasing the lock on the object
+    elements.ok_or_else(|| {
+        RuntimeError::type_error(format!("expected list, got '{}'", object.type_name()))
+    })
+}
+
+/// Bound a number to the range `[lo, hi]`.
+///
+/// The selected object is returned as-is, so the type of the input (or of the
+/// bound it was clamped to) is preserved.
+///
+/// Pops 3 arguments, the number followed by the lower and upper bounds.
+/// Pushes 1 result, the bounded number.
+pub fn clamp(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 3..=3)?;
+
+    let x = state.pop_or_err()?;
+    let lo = state.pop_or_err()?;
+    let hi = state.pop_or_err()?;
+
+    operations::greater_than(state, &lo, &hi)?;
+    if state.pop_or_err()?.as_bool() == Some(true) {
+        return Err(RuntimeError::value_error(
+            "lower bound is greater than upper bound",
+        ));
+    }
+
+    operations::less_than(state, &x, &lo)?;
+    let result = if state.pop_or_err()?.as_bool() == Some(true) {
+        lo
+    } else {
+        operations::greater_than(state, &x, &hi)?;
+        if state.pop_or_err()?.as_bool() == Some(true) {
+            hi
+        } else {
+            x
+        }
+    };
+    state.push(&result);
+    Ok(1)
+}
+
+/// Compute the greatest common divisor of two integers.
+///
+/// The result is never negative, and is 0 only if both integers are 0. Like other
+/// integer operations, a result too large for an integer is promoted to a float.
+///
+/// Pops 2 arguments, the integers.
+/// Pushes 1 result, the greatest common divisor.
+pub fn gcd(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let a = state.pop_int()?;
+    let b = state.pop_int()?;
+    state.push(&unsigned_int(euclid(a.unsigned_abs(), b.unsigned_abs())));
+    Ok(1)
+}
+
+/// Compute the least common multiple of two integers.
+///
+/// The result is never negative, and is 0 if either integer is 0. Like other
+/// integer operations, a result too large for an integer is promoted to a float.
+///
+/// Pops 2 arguments, the integers.
+/// Pushes 1 result, the least common multiple.
+pub fn lcm(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let a = state.pop_int()?.unsigned_abs();
+    let b = state.pop_int()?.unsigned_abs();
+    let result = match euclid(a, b) {
+        0 => unsigned_int(0),
+        divisor => (a / divisor).checked_mul(b).map_or_else(
+            || float(unsigned_float(a / divisor) * unsigned_float(b)),
+            unsigned_int,
+        ),
+    };
+    state.push(&result);
+    Ok(1)
+}
+
+/// Add two integers, saturating at the integer bounds rather than promoting to a float.
+///
+/// Pops 2 arguments, the integers.
+/// Pushes 1 result, the sum.
+pub fn sat_add(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    saturating(state, n, i64::saturating_add)
+}
+
+/// Subtract two integers, saturating at the integer bounds rather than promoting to a float.
+///
+/// Pops 2 arguments, the integers.
+/// Pushes 1 result, the difference.
+pub fn sat_sub(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    saturating(state, n, i64::saturating_sub)
+}
+
+/// Multiply two integers, saturating at the integer bounds rather than promoting to a float.
+///
+/// Pops 2 arguments, the integers.
+/// Pushes 1 result, the product.
+pub fn sat_mul(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    saturating(state, n, i64::saturating_mul)
+}
+
+/// Shared implementation of the saturating arithmetic functions.
+fn saturating(
+    state: &mut State,
+    n: usize,
+    operation: fn(i64, i64) -> i64,
+) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let a = state.pop_int()?;
+    let b = state.pop_int()?;
+    state.push_int(operation(a, b));
+    Ok(1)
+}
+
+/// Euclid's algorithm for the greatest common divisor.
+const fn euclid(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Create an integer object, or a float if the value is too large for an integer.
+fn unsigned_int(x: u64) -> Object {
+    i64::try_from(x).map_or_else(|_| float(unsigned_float(x)), int)
+}
+
+/// Convert an unsigned integer to the nearest float, for results too large for an integer.
+#[allow(clippy::cast_precision_loss)]
+const fn unsigned_float(x: u64) -> f64 {
+    x as f64
+}
+
+/// Create an integer object from a whole float, or keep the float if it is too large for
+/// an integer.
+///
+/// Infinities and `NaN` have no whole value, so they are an error.
+fn whole_number(x: f64) -> Result<Object, RuntimeError> {
+    if !x.is_finite() {
+        return Err(RuntimeError::value_error(format!(
+            "expected a finite number, got '{x}'"
+        )));
+    }
+    // 2^63 is the magnitude of i64::MIN, which is exact as a float unlike i64::MAX
+    let bound = 2f64.powi(63);
+    if x >= -bound && x < bound {
+        // The float is whole and in range, so the conversion is exact
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(int(x as i64))
+    } else {
+        Ok(float(x))
+    }
+}
+
+/// Tolerance used by [`approx_eq`] when none is given.
+const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+/// Check whether two numbers differ by no more than a tolerance.
+///
+/// Equal numbers (including infinities of the same sign) are always approximately equal,
+/// while `NaN` is never approximately equal to anything.
+///
+/// Pops 2 or 3 arguments, the numbers and the tolerance (`1e-9` if not given).
+/// Pushes 1 result, whether the numbers are approximately equal.
+pub fn approx_eq(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=3)?;
+
+    let a = state.pop_float()?;
+    let b = state.pop_float()?;
+    let tolerance = match n {
+        3 => state.pop_float()?,
+        _ => DEFAULT_TOLERANCE,
+    };
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(RuntimeError::value_error(
+            "tolerance must be a non-negative number",
+        ));
+    }
+    #[allow(clippy::float_cmp)]
+    let equal = a == b || (a - b).abs() <= tolerance;
+    state.push_bool(equal);
+    Ok(1)
 }
 
 /// Rounds a number to the nearest integer.
 ///
+/// Objects with a `__round__` dunder method are rounded by calling it.
+/// Floats too large for an integer stay floats, while infinities and `NaN` are an error.
+///
 /// Pops 1 argument, the number to round.
 /// Pushes 1 result, the rounded number.
-pub fn round(state: &mut State, n: usize) -> usize {
-    assert_eq!(n, 1);
+pub fn round(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
 
-    let object = state.pop().unwrap();
+    let object = state.pop_or_err()?;
+    if call_dunder(state, &object, "__round__")? {
+        return Ok(1);
+    }
     let inner = object.inner();
     let value = inner.lock().unwrap();
     let value = value.value();
     let result = match value {
         Some(ObjectValue::Primitive(x)) => match x {
             Primitive::Integer(x) => int(*x),
-            Primitive::Float(x) => int(x.round() as i64),
+            Primitive::Float(x) => whole_number(x.round())?,
             Primitive::Boolean(x) => int(i64::from(*x)),
-            _ => panic!("unsupported type"),
+            _ => return Err(RuntimeError::type_error("unsupported type")),
         },
-        _ => panic!("unsupported type"),
+        _ => return Err(RuntimeError::type_error("unsupported type")),
+    };
+    state.push(&result);
+    Ok(1)
+}
+
+/// Rounds a number down to the nearest integer.
+///
+/// Objects with a `__floor__` dunder method are rounded by calling it.
+/// Floats too large for an integer stay floats, while infinities and `NaN` are an error.
+///
+/// Pops 1 argument, the number to round.
+/// Pushes 1 result, the rounded number.
+pub fn floor(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    if call_dunder(state, &object, "__floor__")? {
+        return Ok(1);
+    }
+    let result = match object.as_primitive() {
+        Some(Primitive::Integer(x)) => int(x),
+        Some(Primitive::Float(x)) => whole_number(x.floor())?,
+        Some(Primitive::Boolean(x)) => int(i64::from(x)),
+        _ => return Err(RuntimeError::type_error("unsupported type")),
     };
     state.push(&result);
-    1
+    Ok(1)
 }
 
 /// Convert a primitive value to an integer.
@@ -150,10 +774,10 @@ pub fn round(state: &mut State, n: usize) -> usize {
 ///
 /// Pops 1 argument, the primitive value to convert.
 /// Pushes 1 result, the integer value.
-pub fn to_int(state: &mut State, n: usize) -> usize {
-    assert_eq!(n, 1);
+pub fn to_int(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
 
-    let object = state.pop().unwrap();
+    let object = state.pop_or_err()?;
     let inner = object.inner();
     let value = inner.lock().unwrap();
     let value = value.value();
@@ -168,10 +792,10 @@ pub fn to_int(state: &mut State, n: usize) -> usize {
             },
             Primitive::Nil => nil(),
         },
-        _ => panic!("expected primitive"),
+        _ => return Err(RuntimeError::type_error("expected primitive")),
     };
     state.push(&result);
-    1
+    Ok(1)
 }
 
 /// Convert a primitive value to a float.
@@ -180,10 +804,10 @@ pub fn to_int(state: &mut State, n: usize) -> usize {
 ///
 /// Pops 1 argument, the primitive value to convert.
 /// Pushes 1 result, the float value.
-pub fn to_float(state: &mut State, n: usize) -> usize {
-    assert_eq!(n, 1);
+pub fn to_float(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
 
-    let object = state.pop().unwrap();
+    let object = state.pop_or_err()?;
     let inner = object.inner();
     let value = inner.lock().unwrap();
     let value = value.value();
@@ -198,110 +822,1641 @@ pub fn to_float(state: &mut State, n: usize) -> usize {
             },
             Primitive::Nil => nil(),
         },
-        _ => panic!("expected primitive"),
+        _ => return Err(RuntimeError::type_error("expected primitive")),
+    };
+    state.push(&result);
+    Ok(1)
+}
+
+/// Parse a string into a number.
+///
+/// Strings follow the same rules as number literals, so the result is a float if the
+/// string has a fractional part or an exponent, and an integer otherwise.
+/// Numbers are returned unchanged.
+///
+/// Pops 1 argument, the string to parse.
+/// Pushes 1 result, the number, or `nil` if the string isn't a valid number.
+pub fn to_number(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    let parsed = match object.inner().lock().unwrap().value() {
+        Some(ObjectValue::Primitive(Primitive::String(x))) => Some(compiler::parse_number(x)),
+        Some(ObjectValue::Primitive(Primitive::Integer(_) | Primitive::Float(_))) => None,
+        _ => return Err(RuntimeError::type_error("expected a string or number")),
+    };
+    let result = match parsed {
+        Some(Some(compiler::Number::Integer(x))) => int(x),
+        Some(Some(compiler::Number::Float(x))) => float(x),
+        Some(None) => nil(),
+        None => object,
     };
     state.push(&result);
-    1
+    Ok(1)
 }
 
 /// Compute the absolute value of a number.
 ///
+/// Integers stay integers, except for the smallest integer whose absolute value
+/// cannot be represented as an integer; it is promoted to a float instead.
+///
+/// Objects with an `__abs__` dunder method are handled by calling it.
+///
 /// Pops 1 argument, the number to compute the absolute value of.
 /// Pushes 1 result, the absolute value.
-pub fn abs(state: &mut State, n: usize) -> usize {
-    assert_eq!(n, 1);
+pub fn abs(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
 
-    let object = state.pop().unwrap();
-    let inner = object.inner();
-    let value = inner.lock().unwrap();
-    let value = value.value();
-    let result = match value {
-        Some(ObjectValue::Primitive(x)) => match x {
-            Primitive::Integer(x) => int(x.abs()),
-            Primitive::Float(x) => float(x.abs()),
-            _ => nil(),
-        },
-        _ => panic!("expected primitive"),
+    let object = state.pop_or_err()?;
+    if call_dunder(state, &object, "__abs__")? {
+        return Ok(1);
+    }
+    let result = match object.as_primitive() {
+        // Only the smallest integer has no integer magnitude, which is 2^63
+        Some(Primitive::Integer(x)) => x.checked_abs().map_or_else(|| float(2f64.powi(63)), int),
+        Some(Primitive::Float(x)) => float(x.abs()),
+        Some(_) => nil(),
+        None => return Err(RuntimeError::type_error("expected primitive")),
     };
     state.push(&result);
-    1
+    Ok(1)
 }
 
-/// Executes a string as source code.
+/// Compute the length of a list or string.
 ///
-/// This will compile and execute the source code on the
-/// current call frame.
+/// The length of a string is its number of characters.
 ///
-/// Pops 1 argument, the string to execute.
-/// Pushes 1 result, the result of the execution.
-pub fn exec(state: &mut State, n: usize) -> usize {
-    assert_eq!(n, 1);
+/// Pops 1 argument, the list or string.
+/// Pushes 1 result, the length.
+pub fn len(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
 
-    let object = state.pop().unwrap();
-    let inner = object.inner();
-    let value = inner.lock().unwrap();
-    let value = value.value();
-    let result = match value {
-        Some(ObjectValue::Primitive(Primitive::String(source))) => {
-            let result = execute_source(state, source);
-            match result {
-                Ok(_) => state.pop().unwrap_or_else(nil),
-                Err(e) => string(e.to_string()),
-            }
-        }
-        _ => panic!("unsupported type"),
+    let object = state.pop_or_err()?;
+    let result = match object.inner().lock().unwrap().value() {
+        Some(ObjectValue::List(x)) => int(x.len()),
+        Some(ObjectValue::Primitive(Primitive::String(x))) => int(x.chars().count()),
+        _ => return Err(RuntimeError::type_error("unsupported type")),
     };
     state.push(&result);
-    1
+    Ok(1)
 }
 
-/// Exits the program with the given status code.
+/// Check whether a predicate holds for every element of a list.
 ///
-/// Pops 1 argument, the status code.
-/// Pushes 0 results.
-pub fn exit(state: &mut State, n: usize) -> usize {
-    assert!(n <= 1);
+/// Stops calling the predicate at the first element it doesn't hold for.
+///
+/// Pops 2 arguments, the list and the predicate.
+/// Pushes 1 result, whether the predicate held for every element (true for an empty list).
+pub fn all(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    find_predicate_result(state, n, false)
+}
 
-    let object = state.pop().unwrap_or_else(|| int(0));
-    let inner = object.inner();
-    let value = inner.lock().unwrap();
-    let value = value.value();
-    match value {
-        Some(ObjectValue::Primitive(x)) => match x {
-            Primitive::Integer(x) => {
-                std::process::exit(*x as i32);
-            }
-            _ => panic!("expected integer"),
-        },
-        _ => panic!("expected primitive"),
-    };
+/// Check whether a predicate holds for any element of a list.
+///
+/// Stops calling the predicate at the first element it holds for.
+///
+/// Pops 2 arguments, the list and the predicate.
+/// Pushes 1 result, whether the predicate held for any element (false for an empty list).
+pub fn any(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    find_predicate_result(state, n, true)
+}
+
+/// Shared implementation of `all` and `any`, calling a predicate on each element of a list
+/// until it returns `target`. Pushes whether `target` was found.
+fn find_predicate_result(state: &mut State, n: usize, target: bool) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let elements = list_elements(&state.pop_or_err()?)?;
+    let predicate = state.pop_or_err()?;
+    for element in &elements {
+        let result = call_with_element(state, &predicate, element)?
+            .as_bool()
+            .ok_or_else(|| RuntimeError::type_error("expected predicate to return a bool"))?;
+        if result == target {
+            state.push_bool(target);
+            return Ok(1);
+        }
+    }
+    state.push_bool(!target);
+    Ok(1)
 }
 
-/// Read a line from stdin.
+/// Count the elements of a list which equal a value.
 ///
-/// Pops 0 to 1 arguments, the prompt string or nothing.
-/// Pushes 1 result, the line read from stdin.
-pub fn input(state: &mut State, n: usize) -> usize {
-    assert!(n <= 1);
+/// Elements are compared with the same semantics as `==`, including `__eq__`.
+///
+/// Pops 2 arguments, the list and the value.
+/// Pushes 1 result, the number of matching elements.
+pub fn count(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
 
-    let object = state.pop().unwrap_or_else(|| string(""));
-    let inner = object.inner();
-    let value = inner.lock().unwrap();
-    let value = value.value();
-    let result = match value {
-        Some(ObjectValue::Primitive(x)) => match x {
-            Primitive::String(x) => {
-                print!("{x}");
-                let _ = std::io::stdout().lock().flush();
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input).unwrap();
-                // remove \n and \r
-                string(&input[..input.len() - 2])
+    let elements = list_elements(&state.pop_or_err()?)?;
+    let value = state.pop_or_err()?;
+    let mut result = 0;
+    for element in &elements {
+        if element_equals(state, element, &value)? {
+            result += 1;
+        }
+    }
+    state.push_int(result);
+    Ok(1)
+}
+
+/// Find the index of the first element of a list which equals a value.
+///
+/// Elements are compared with the same semantics as `==`, including `__eq__`.
+///
+/// Pops 2 arguments, the list and the value.
+/// Pushes 1 result, the index of the first matching element, or nil if there is none.
+pub fn index_of(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let elements = list_elements(&state.pop_or_err()?)?;
+    let value = state.pop_or_err()?;
+    for (i, element) in elements.iter().enumerate() {
+        if element_equals(state, element, &value)? {
+            state.push(&int(i));
+            return Ok(1);
+        }
+    }
+    state.push(&nil());
+    Ok(1)
+}
+
+/// Compare a list element to a value as `element == value` would.
+fn element_equals(
+    state: &mut State,
+    element: &Object,
+    value: &Object,
+) -> Result<bool, RuntimeError> {
+    operations::equals(state, element, value)?;
+    state
+        .pop()
+        .unwrap()
+        .as_bool()
+        .ok_or_else(|| RuntimeError::type_error("expected __eq__ to return a bool"))
+}
+
+/// Split a string into a list of substrings.
+///
+/// With no separator the string is split on runs of whitespace. Otherwise the
+/// string is split on every occurrence of the (possibly multi-character) separator,
+/// at most `limit` times if a limit is given.
+///
+/// Pops 1 to 3 arguments, the string, the separator, and the split limit.
+/// Pushes 1 result, the list of substrings.
+pub fn split(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=3)?;
+
+    let args: Vec<_> = state
+        .try_pop_n(n)?
+        .iter()
+        .map(Object::as_primitive)
+        .collect();
+    let Some(Primitive::String(source)) = &args[0] else {
+        return Err(RuntimeError::type_error("expected string"));
+    };
+    let separator = match args.get(1) {
+        Some(Some(Primitive::String(x))) if x.is_empty() => {
+            return Err(RuntimeError::value_error("empty separator"))
+        }
+        Some(Some(Primitive::String(x))) => Some(x),
+        None | Some(Some(Primitive::Nil)) => None,
+        _ => return Err(RuntimeError::type_error("expected string separator")),
+    };
+    let limit = match args.get(2) {
+        Some(Some(Primitive::Integer(x))) => Some(
+            usize::try_from(*x)
+                .map_err(|_| RuntimeError::value_error("expected non-negative limit"))?,
+        ),
+        None => None,
+        _ => return Err(RuntimeError::type_error("expected integer limit")),
+    };
+
+    let parts: Vec<_> = match (separator, limit) {
+        (Some(separator), Some(limit)) => source
+            .splitn(limit + 1, separator.as_str())
+            .map(string)
+            .collect(),
+        (Some(separator), None) => source.split(separator.as_str()).map(string).collect(),
+        (None, Some(limit)) => {
+            let mut parts = Vec::new();
+            let mut rest = source.trim_start();
+            while !rest.is_empty() {
+                if parts.len() == limit {
+                    parts.push(string(rest));
+                    break;
+                }
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                parts.push(string(&rest[..end]));
+                rest = rest[end..].trim_start();
             }
-            _ => panic!("expected string"),
-        },
-        _ => panic!("expected primitive"),
+            parts
+        }
+        (None, None) => source.split_whitespace().map(string).collect(),
     };
-    state.push(&result);
-    1
+    state.push(&list(parts));
+    Ok(1)
+}
+
+/// Split a string into a list of lines.
+///
+/// Lines end with `\n` or `\r\n`, which are not included in the lines. A newline at
+/// the end of the string doesn't start another line.
+///
+/// Pops 1 argument, the string.
+/// Pushes 1 result, the list of lines.
+pub fn lines(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    state.push(&list(source.lines().map(string).collect()));
+    Ok(1)
+}
+
+/// Split a string into a list of tokens the way a shell splits a command line.
+///
+/// Tokens are separated by whitespace. Single quotes keep everything up to the next
+/// single quote, and double quotes do the same but allow `\"` and `\\` escapes. Outside
+/// of quotes, a backslash keeps the next character as is. Quoted and unquoted segments
+/// with no whitespace between them form a single token, e.g. `a"b c"` is `ab c`.
+///
+/// Pops 1 argument, the string.
+/// Pushes 1 result, the list of tokens.
+pub fn shlex(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    let unterminated = || RuntimeError::value_error("unterminated quote");
+    let mut tokens = Vec::new();
+    // `None` between tokens, so that an empty quoted token is still a token
+    let mut token: Option<String> = None;
+    let mut chars = source.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => tokens.extend(token.take()),
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| RuntimeError::value_error("trailing backslash"))?;
+                token.get_or_insert_with(String::new).push(escaped);
+            }
+            '\'' => {
+                let token = token.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => token.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let token = token.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unterminated)? {
+                            c @ ('"' | '\\') => token.push(c),
+                            c => {
+                                token.push('\\');
+                                token.push(c);
+                            }
+                        },
+                        c => token.push(c),
+                    }
+                }
+            }
+            c => token.get_or_insert_with(String::new).push(c),
+        }
+    }
+    tokens.extend(token);
+
+    state.push(&list(tokens.into_iter().map(string).collect()));
+    Ok(1)
+}
+
+/// Split a string into its characters.
+///
+/// Pops 1 argument, the string.
+/// Pushes 1 result, the list of characters, each as a single-character string.
+pub fn chars(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    let characters = source.chars().map(|c| string(c.to_string())).collect();
+    state.push(&list(characters));
+    Ok(1)
+}
+
+/// Split a string into the bytes of its UTF-8 encoding.
+///
+/// Pops 1 argument, the string.
+/// Pushes 1 result, the list of bytes, as integers from 0 to 255.
+pub fn bytes(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    let bytes = source.bytes().map(|b| int(i64::from(b))).collect();
+    state.push(&list(bytes));
+    Ok(1)
+}
+
+/// Decode a string from the bytes of its UTF-8 encoding, the inverse of `bytes`.
+///
+/// Bytes which aren't valid UTF-8 are an error:
+///
+/// ```
+/// use scriptyscript::runtime::{executor::execute_source, state::State};
+///
+/// let mut state = State::new();
+/// assert!(execute_source(&mut state, r#"from_bytes(bytes("héllo"));"#).is_ok());
+/// // The first byte of "é" on its own
+/// assert!(execute_source(&mut state, "from_bytes([104, 195]);").is_err());
+/// ```
+///
+/// Pops 1 argument, the list of bytes, as integers from 0 to 255.
+/// Pushes 1 result, the decoded string.
+pub fn from_bytes(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let bytes = byte_list(&state.pop_or_err()?)?;
+    let result = String::from_utf8(bytes)
+        .map_err(|e| RuntimeError::value_error(format!("invalid UTF-8: {}", e.utf8_error())))?;
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Get the bytes held by a list of integers from 0 to 255.
+fn byte_list(object: &Object) -> Result<Vec<u8>, RuntimeError> {
+    let elements = list_elements(object)?;
+    let mut bytes = Vec::with_capacity(elements.len());
+    for element in &elements {
+        let byte = match element.as_primitive() {
+            Some(Primitive::Integer(x)) => u8::try_from(x)
+                .map_err(|_| RuntimeError::value_error(format!("byte {x} out of range")))?,
+            _ => {
+                return Err(RuntimeError::type_error(format!(
+                    "expected list of integers, got '{}' element",
+                    element.type_name()
+                )))
+            }
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+/// The digits of base64, in order of their values.
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode a string, or a list of bytes, as base64 with `=` padding.
+///
+/// Strings are encoded as their UTF-8 bytes.
+///
+/// Pops 1 argument, the string or list of bytes.
+/// Pushes 1 result, the base64 string.
+pub fn base64_encode(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    let bytes = match object.as_primitive() {
+        Some(Primitive::String(x)) => x.into_bytes(),
+        _ => byte_list(&object)?,
+    };
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &byte)| bits | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let digit = (bits >> (18 - 6 * i)) & 0x3f;
+                result.push(char::from(BASE64_ALPHABET[digit as usize]));
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Decode a base64 string with `=` padding into a list of bytes.
+///
+/// Use `from_bytes` to turn the bytes of a string back into the string:
+///
+/// ```
+/// use scriptyscript::runtime::{executor::execute_source, state::State};
+///
+/// let mut state = State::new();
+/// let source = r#"from_bytes(base64_decode(base64_encode("héllo"))) == "héllo";"#;
+/// let result = execute_source(&mut state, source).unwrap().unwrap();
+/// assert_eq!(result.as_bool(), Some(true));
+/// assert!(execute_source(&mut state, r#"base64_decode("aGk*");"#).is_err());
+/// ```
+///
+/// Pops 1 argument, the base64 string.
+/// Pushes 1 result, the list of bytes.
+pub fn base64_decode(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    let invalid = |message: &str| RuntimeError::value_error(format!("invalid base64: {message}"));
+    if source.len() % 4 != 0 {
+        return Err(invalid("length is not a multiple of 4"));
+    }
+    let digits = source.trim_end_matches('=');
+    if source.len() - digits.len() > 2 {
+        return Err(invalid("too much padding"));
+    }
+
+    let mut bytes = Vec::with_capacity(digits.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in digits.bytes() {
+        let digit = BASE64_ALPHABET
+            .iter()
+            .position(|&x| x == c)
+            .ok_or_else(|| invalid(&format!("unexpected character '{}'", char::from(c))))?;
+        // The digit is below 64, so it always fits
+        bits = (bits << 6) | u32::try_from(digit).unwrap();
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push(u8::try_from(bits >> bit_count).unwrap());
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    state.push(&list(
+        bytes.into_iter().map(|b| int(i64::from(b))).collect(),
+    ));
+    Ok(1)
+}
+
+/// Remove characters from both ends of a string.
+///
+/// Whitespace is removed unless a string of characters to remove is given.
+///
+/// Pops 1 to 2 arguments, the string and the characters to remove.
+/// Pushes 1 result, the trimmed string.
+pub fn trim(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    trim_sides(state, n, true, true)
+}
+
+/// Remove characters from the start of a string.
+///
+/// Whitespace is removed unless a string of characters to remove is given.
+///
+/// Pops 1 to 2 arguments, the string and the characters to remove.
+/// Pushes 1 result, the trimmed string.
+pub fn trim_start(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    trim_sides(state, n, true, false)
+}
+
+/// Remove characters from the end of a string.
+///
+/// Whitespace is removed unless a string of characters to remove is given.
+///
+/// Pops 1 to 2 arguments, the string and the characters to remove.
+/// Pushes 1 result, the trimmed string.
+pub fn trim_end(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    trim_sides(state, n, false, true)
+}
+
+/// Shared implementation of the trim functions, trimming the given sides of a string.
+fn trim_sides(state: &mut State, n: usize, start: bool, end: bool) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=2)?;
+
+    let source = state.pop_string()?;
+    let chars: Option<Vec<char>> = match n {
+        2 => Some(state.pop_string()?.chars().collect()),
+        _ => None,
+    };
+    let is_trimmed = |c: char| {
+        chars
+            .as_ref()
+            .map_or_else(|| c.is_whitespace(), |chars| chars.contains(&c))
+    };
+
+    let mut result = source.as_str();
+    if start {
+        result = result.trim_start_matches(is_trimmed);
+    }
+    if end {
+        result = result.trim_end_matches(is_trimmed);
+    }
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Repeat a string a number of times, optionally with a separator between the copies.
+///
+/// Unlike `*`, a separator can be given, and a negative count is an error.
+///
+/// Pops 2 or 3 arguments, the string, the count, and the separator (none if not given).
+/// Pushes 1 result, the repeated string.
+///
+/// # Errors
+/// Returns a [`RuntimeError::ValueError`] if the count is negative, or if the result
+/// would be too large to allocate.
+pub fn repeat(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=3)?;
+
+    let source = state.pop_string()?;
+    let count = usize::try_from(state.pop_int()?)
+        .map_err(|_| RuntimeError::value_error("repeat count must not be negative"))?;
+    let separator = match n {
+        3 => state.pop_string()?,
+        _ => String::new(),
+    };
+    // A failed allocation would abort the whole process rather than just the script
+    let too_large = || RuntimeError::value_error("repeated string is too large");
+    let length = source
+        .len()
+        .checked_mul(count)
+        .and_then(|x| x.checked_add(separator.len().checked_mul(count.saturating_sub(1))?))
+        .ok_or_else(too_large)?;
+    let mut result = String::new();
+    result.try_reserve_exact(length).map_err(|_| too_large())?;
+    for i in 0..count {
+        if i > 0 {
+            result.push_str(&separator);
+        }
+        result.push_str(&source);
+    }
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Pad the start of a string to a minimum width.
+///
+/// The width counts characters rather than bytes. Strings which are already at least
+/// as wide are left unchanged. The string is padded with spaces unless a single
+/// fill character is given.
+///
+/// Pops 2 to 3 arguments, the string, the width, and the fill character.
+/// Pushes 1 result, the padded string.
+pub fn pad_left(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    pad_side(state, n, true)
+}
+
+/// Pad the end of a string to a minimum width.
+///
+/// The width counts characters rather than bytes. Strings which are already at least
+/// as wide are left unchanged. The string is padded with spaces unless a single
+/// fill character is given.
+///
+/// Pops 2 to 3 arguments, the string, the width, and the fill character.
+/// Pushes 1 result, the padded string.
+pub fn pad_right(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    pad_side(state, n, false)
+}
+
+/// Shared implementation of the pad functions, padding either the start or the end of a string.
+fn pad_side(state: &mut State, n: usize, start: bool) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=3)?;
+
+    let source = state.pop_string()?;
+    let width = state.pop_int()?;
+    let fill = match n {
+        3 => {
+            let fill = state.pop_string()?;
+            let mut chars = fill.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(RuntimeError::value_error("fill must be a single character")),
+            }
+        }
+        _ => ' ',
+    };
+
+    // A negative width is never wider than the string
+    let width = usize::try_from(width).unwrap_or(0);
+    let padding: String =
+        std::iter::repeat_n(fill, width.saturating_sub(source.chars().count())).collect();
+    let result = if start {
+        padding + &source
+    } else {
+        source + &padding
+    };
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Format a template string, replacing each `{}` placeholder with the next value.
+///
+/// A placeholder may contain a spec after a colon: an alignment (`<`, `>` or `^`),
+/// a `0` flag to zero-pad numbers, and a minimum width, e.g. `{:>10}` or `{:04}`.
+/// Numbers are right-aligned by default, and everything else is left-aligned.
+/// Literal braces are written as `{{` and `}}`.
+///
+/// Pops 1 or more arguments, the template and the values to format.
+/// Pushes 1 result, the formatted string.
+pub fn format(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..)?;
+
+    let template = state.pop_string()?;
+    let mut values = state.try_pop_n(n - 1)?.into_iter();
+    let mut result = String::new();
+    let mut rest = template.as_str();
+    while let Some(i) = rest.find(['{', '}']) {
+        result.push_str(&rest[..i]);
+        let brace = rest.as_bytes()[i];
+        rest = &rest[i + 1..];
+        if rest.as_bytes().first() == Some(&brace) {
+            result.push(char::from(brace));
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == b'}' {
+            return Err(RuntimeError::value_error("unmatched '}' in format string"));
+        }
+
+        let end = rest.find('}').ok_or_else(|| {
+            RuntimeError::value_error("unterminated placeholder in format string")
+        })?;
+        let spec = FormatSpec::parse(&rest[..end])?;
+        rest = &rest[end + 1..];
+        let value = values
+            .next()
+            .ok_or_else(|| RuntimeError::value_error("not enough values for format string"))?;
+        result.push_str(&spec.apply(state, &value)?);
+    }
+    result.push_str(rest);
+
+    if values.len() != 0 {
+        return Err(RuntimeError::value_error(
+            "too many values for format string",
+        ));
+    }
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Concatenate a list of strings, with no separator between them.
+///
+/// The result is allocated once, rather than once per string as with repeated `+`.
+///
+/// Pops 1 argument, the list of strings.
+/// Pushes 1 result, the concatenated string.
+pub fn concat(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let elements = list_elements(&state.pop_or_err()?)?;
+    let mut capacity = 0;
+    for element in &elements {
+        let length = match element.inner().lock().unwrap().value() {
+            Some(ObjectValue::Primitive(Primitive::String(x))) => Some(x.len()),
+            _ => None,
+        };
+        capacity += length.ok_or_else(|| {
+            RuntimeError::type_error(format!(
+                "expected list of strings, got '{}' element",
+                element.type_name()
+            ))
+        })?;
+    }
+
+    let mut result = String::with_capacity(capacity);
+    for element in &elements {
+        if let Some(ObjectValue::Primitive(Primitive::String(x))) =
+            element.inner().lock().unwrap().value()
+        {
+            result.push_str(x);
+        }
+    }
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Format a number with its integer digits grouped in threes, e.g. `1234567` as `"1,234,567"`.
+///
+/// Only the integer part of a float is grouped.
+///
+/// Pops 1 or 2 arguments, the number and the separator (`","` if not given).
+/// Pushes 1 result, the formatted number.
+pub fn group(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=2)?;
+
+    let number = state.pop_or_err()?;
+    let number = match number.as_primitive() {
+        Some(x @ (Primitive::Integer(_) | Primitive::Float(_))) => x.to_string(),
+        _ => {
+            return Err(RuntimeError::type_error(format!(
+                "expected number, got '{}'",
+                number.type_name()
+            )))
+        }
+    };
+    let separator = match n {
+        2 => state.pop_string()?,
+        _ => ",".to_owned(),
+    };
+
+    let (sign, unsigned) = number
+        .strip_prefix('-')
+        .map_or(("", number.as_str()), |unsigned| ("-", unsigned));
+    let split = unsigned
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(unsigned.len());
+    let (digits, rest) = unsigned.split_at(split);
+
+    let mut result = String::from(sign);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            result.push_str(&separator);
+        }
+        result.push(digit);
+    }
+    result.push_str(rest);
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Format a number according to a table of options.
+///
+/// Every option may be left out:
+/// - `base`: the base of an integer, from 2 to 36 (10 if not given).
+///   Digits above 9 are written as lowercase letters.
+/// - `precision`: the number of digits after the decimal point, for base 10 only.
+/// - `sign`: whether to write a `+` before numbers which aren't negative.
+/// - `width`: the minimum width in characters, padding at the start.
+/// - `pad`: the fill character for the padding (a space if not given).
+///   Zeros go between the sign and the digits.
+///
+/// Pops 1 or 2 arguments, the number and the table of options.
+/// Pushes 1 result, the formatted string.
+pub fn numfmt(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=2)?;
+
+    let number = state.pop_or_err()?;
+    let options = match n {
+        2 => NumberFormat::from_table(&state.pop_or_err()?)?,
+        _ => NumberFormat::default(),
+    };
+    let result = match number.as_primitive() {
+        Some(Primitive::Integer(x)) => options.format_int(x),
+        Some(Primitive::Float(x)) => options.format_float(x)?,
+        _ => {
+            return Err(RuntimeError::type_error(format!(
+                "expected number, got '{}'",
+                number.type_name()
+            )))
+        }
+    };
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Options of the [`numfmt`] builtin.
+struct NumberFormat {
+    base: u32,
+    precision: Option<usize>,
+    sign: bool,
+    width: usize,
+    pad: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            base: 10,
+            precision: None,
+            sign: false,
+            width: 0,
+            pad: ' ',
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Read the options from a table, rejecting unknown keys so typos don't go unnoticed.
+    fn from_table(object: &Object) -> Result<Self, RuntimeError> {
+        let entries: Vec<_> = match object.inner().lock().unwrap().value() {
+            Some(ObjectValue::Table(table)) => table
+                .sorted()
+                .into_iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            _ => {
+                return Err(RuntimeError::type_error(format!(
+                    "expected table of options, got '{}'",
+                    object.type_name()
+                )))
+            }
+        };
+
+        let mut result = Self::default();
+        for (key, value) in entries {
+            let value = value.as_primitive();
+            let name = key.to_string();
+            let expected = |type_name: &str| {
+                RuntimeError::type_error(format!("expected {type_name} for option '{name}'"))
+            };
+            match (name.as_str(), value) {
+                (_, None | Some(Primitive::Nil)) => {}
+                ("base", Some(Primitive::Integer(x))) => {
+                    result.base = u32::try_from(x)
+                        .ok()
+                        .filter(|x| (2..=36).contains(x))
+                        .ok_or_else(|| RuntimeError::value_error("base must be from 2 to 36"))?;
+                }
+                ("precision", Some(Primitive::Integer(x))) => {
+                    result.precision = Some(usize::try_from(x).map_err(|_| {
+                        RuntimeError::value_error("precision must not be negative")
+                    })?);
+                }
+                ("width", Some(Primitive::Integer(x))) => {
+                    // A negative width is never wider than the number
+                    result.width = usize::try_from(x).unwrap_or(0);
+                }
+                ("sign", Some(Primitive::Boolean(x))) => result.sign = x,
+                ("pad", Some(Primitive::String(x))) => {
+                    let mut chars = x.chars();
+                    result.pad = match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => {
+                            return Err(RuntimeError::value_error("pad must be a single character"))
+                        }
+                    };
+                }
+                ("base" | "precision" | "width", _) => return Err(expected("int")),
+                ("sign", _) => return Err(expected("bool")),
+                ("pad", _) => return Err(expected("string")),
+                _ => {
+                    return Err(RuntimeError::value_error(format!(
+                        "unknown numfmt option '{name}'"
+                    )))
+                }
+            }
+        }
+        if result.base != 10 && result.precision.is_some() {
+            return Err(RuntimeError::value_error(
+                "precision is only supported in base 10",
+            ));
+        }
+        Ok(result)
+    }
+
+    fn format_int(&self, x: i64) -> String {
+        // Going through a float would round integers beyond 2^53
+        let mut digits = self.digits(x.unsigned_abs());
+        if let Some(precision) = self.precision.filter(|&precision| precision > 0) {
+            digits.push('.');
+            digits.push_str(&"0".repeat(precision));
+        }
+        self.pad(x.is_negative(), &digits)
+    }
+
+    /// Write the digits of an integer in the base.
+    fn digits(&self, mut magnitude: u64) -> String {
+        let mut digits = Vec::new();
+        loop {
+            // The remainder is always a valid digit, since the base is at most 36
+            let digit = u32::try_from(magnitude % u64::from(self.base)).unwrap();
+            digits.push(char::from_digit(digit, self.base).unwrap());
+            magnitude /= u64::from(self.base);
+            if magnitude == 0 {
+                break;
+            }
+        }
+        digits.into_iter().rev().collect()
+    }
+
+    fn format_float(&self, x: f64) -> Result<String, RuntimeError> {
+        if self.base != 10 {
+            return Err(RuntimeError::value_error(
+                "base is only supported for integers",
+            ));
+        }
+        let digits = self.precision.map_or_else(
+            || Primitive::Float(x.abs()).to_string(),
+            |precision| format!("{:.precision$}", x.abs()),
+        );
+        Ok(self.pad(x.is_sign_negative() && !x.is_nan(), &digits))
+    }
+
+    /// Add the sign and padding to the digits of a number.
+    fn pad(&self, negative: bool, digits: &str) -> String {
+        let sign = match (negative, self.sign) {
+            (true, _) => "-",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        let padding = self
+            .width
+            .saturating_sub(sign.len() + digits.chars().count());
+        let padding: String = std::iter::repeat_n(self.pad, padding).collect();
+        if self.pad == '0' {
+            format!("{sign}{padding}{digits}")
+        } else {
+            format!("{padding}{sign}{digits}")
+        }
+    }
+}
+
+/// Alignment of a value within the width of a format placeholder.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+/// The parsed spec of a format placeholder, e.g. the `:>10` in `{:>10}`.
+#[derive(Default)]
+struct FormatSpec {
+    align: Option<Alignment>,
+    zero_pad: bool,
+    width: usize,
+}
+
+impl FormatSpec {
+    /// Parse the text between the braces of a placeholder.
+    fn parse(spec: &str) -> Result<Self, RuntimeError> {
+        let invalid = || RuntimeError::value_error(format!("invalid format spec '{{{spec}}}'"));
+        if spec.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut rest = spec.strip_prefix(':').ok_or_else(invalid)?;
+
+        let align = match rest.chars().next() {
+            Some('<') => Some(Alignment::Left),
+            Some('>') => Some(Alignment::Right),
+            Some('^') => Some(Alignment::Center),
+            _ => None,
+        };
+        if align.is_some() {
+            rest = &rest[1..];
+        }
+        let zero_pad = rest.starts_with('0');
+        if zero_pad {
+            rest = &rest[1..];
+        }
+        let width = match rest {
+            "" => 0,
+            _ => rest.parse().map_err(|_| invalid())?,
+        };
+        Ok(Self {
+            align,
+            zero_pad,
+            width,
+        })
+    }
+
+    /// Format a value according to this spec.
+    fn apply(&self, state: &mut State, value: &Object) -> Result<String, RuntimeError> {
+        let is_number = matches!(
+            value.as_primitive(),
+            Some(Primitive::Integer(_) | Primitive::Float(_))
+        );
+        if self.zero_pad && !is_number {
+            return Err(RuntimeError::type_error(format!(
+                "cannot zero-pad '{}' value",
+                value.type_name()
+            )));
+        }
+        state.push(value);
+        to_string(state, 1)?;
+        let text = state.pop_string()?;
+
+        let padding = self.width.saturating_sub(text.chars().count());
+        if self.zero_pad && self.align.is_none() {
+            // Zeros go between the sign and the digits
+            let (sign, digits) = text
+                .strip_prefix('-')
+                .map_or(("", text.as_str()), |digits| ("-", digits));
+            return Ok(format!("{sign}{}{digits}", "0".repeat(padding)));
+        }
+
+        let default = if is_number {
+            Alignment::Right
+        } else {
+            Alignment::Left
+        };
+        let (before, after) = match self.align.unwrap_or(default) {
+            Alignment::Left => (0, padding),
+            Alignment::Right => (padding, 0),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = if self.zero_pad { "0" } else { " " };
+        Ok(format!(
+            "{}{text}{}",
+            fill.repeat(before),
+            fill.repeat(after)
+        ))
+    }
+}
+
+/// Create a new, empty string builder.
+///
+/// Appending to a builder grows its buffer in place, so building a string from many
+/// fragments takes linear time rather than the quadratic time of repeated concatenation.
+/// The buffer is hidden inside the builder, and is copied out by [`string_builder_build`].
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, the string builder.
+pub fn new_string_builder(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    state.push(&Object::new(
+        Some(ObjectValue::StringBuilder(String::new())),
+        None,
+    ));
+    Ok(1)
+}
+
+/// Append a string to the end of a string builder.
+///
+/// Pops 2 arguments, the string builder and the string to append.
+/// Pushes no results.
+pub fn string_builder_append(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let builder = state.pop_or_err()?;
+    let fragment = state.pop_string()?;
+    // The error is made after the lock is released, since it locks the object again
+    let appended = match &mut builder.inner().lock().unwrap().value {
+        Some(ObjectValue::StringBuilder(buffer)) => {
+            buffer.push_str(&fragment);
+            true
+        }
+        _ => false,
+    };
+    if !appended {
+        return Err(expected_string_builder(&builder));
+    }
+    Ok(0)
+}
+
+/// Get the string built so far by a string builder.
+///
+/// Pops 1 argument, the string builder.
+/// Pushes 1 result, a copy of the built string.
+pub fn string_builder_build(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let builder = state.pop_or_err()?;
+    let result = match builder.inner().lock().unwrap().value() {
+        Some(ObjectValue::StringBuilder(buffer)) => Some(string(buffer)),
+        _ => None,
+    };
+    let result = result.ok_or_else(|| expected_string_builder(&builder))?;
+    state.push(&result);
+    Ok(1)
+}
+
+/// Create the error for an object which was expected to be a string builder.
+fn expected_string_builder(object: &Object) -> RuntimeError {
+    RuntimeError::type_error(format!(
+        "expected string builder, got '{}'",
+        object.type_name()
+    ))
+}
+
+/// Create a list of a given length, with every element set to the same value.
+///
+/// The value is shared rather than copied, so e.g. every element of `list(3, table())`
+/// is the same table.
+///
+/// Pops 1 or 2 arguments, the length and the value (nil if not given).
+/// Pushes 1 result, the list.
+///
+/// # Errors
+/// Returns a [`RuntimeError::ValueError`] if the length is negative, or too large to
+/// allocate.
+pub fn new_list(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=2)?;
+
+    let length = usize::try_from(state.pop_int()?)
+        .map_err(|_| RuntimeError::value_error("list length must not be negative"))?;
+    let value = match n {
+        2 => state.pop_or_err()?,
+        _ => nil(),
+    };
+    // A failed allocation would abort the whole process rather than just the script
+    let mut elements = Vec::new();
+    elements
+        .try_reserve_exact(length)
+        .map_err(|_| RuntimeError::value_error(format!("list length {length} is too large")))?;
+    elements.resize(length, value);
+    state.push(&list(elements));
+    Ok(1)
+}
+
+/// Create a new, empty table.
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, the table.
+pub fn new_table(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    state.push(&table());
+    Ok(1)
+}
+
+/// Freeze a table, preventing any further assignments to its keys.
+///
+/// Assigning to a key of a frozen table raises an error.
+///
+/// Pops 1 argument, the table.
+/// Pushes 1 result, the same (now frozen) table.
+pub fn freeze(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    match &mut object.inner().lock().unwrap().value {
+        Some(ObjectValue::Table(x)) => x.freeze(),
+        _ => return Err(RuntimeError::type_error("expected table")),
+    }
+    state.push(&object);
+    Ok(1)
+}
+
+/// Check whether a table has been frozen.
+///
+/// Pops 1 argument, the table.
+/// Pushes 1 result, whether the table is frozen.
+pub fn is_frozen(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    let frozen = match object.inner().lock().unwrap().value() {
+        Some(ObjectValue::Table(x)) => x.is_frozen(),
+        _ => return Err(RuntimeError::type_error("expected table")),
+    };
+    state.push_bool(frozen);
+    Ok(1)
+}
+
+/// Check whether a table has a key.
+///
+/// Unlike comparing `t[key]` to nil, this is true for keys assigned a nil value.
+///
+/// Pops 2 arguments, the table and the key.
+/// Pushes 1 result, whether the key is present.
+pub fn has(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let object = state.pop_or_err()?;
+    let key = Key::try_from(&state.pop_or_err()?)?;
+    let present = match object.inner().lock().unwrap().value() {
+        Some(ObjectValue::Table(x)) => x.contains_key(key),
+        _ => return Err(RuntimeError::type_error("expected table")),
+    };
+    state.push_bool(present);
+    Ok(1)
+}
+
+/// Remove a key from a table.
+///
+/// Removing a key which isn't present does nothing. Keys cannot be removed
+/// from a frozen table.
+///
+/// Pops 2 arguments, the table and the key.
+/// Pushes 1 result, the removed value, or nil if the key wasn't present.
+pub fn delete(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let object = state.pop_or_err()?;
+    let key = Key::try_from(&state.pop_or_err()?)?;
+    let removed = match &mut object.inner().lock().unwrap().value {
+        Some(ObjectValue::Table(x)) if x.is_frozen() => {
+            return Err(RuntimeError::FrozenTable(key.to_string()))
+        }
+        Some(ObjectValue::Table(x)) => x.remove(key),
+        _ => return Err(RuntimeError::type_error("expected table")),
+    };
+    state.push(&removed.unwrap_or_else(nil));
+    Ok(1)
+}
+
+/// Hash a value's contents into an integer, recursing into lists and tables.
+///
+/// The hash is the same in every run, so it can be stored or compared across runs.
+/// Equal values hash equally, while different values almost always hash differently.
+/// Functions and weak references can't be hashed.
+///
+/// Pops 1 argument, the value.
+/// Pushes 1 result, the hash.
+pub fn hash(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    let hash = object
+        .stable_hash()
+        .ok_or_else(|| RuntimeError::type_error("cannot hash functions or weak references"))?;
+    state.push_int(i64::from_le_bytes(hash.to_le_bytes()));
+    Ok(1)
+}
+
+/// Check whether two values are the same object, rather than equal values.
+///
+/// This tells a shared table or list apart from a copy of it. Small integers and
+/// booleans are shared, while other primitives are created anew by each expression,
+/// so the result is only meaningful for tables, lists, and functions.
+///
+/// Pops 2 arguments, the objects to compare.
+/// Pushes 1 result, whether they are the same object.
+pub fn is(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let a = state.pop_or_err()?;
+    let b = state.pop_or_err()?;
+    state.push_bool(a.ptr_eq(&b));
+    Ok(1)
+}
+
+/// Compare two objects structurally, recursing into lists and tables.
+///
+/// Cyclic lists and tables are handled safely. `NaN` is never equal to anything,
+/// including itself. Dunder methods such as `__eq__` are not used.
+///
+/// Pops 2 arguments, the objects to compare.
+/// Pushes 1 result, whether the objects are deeply equal.
+pub fn deep_equal(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let a = state.pop_or_err()?;
+    let b = state.pop_or_err()?;
+    state.push_bool(a.deep_eq(&b));
+    Ok(1)
+}
+
+/// Raise an error if a condition is false.
+///
+/// `assert(condition);` statements are compiled on their own, so that failures can report
+/// the source of the condition. This is the same check for when `assert` is called as a
+/// function, e.g. after being passed around as a value.
+///
+/// Pops 1 to 2 arguments, the condition and the message.
+/// Pushes 0 results.
+pub fn assert(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=2)?;
+
+    let mut args = state.try_pop_n(n)?.into_iter().map(|x| x.as_primitive());
+    match args.next().flatten() {
+        Some(Primitive::Boolean(true)) => Ok(0),
+        Some(Primitive::Boolean(false)) => {
+            Err(RuntimeError::AssertionFailed(match args.next().flatten() {
+                Some(Primitive::String(message)) => message,
+                _ => "condition is false".to_string(),
+            }))
+        }
+        _ => Err(RuntimeError::type_error("assert condition must be a bool")),
+    }
+}
+
+/// Set the metatable of an object. The metatable holds dunder methods
+/// (e.g. `__add__`) used to overload operators on the object.
+///
+/// Pops 2 arguments, the object and the metatable (a table, or nil to remove it).
+/// Pushes 1 result, the object.
+pub fn set_metatable(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 2..=2)?;
+
+    let object = state.pop_or_err()?;
+    // Ints and bools are shared between every use of the same value (see `utilities`),
+    // so a metatable set on one would apply to all of them
+    if matches!(object.type_name(), "int" | "bool") {
+        return Err(RuntimeError::type_error(format!(
+            "cannot set the metatable of '{}' value",
+            object.type_name()
+        )));
+    }
+    let metatable = state.pop_or_err()?;
+    let metatable = match metatable.type_name() {
+        "table" => Some(metatable),
+        "nil" => None,
+        _ => return Err(RuntimeError::type_error("expected table or nil metatable")),
+    };
+    object.inner().lock().unwrap().set_metatable(metatable);
+    gc::track(&object);
+    state.push(&object);
+    Ok(1)
+}
+
+/// Get the metatable of an object.
+///
+/// Pops 1 argument, the object.
+/// Pushes 1 result, the metatable, or nil if the object has none.
+pub fn get_metatable(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    let metatable = object.inner().lock().unwrap().metatable().clone();
+    state.push(&metatable.unwrap_or_else(nil));
+    Ok(1)
+}
+
+/// Create a weak reference to an object.
+///
+/// A weak reference doesn't keep the object alive, which makes it useful for
+/// caches that shouldn't prevent their entries from being freed.
+/// Use `deref` to get the object back.
+///
+/// Pops 1 argument, the object.
+/// Pushes 1 result, the weak reference.
+pub fn new_weak(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let object = state.pop_or_err()?;
+    state.push(&weak(&object));
+    Ok(1)
+}
+
+/// Get the object a weak reference refers to.
+///
+/// Pops 1 argument, the weak reference.
+/// Pushes 1 result, the object, or nil if it has been freed.
+pub fn deref(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let reference = state.pop_or_err()?;
+    let target = match reference.inner().lock().unwrap().value() {
+        Some(ObjectValue::Weak(x)) => Some(x.upgrade()),
+        _ => None,
+    };
+    let Some(target) = target else {
+        return Err(RuntimeError::type_error(format!(
+            "expected weak reference, got '{}'",
+            reference.type_name()
+        )));
+    };
+    state.push(&target.map_or_else(nil, |inner| Object { inner }));
+    Ok(1)
+}
+
+/// Executes a string as source code.
+///
+/// This will compile and execute the source code on the
+/// current call frame.
+///
+/// Pops 1 argument, the string to execute.
+/// Pushes 1 result, the result of the execution.
+pub fn exec(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    let result = match execute_source(state, &source) {
+        Ok(result) => result.unwrap_or_else(nil),
+        Err(e) => string(e.to_string()),
+    };
+    state.push(&result);
+    Ok(1)
+}
+
+/// Parses a string as source code without executing it.
+///
+/// The syntax tree is returned as nested tables. See the
+/// [`ast_table`] module for a description of the structure. A single expression
+/// may leave out its trailing `;`, so `parse("1 + 2")` gives the same tree as
+/// `parse("1 + 2;")`.
+///
+/// Pops 1 argument, the string to parse.
+/// Pushes 1 result, the table for the root node of the syntax tree.
+pub fn parse(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let source = state.pop_string()?;
+    let ast = compiler::parse(&source)
+        .or_else(|e| compiler::parse(format!("{source};")).map_err(|_| e))
+        .map_err(|e| RuntimeError::SyntaxError(e.to_string()))?;
+    state.push(&ast_table::to_table(&ast));
+    Ok(1)
+}
+
+/// Exits the program with the given status code, after flushing the output.
+///
+/// Pops 1 argument, the status code.
+/// Pushes 0 results.
+pub fn exit(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, ..=1)?;
+
+    let code = if n == 0 { 0 } else { state.pop_int()? };
+    let code = i32::try_from(code)
+        .map_err(|_| RuntimeError::value_error(format!("exit code {code} is out of range")))?;
+    state.streams().flush()?;
+    std::process::exit(code);
+}
+
+/// Get the value of an environment variable.
+///
+/// Pops 1 argument, the name of the variable.
+/// Pushes 1 result, the value of the variable, or nil if it isn't set or isn't valid unicode.
+pub fn env(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let name = state.pop_string()?;
+    let result = std::env::var(name).map_or_else(|_| nil(), string);
+    state.push(&result);
+    Ok(1)
+}
+
+/// Flush the output, writing anything which is still buffered.
+///
+/// Output is only buffered if the embedder [chose to](State::set_output_buffered), and
+/// `input` flushes before reading anyway, so this is rarely needed.
+///
+/// Pops 0 arguments.
+/// Pushes no results.
+pub fn flush(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    state.streams().flush()?;
+    Ok(0)
+}
+
+/// Read a line from the input, stdin unless the embedder [set](State::set_input) another.
+///
+/// The prompt is written without a newline, and the output is flushed before reading,
+/// so that the prompt and any earlier output are shown first.
+///
+/// Pops 0 to 1 arguments, the prompt string or nothing.
+/// Pushes 1 result, the line read from the input.
+pub fn input(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, ..=1)?;
+
+    let prompt = if n == 0 {
+        String::new()
+    } else {
+        state.pop_string()?
+    };
+    let streams = state.streams();
+    streams.write(&prompt)?;
+    streams.flush()?;
+    let mut input = String::new();
+    streams.read_line(&mut input)?;
+    state.push_string(strip_line_ending(&input));
+    Ok(1)
+}
+
+/// Read the next line from the input, without writing a prompt.
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, the line read from the input, or nil if the end of input was reached.
+pub fn read_line(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    let mut line = String::new();
+    let result = match state.streams().read_line(&mut line)? {
+        0 => nil(),
+        _ => string(strip_line_ending(&line)),
+    };
+    state.push(&result);
+    Ok(1)
+}
+
+/// Read everything remaining on the input.
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, the remaining input as a string.
+pub fn read_all(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    let mut input = String::new();
+    state.streams().read_to_string(&mut input)?;
+    state.push_string(input);
+    Ok(1)
+}
+
+/// Remove a trailing `\n` or `\r\n` from a line.
+fn strip_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// Block the current thread for the given number of seconds.
+///
+/// Pops 1 argument, the duration in seconds (integer or float).
+/// Pushes 0 results.
+pub fn sleep(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let seconds = state.pop_float()?;
+    let duration = Duration::try_from_secs_f64(seconds)
+        .map_err(|_| RuntimeError::value_error("invalid sleep duration"))?;
+    std::thread::sleep(duration);
+    Ok(0)
+}
+
+/// Read a monotonic clock, in seconds.
+///
+/// Only the difference between two readings is meaningful,
+/// which makes this useful for measuring durations.
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, the elapsed time in seconds as a float.
+pub fn clock(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    static START: Lazy<Instant> = Lazy::new(Instant::now);
+    State::expect_args(n, 0..=0)?;
+
+    state.push_float(START.elapsed().as_secs_f64());
+    Ok(1)
+}
+
+/// Take a snapshot of the calling scope's local variables.
+///
+/// Changing the table doesn't change the variables. At the top level of a script,
+/// the locals are the global variables, including the builtins.
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, a table of the local variables keyed by name.
+pub fn locals(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    let mut result = Table::new();
+    for (name, value) in state.caller_locals().unwrap_or_default() {
+        result.set(name, value);
+    }
+    state.push(&Object::new(Some(ObjectValue::Table(result)), None));
+    Ok(1)
+}
+
+/// Describe the current call stack, most recent call last.
+///
+/// Pops 0 arguments.
+/// Pushes 1 result, a string with one line per call frame.
+pub fn traceback(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 0..=0)?;
+
+    let mut frames = state.traceback();
+    // Leave out the frame of this call itself
+    frames.pop();
+    let mut result = String::from("traceback (most recent call last):");
+    for frame in frames {
+        result.push_str("\n  ");
+        result.push_str(&frame);
+    }
+    state.push_string(result);
+    Ok(1)
+}
+
+/// Run another script file in a new child scope.
+///
+/// Relative paths are resolved against the current working directory.
+///
+/// Pops 1 argument, the path of the script file.
+/// Pushes 1 result, the final value of the script, or nil if there is none.
+pub fn dofile(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let path = state.pop_string()?;
+    let result = run_file(state, &path)?;
+    state.push(&result.unwrap_or_else(nil));
+    Ok(1)
+}
+
+/// Import a module, running its file only the first time it is imported.
+///
+/// The module `name` is resolved to the file `name.ss`, relative to the current
+/// working directory. Names which already end in `.ss` (in any case) are used as-is.
+///
+/// Pops 1 argument, the name of the module.
+/// Pushes 1 result, the final value of the module file (usually a table).
+pub fn import(state: &mut State, n: usize) -> Result<usize, RuntimeError> {
+    State::expect_args(n, 1..=1)?;
+
+    let name = state.pop_string()?;
+    let is_script = Path::new(&name)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ss"));
+    let path = if is_script {
+        name
+    } else {
+        format!("{name}.ss")
+    };
+    // The same file may be imported through different paths, e.g. `./a.ss` and `a.ss`.
+    // A missing file has no canonical path, and is reported when it is run.
+    let key = std::fs::canonicalize(&path).unwrap_or_else(|_| PathBuf::from(&path));
+    let module = if let Some(module) = state.get_module(&key) {
+        module
+    } else {
+        let module = run_file(state, &path)?.unwrap_or_else(nil);
+        state.cache_module(&key, module.clone());
+        module
+    };
+    state.push(&module);
+    Ok(1)
+}
+
+/// Read, compile, and run a script file in a new child scope.
+///
+/// Any error is wrapped in a [`RuntimeError::InFile`] naming the file.
+fn run_file(state: &mut State, path: &str) -> Result<Option<Object>, RuntimeError> {
+    let in_file = |error| RuntimeError::InFile {
+        path: path.to_owned(),
+        error: Box::new(error),
+    };
+    let source =
+        std::fs::read_to_string(path).map_err(|e| in_file(RuntimeError::IoError(e.to_string())))?;
+    let bytecode =
+        compiler::compile(source).map_err(|e| in_file(RuntimeError::SyntaxError(e.to_string())))?;
+
+    state.push_named_frame(Some(path.into()));
+    let result = state.run(&bytecode);
+    state.pop_frame();
+    result.map_err(in_file)
 }
@@ -0,0 +1,65 @@
+//! Helpers shared by the integration tests, for running scripts and inspecting their results.
+#![allow(dead_code)]
+
+use scriptyscript::{
+    compiler::compile,
+    runtime::{error::RuntimeError, state::State, types::primitive::Primitive},
+};
+
+/// Run a script on a fresh state, returning the value of its top-level `return`.
+///
+/// Panics if the script doesn't compile.
+pub fn run(source: &str) -> Result<Option<Primitive>, RuntimeError> {
+    run_on(&mut State::new(), source)
+}
+
+/// Run a script on the given state, returning the value of its top-level `return`.
+///
+/// Panics if the script doesn't compile.
+pub fn run_on(state: &mut State, source: &str) -> Result<Option<Primitive>, RuntimeError> {
+    let bytecode = compile(source).unwrap_or_else(|e| panic!("failed to compile {source:?}: {e}"));
+    Ok(state.run(&bytecode)?.and_then(|x| x.as_primitive()))
+}
+
+/// Evaluate an expression on a fresh state, which must succeed with a primitive value.
+pub fn eval(expression: &str) -> Primitive {
+    run(&format!("return {expression};"))
+        .unwrap_or_else(|e| panic!("failed to evaluate {expression:?}: {e}"))
+        .unwrap_or_else(|| panic!("{expression:?} is not a primitive"))
+}
+
+/// Evaluate an expression on a fresh state, giving the string representation of its value.
+pub fn eval_string(expression: &str) -> String {
+    match eval(&format!("string({expression})")) {
+        Primitive::String(s) => s,
+        other => panic!("expected a string, got {other:?}"),
+    }
+}
+
+/// Evaluate an expression on a fresh state, which must fail.
+pub fn eval_err(expression: &str) -> RuntimeError {
+    match run(&format!("return {expression};")) {
+        Ok(value) => panic!("{expression:?} should fail, but gave {value:?}"),
+        Err(e) => e,
+    }
+}
+
+/// Shorthand for an integer primitive.
+pub const fn int(x: i64) -> Primitive {
+    Primitive::Integer(x)
+}
+
+/// Shorthand for a float primitive.
+pub const fn float(x: f64) -> Primitive {
+    Primitive::Float(x)
+}
+
+/// Shorthand for a string primitive.
+pub fn string(x: &str) -> Primitive {
+    Primitive::String(x.to_owned())
+}
+
+/// Shorthand for a boolean primitive.
+pub const fn boolean(x: bool) -> Primitive {
+    Primitive::Boolean(x)
+}
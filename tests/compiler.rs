@@ -0,0 +1,126 @@
+//! Tests for the parser, translator, and static analysis.
+mod common;
+
+use common::{eval, int, run};
+use scriptyscript::compiler::{analysis::Warning, compile, compile_with_warnings, parse};
+
+#[test]
+fn octal_literals() {
+    assert_eq!(eval("0o0"), int(0));
+    assert_eq!(eval("0o17"), int(15));
+    assert_eq!(eval("0O17"), int(15));
+    // A leading zero is still decimal
+    assert_eq!(eval("017"), int(17));
+    assert!(parse("x = 0o18;").is_err());
+    assert!(parse("x = 0o9;").is_err());
+}
+
+#[test]
+fn code_after_return_is_unreachable() {
+    let (_, warnings) = compile_with_warnings(
+        "f = fn() {
+            return 1;
+            print(\"never\");
+            x = 2;
+        };",
+    )
+    .unwrap();
+    assert_eq!(
+        warnings,
+        vec![Warning::UnreachableCode {
+            after: "return",
+            statements: 2,
+        }]
+    );
+
+    let (_, warnings) = compile_with_warnings("f = fn() { x = 1; return x; };").unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn trailing_commas_in_calls_and_definitions() {
+    assert!(parse("f();").is_ok());
+    assert!(parse("f(1, 2,);").is_ok());
+    assert!(parse("f(\n    1,\n    2,\n);").is_ok());
+    assert!(parse("f = fn(a, b,) { a };").is_ok());
+    assert!(parse("f = fn(\n    a,\n    b,\n) { a };").is_ok());
+    assert!(parse("f(,);").is_err());
+    assert!(parse("f(1,,);").is_err());
+    assert!(parse("f = fn(,) { };").is_err());
+    assert_eq!(eval("fn(a, b,) { a - b }(5, 3,)"), int(2));
+}
+
+#[test]
+fn constant_if_conditions_drop_the_dead_branch() {
+    assert_eq!(
+        compile("if true { x = 1; } else { x = 2; }").unwrap(),
+        compile("x = 1;").unwrap()
+    );
+    assert_eq!(
+        compile("if not (1 > 2) { x = 1; } else { x = 2; }").unwrap(),
+        compile("x = 1;").unwrap()
+    );
+    assert_eq!(
+        compile("if false { x = 1; } else { x = 2; }").unwrap(),
+        compile("x = 2;").unwrap()
+    );
+    assert!(compile("if false { x = 1; }").unwrap().inner().is_empty());
+    assert_ne!(
+        compile("if x { x = 1; } else { x = 2; }").unwrap(),
+        compile("x = 1;").unwrap()
+    );
+}
+
+#[test]
+fn anonymous_functions_are_expressions() {
+    assert!(parse("ys = map(xs, fn(x) { return x * 2; });").is_ok());
+    assert!(parse("f = fn() { return fn(y) { y }; };").is_ok());
+    assert_eq!(eval("fn(x) { return x + 1; }(41)"), int(42));
+    assert_eq!(
+        run("apply = fn(f, x) { return f(x); }; return apply(fn(x) { x * 2 }, 21);"),
+        Ok(Some(int(42)))
+    );
+}
+
+#[test]
+fn magnitude_of_the_smallest_integer_is_only_allowed_negated() {
+    assert!(parse("x = -9223372036854775808;").is_ok());
+    assert!(parse("x = 1 - -0x8000000000000000;").is_ok());
+    assert!(
+        parse("x = [-0b1000000000000000000000000000000000000000000000000000000000000000];").is_ok()
+    );
+    assert!(parse("x = 9223372036854775808;").is_err());
+    assert!(parse("x = 1 - 9223372036854775808;").is_err());
+    assert!(parse("x = -9223372036854775809;").is_err());
+    // Indexing binds tighter than negation
+    assert!(parse("x = -9223372036854775808[0];").is_err());
+}
+
+#[test]
+fn stray_semicolons_are_empty_statements() {
+    let same = |a: &str, b: &str| assert_eq!(compile(a).unwrap(), compile(b).unwrap());
+    same(";", "");
+    same(";;;", "");
+    same("x = 1;;", "x = 1;");
+    same(";x = 1;; ;y = 2;", "x = 1; y = 2;");
+    // After blocks, and inside them
+    same(
+        "if x { ;; y = 1;; }; while false { };",
+        "if x { y = 1; } while false { }",
+    );
+    assert_eq!(run(";; x = 4;; return x;;"), Ok(Some(int(4))));
+    // A semicolon still doesn't make a statement out of nothing
+    assert!(parse("x = ;").is_err());
+}
+
+#[test]
+fn line_comments_start_with_a_hash() {
+    let same = |a: &str, b: &str| assert_eq!(compile(a).unwrap(), compile(b).unwrap());
+    same("# a comment\nx = 1; # another\n#", "x = 1;");
+    same("x = [1, # one\n 2];", "x = [1, 2];");
+    same("x = 1 /* inline */ + 2;", "x = 1 + 2;");
+    // `//` is floor division rather than a comment
+    assert_eq!(run("return 7 // 2;"), Ok(Some(int(3))));
+    assert_eq!(run("return 7 //2 # comment\n;"), Ok(Some(int(3))));
+    assert!(parse("x = 1; // not a comment").is_err());
+}
@@ -0,0 +1,514 @@
+//! Tests for the syntax and semantics of the language, run as scripts.
+mod common;
+
+use common::{boolean, eval, eval_err, float, int, run, run_on, string};
+use scriptyscript::runtime::{
+    error::RuntimeError, executor::call_function, state::State, types::utilities,
+};
+
+#[test]
+fn negative_indices_count_from_the_end() {
+    assert_eq!(eval("[1, 2, 3][-1]"), int(3));
+    assert_eq!(eval("[1, 2, 3][-2]"), int(2));
+    assert_eq!(eval("\"abc\"[-1]"), string("c"));
+    assert_eq!(eval("\"abc\"[-2]"), string("b"));
+    assert_eq!(
+        run("xs = [1, 2, 3]; xs[-1] = 9; return xs[2];"),
+        Ok(Some(int(9)))
+    );
+}
+
+#[test]
+fn out_of_range_negative_index_is_an_error() {
+    assert_eq!(
+        eval_err("[1, 2, 3][-4]"),
+        RuntimeError::IndexOutOfRange { index: -4, len: 3 }
+    );
+    assert!(matches!(
+        run("xs = [1]; xs[-2] = 0;"),
+        Err(RuntimeError::IndexOutOfRange { .. })
+    ));
+}
+
+#[test]
+fn strings_cannot_be_assigned_by_index() {
+    let mut state = State::new();
+    assert!(matches!(
+        run_on(&mut state, "s = \"abc\"; t = s; t[0] = \"x\";"),
+        Err(RuntimeError::TypeError(_))
+    ));
+    assert_eq!(run_on(&mut state, "return s;"), Ok(Some(string("abc"))));
+}
+
+#[test]
+fn unsupported_operands_share_an_error_format() {
+    for op in ["+", "-", "*"] {
+        assert_eq!(
+            eval_err(&format!("1 {op} true")),
+            RuntimeError::type_error(format!(
+                "unsupported operand types for {op}: 'int' and 'bool'"
+            ))
+        );
+        assert_eq!(
+            eval_err(&format!("table() {op} nil")),
+            RuntimeError::type_error(format!(
+                "unsupported operand types for {op}: 'table' and 'nil'"
+            ))
+        );
+    }
+}
+
+#[test]
+fn comparisons_are_derived_from_lt_and_eq() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "m = table();
+        m.__lt__ = fn(x, y) { x.v < y.v };
+        m.__eq__ = fn(x, y) { x.v == y.v };
+        new = fn(v) { t = table(); t.v = v; setmetatable(t, m); t };
+        one = new(1);
+        two = new(2);
+        also_one = new(1);",
+    )
+    .unwrap();
+    for (expression, expected) in [
+        ("one < two", true),
+        ("two < one", false),
+        ("one > two", false),
+        ("two > one", true),
+        ("one <= also_one", true),
+        ("two <= one", false),
+        ("one >= also_one", true),
+        ("one >= two", false),
+        ("one == also_one", true),
+        ("one == two", false),
+        ("one != two", true),
+        ("one != also_one", false),
+    ] {
+        assert_eq!(
+            run_on(&mut state, &format!("return {expression};")),
+            Ok(Some(boolean(expected))),
+            "{expression}"
+        );
+    }
+}
+
+#[test]
+fn with_closes_the_resource_on_exit_and_on_error() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "closed = table(); closed.n = 0;
+        resource = table();
+        resource.__close__ = fn(self) { closed.n = closed.n + 1; };",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "with resource as r { x = 1; } return closed.n;"),
+        Ok(Some(int(1)))
+    );
+    assert!(matches!(
+        run_on(&mut state, "with resource as r { x = 1 + nil; }"),
+        Err(RuntimeError::TypeError(_))
+    ));
+    assert_eq!(run_on(&mut state, "return closed.n;"), Ok(Some(int(2))));
+}
+
+#[test]
+fn chained_comparisons_evaluate_the_middle_once() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "calls = table(); calls.n = 0;
+        two = fn() { calls.n = calls.n + 1; 2 };",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "return 1 < two() <= 3;"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(run_on(&mut state, "return calls.n;"), Ok(Some(int(1))));
+    assert_eq!(
+        run_on(&mut state, "return 3 < two() < 5;"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return 1 < two() < 2;"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(run_on(&mut state, "return calls.n;"), Ok(Some(int(3))));
+    assert_eq!(eval("1 < 2 < 3 < 4"), boolean(true));
+}
+
+#[test]
+fn nested_chained_comparisons_keep_their_operands_on_the_stack() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "pick = fn(condition, x, y) { if condition { return x; } return y; };",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "return 1 < pick(2 < 3 < 4, 2, 9) < 3;"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return 1 < pick(4 < 3 < 5, 2, 9) < 3;"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(
+        run_on(
+            &mut state,
+            "return 0 < pick(1 < 2 < 3, 1, 5) < pick(1 < 2 > 3, 0, 2) <= 2;"
+        ),
+        Ok(Some(boolean(true)))
+    );
+
+    // No hidden variables are left behind
+    assert!(state
+        .current_locals()
+        .iter()
+        .all(|(name, _)| name != "<chain>"));
+}
+
+#[test]
+fn repeat_runs_the_body_n_times() {
+    assert_eq!(eval("do { n = 0; repeat 5 { n++; } n }"), int(5));
+    assert_eq!(eval("do { n = 0; repeat 0 { n++; } n }"), int(0));
+    assert_eq!(
+        eval("do { n = 0; repeat 10 { n++; if n == 3 { break; } } n }"),
+        int(3)
+    );
+    assert_eq!(
+        eval("do { n = 0; odd = 0; repeat 5 { n++; if n % 2 == 0 { continue; } odd++; } odd }"),
+        int(3)
+    );
+}
+
+#[test]
+fn overflowing_integer_arithmetic_is_promoted_to_float() {
+    assert_eq!(eval("math.int_max + 1"), float(i64::MAX as f64 + 1.0));
+    assert_eq!(eval("math.int_min - 1"), float(i64::MIN as f64 - 1.0));
+    assert_eq!(eval("math.int_max * 2"), float(i64::MAX as f64 * 2.0));
+    assert_eq!(eval("math.int_max - 1 + 1"), int(i64::MAX));
+    assert_eq!(eval("math.inf > math.float_max"), boolean(true));
+    assert_eq!(eval("-math.inf < -math.float_max"), boolean(true));
+}
+
+#[test]
+fn tables_are_keyed_on_integers_strings_and_booleans() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "t = table(); t[1] = \"one\"; t[\"1\"] = \"string one\"; t.a = 2; t[true] = 3;",
+    )
+    .unwrap();
+    assert_eq!(run_on(&mut state, "return t[1];"), Ok(Some(string("one"))));
+    assert_eq!(
+        run_on(&mut state, "return t[\"1\"];"),
+        Ok(Some(string("string one")))
+    );
+    assert_eq!(run_on(&mut state, "return t[\"a\"];"), Ok(Some(int(2))));
+    assert_eq!(run_on(&mut state, "return t[true];"), Ok(Some(int(3))));
+    assert_eq!(
+        run_on(&mut state, "return t[0 + 1];"),
+        Ok(Some(string("one")))
+    );
+
+    for source in ["t[1.5] = 0;", "return t[1.0];"] {
+        assert_eq!(
+            run_on(&mut state, source).unwrap_err().to_string(),
+            "type error: unhashable key type 'float'"
+        );
+    }
+}
+
+#[test]
+fn increment_and_decrement_statements() {
+    assert_eq!(run("x = 1; x++; x++; x--; return x;"), Ok(Some(int(2))));
+    assert_eq!(run("x = 1.5; x++; return x;"), Ok(Some(float(2.5))));
+    assert_eq!(run("x = 0; x--; x--; return x;"), Ok(Some(int(-2))));
+    assert_eq!(
+        run("n = 0; for (i = 0; i < 3; i++) { n--; } return n;"),
+        Ok(Some(int(-3)))
+    );
+
+    for source in ["x = \"a\"; x++;", "x = nil; x--;", "x = [1]; x++;"] {
+        assert!(
+            matches!(run(source), Err(RuntimeError::TypeError(_))),
+            "{source}"
+        );
+    }
+}
+
+#[test]
+fn indexing_a_non_table_names_the_accessed_expression() {
+    assert_eq!(
+        eval_err("a.b").to_string(),
+        "type error: attempt to index nil value 'a'"
+    );
+    assert_eq!(
+        run("a = table(); a.b = 1; return a.b.c.d;")
+            .unwrap_err()
+            .to_string(),
+        "type error: attempt to index int value 'a.b'"
+    );
+    assert_eq!(
+        eval_err("[1, 2].x").to_string(),
+        "type error: attempt to index list value"
+    );
+}
+
+#[test]
+fn loop_else_runs_only_when_the_loop_finishes_without_break() {
+    assert_eq!(
+        run("n = 0; for (i = 0; i < 3; i++) { n++; } else { n = -n; } return n;"),
+        Ok(Some(int(-3)))
+    );
+    assert_eq!(
+        run("n = 0; for (i = 0; i < 3; i++) { if i == 1 { break; } n++; } else { n = -n; } return n;"),
+        Ok(Some(int(1)))
+    );
+    assert_eq!(
+        run("x = 0; while x < 2 { x++; } else { x = 10; } return x;"),
+        Ok(Some(int(10)))
+    );
+    assert_eq!(
+        run("x = 0; while true { x++; if x == 2 { break; } } else { x = 10; } return x;"),
+        Ok(Some(int(2)))
+    );
+    // A loop which never runs its body still finishes normally
+    assert_eq!(
+        run("x = 0; while false { } else { x = 10; } return x;"),
+        Ok(Some(int(10)))
+    );
+}
+
+#[test]
+fn stacked_prefix_operators() {
+    assert_eq!(eval("+5"), int(5));
+    assert_eq!(eval("--5"), int(5));
+    assert_eq!(eval("-+-5"), int(5));
+    assert_eq!(eval("not not true"), boolean(true));
+    assert_eq!(eval("-math.int_min"), float(-(i64::MIN as f64)));
+    assert!(matches!(eval_err("+\"a\""), RuntimeError::TypeError(_)));
+    assert!(matches!(eval_err("-\"a\""), RuntimeError::TypeError(_)));
+    assert!(matches!(eval_err("-nil"), RuntimeError::TypeError(_)));
+}
+
+#[test]
+fn failed_asserts_report_their_condition() {
+    assert_eq!(
+        run("x = 5; assert(x > 10);"),
+        Err(RuntimeError::AssertionFailed("x > 10 (x = 5)".to_owned()))
+    );
+    assert_eq!(
+        run("x = 5; assert(x > 10, \"too small\");"),
+        Err(RuntimeError::AssertionFailed("too small".to_owned()))
+    );
+    assert_eq!(run("assert(1 < 2);"), Ok(None));
+}
+
+#[test]
+fn asserts_dont_depend_on_the_assert_global() {
+    assert_eq!(
+        run("assert = 5; x = 1; assert(x == 2);"),
+        Err(RuntimeError::AssertionFailed("x == 2 (x = 1)".to_owned()))
+    );
+
+    let mut state = State::with_modules(&[]);
+    assert_eq!(run_on(&mut state, "assert(true);"), Ok(None));
+    assert_eq!(
+        run_on(&mut state, "f = fn(y) { assert(y > 0, \"bad\"); };  f(-1);"),
+        Err(RuntimeError::AssertionFailed("bad".to_owned()))
+    );
+    assert_eq!(
+        run_on(&mut state, "f = fn(y) { z = 2; assert(y > z); }; f(1);"),
+        Err(RuntimeError::AssertionFailed(
+            "y > z (y = 1, z = 2)".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn xor_is_logical_for_booleans_and_bitwise_for_integers() {
+    assert_eq!(eval("true xor false"), boolean(true));
+    assert_eq!(eval("true xor true"), boolean(false));
+    assert_eq!(eval("false xor false"), boolean(false));
+    assert_eq!(eval("5 xor 3"), int(6));
+    assert_eq!(eval("-1 xor 0"), int(-1));
+    assert!(matches!(eval_err("true xor 1"), RuntimeError::TypeError(_)));
+}
+
+#[test]
+fn indexing_a_string_gives_a_character() {
+    assert_eq!(eval("\"abc\"[0]"), string("a"));
+    assert_eq!(eval("\"abc\"[-1]"), string("c"));
+    assert_eq!(eval("\"héllo\"[1]"), string("é"));
+    assert_eq!(eval("\"日本語\"[2]"), string("語"));
+    assert_eq!(
+        eval_err("\"héllo\"[5]"),
+        RuntimeError::IndexOutOfRange { index: 5, len: 5 }
+    );
+}
+
+#[test]
+fn do_blocks_scope_new_variables_and_update_existing_ones() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "x = 1; y = do { tmp = 20; x = x + 1; tmp + x };",
+    )
+    .unwrap();
+    assert_eq!(run_on(&mut state, "return y;"), Ok(Some(int(22))));
+    assert_eq!(run_on(&mut state, "return x;"), Ok(Some(int(2))));
+    // Variables first assigned in the block don't leak
+    assert_eq!(
+        run_on(&mut state, "return tmp == nil;"),
+        Ok(Some(boolean(true)))
+    );
+
+    // Nested blocks update the variables of the function they are in, not its caller's
+    let source = "
+        count = 0;
+        f = fn() { count = 10; do { do { count++; 0 }; 0 }; return count; };
+    ";
+    run_on(&mut state, source).unwrap();
+    assert_eq!(run_on(&mut state, "return f();"), Ok(Some(int(11))));
+    assert_eq!(run_on(&mut state, "return count;"), Ok(Some(int(0))));
+}
+
+#[test]
+fn continue_and_break_inside_an_if_affect_the_enclosing_loop() {
+    let source = "
+        sum_odd_below = fn(max) {
+            total = 0;
+            for (i = 0; i < 10; i++) {
+                if i % 2 == 0 {
+                    continue;
+                }
+                if i >= max {
+                    break;
+                } else {
+                    total = total + i;
+                }
+            }
+            return total;
+        };
+        return sum_odd_below(7);
+    ";
+    assert_eq!(run(source), Ok(Some(int(1 + 3 + 5))));
+
+    // The same at the top level of a script, and with nested conditionals
+    let source = "
+        visited = 0;
+        for (i = 0; i < 10; i++) {
+            if i > 2 {
+                if i == 4 {
+                    continue;
+                }
+                if i == 6 {
+                    break;
+                }
+            }
+            visited = visited + 1;
+        }
+        return string(visited) + \" up to \" + string(i);
+    ";
+    assert_eq!(run(source), Ok(Some(string("5 up to 6"))));
+}
+
+#[test]
+fn top_level_return_stops_the_script() {
+    let mut state = State::new();
+    let source = "
+        ran = 1;
+        return ran * 10;
+        ran = 2;
+        print(\"unreachable\");
+    ";
+    assert_eq!(run_on(&mut state, source), Ok(Some(int(10))));
+    assert_eq!(run_on(&mut state, "return ran;"), Ok(Some(int(1))));
+
+    // Also from inside loops and conditionals at the top level
+    let source = "
+        for (i = 0; i < 10; i++) {
+            if i == 3 {
+                return i;
+            }
+            ran = i;
+        }
+        ran = 100;
+    ";
+    assert_eq!(run_on(&mut state, source), Ok(Some(int(3))));
+    assert_eq!(run_on(&mut state, "return ran;"), Ok(Some(int(2))));
+}
+
+#[test]
+fn trailing_expressions_of_function_bodies_are_returned() {
+    assert_eq!(eval("fn(x) { x * x }(7)"), int(49));
+    assert_eq!(eval("fn(x) { y = x + 1; y * 2 }(4)"), int(10));
+    assert_eq!(eval("fn() { \"done\" }()"), string("done"));
+    // Explicit returns still work, including before the trailing expression
+    assert_eq!(eval("fn(x) { return x + 1; }(1)"), int(2));
+    assert_eq!(
+        eval("fn(x) { if x < 0 { return 0; } x }(-5) + fn(x) { if x < 0 { return 0; } x }(5)"),
+        int(5)
+    );
+    // Only the final expression is returned
+    assert_eq!(eval("fn(x) { x * x; x + 1 }(3)"), int(4));
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "f = fn(x) { x * x; y = 1; }; g = fn(x) { y = 1; x * x };",
+    )
+    .unwrap();
+    let results = |state: &mut State, name: &str| {
+        state.load(name);
+        let function = state.pop().unwrap();
+        call_function(state, &function, &[utilities::int(3)]).unwrap()
+    };
+    assert_eq!(results(&mut state, "f"), 0);
+    assert_eq!(results(&mut state, "g"), 1);
+}
+
+#[test]
+fn values_returned_from_nested_blocks_reach_the_caller() {
+    let source = "
+        find = fn(items, target) {
+            for (i = 0; i < len(items); i++) {
+                while true {
+                    if items[i] == target {
+                        do {
+                            found = i;
+                            return found * 10;
+                        };
+                    }
+                    break;
+                }
+            }
+            return -1;
+        };
+        return find([5, 6, 7], 7) + find([5], 9);
+    ";
+    assert_eq!(run(source), Ok(Some(int(19))));
+
+    // The operands the caller is in the middle of are kept apart from the returned values
+    let source = "
+        pick = fn(x) {
+            loop {
+                if x > 0 {
+                    return [x, do { y = x + 1; y }];
+                }
+                return nil;
+            }
+        };
+        total = 0;
+        for (i = 1; i <= 3; i++) {
+            total = total + pick(i)[0] * pick(i)[1];
+        }
+        return 1000 + total;
+    ";
+    assert_eq!(run(source), Ok(Some(int(1000 + 2 + 6 + 12))));
+}
@@ -0,0 +1,542 @@
+//! Tests for the embedding API of the runtime, used from Rust.
+mod common;
+
+use common::{int, string};
+use std::{
+    io::{BufRead, Cursor, Read, Write},
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+};
+
+use scriptyscript::{
+    compiler::compile,
+    runtime::{
+        bytecode::{Bytecode, OpCode},
+        error::RuntimeError,
+        state::State,
+        types::{object::ObjectInner, primitive::Primitive, utilities},
+    },
+    stdlib::Module,
+};
+
+#[test]
+fn bytecode_compiled_once_runs_against_different_globals() {
+    let bytecode = compile("x * 2;").unwrap();
+    let mut state = State::new();
+
+    state.set_global("x", utilities::int(3));
+    let first = state.run(&bytecode).unwrap().unwrap();
+    state.set_global("x", utilities::int(5));
+    let second = state.run(&bytecode).unwrap().unwrap();
+
+    assert_eq!(first.as_primitive(), Some(int(6)));
+    assert_eq!(second.as_primitive(), Some(int(10)));
+}
+
+#[test]
+fn typed_pops_report_an_empty_stack() {
+    let mut state = State::new();
+    let underflow = RuntimeError::StackUnderflow {
+        requested: 1,
+        available: 0,
+    };
+    assert_eq!(state.pop_int(), Err(underflow.clone()));
+    assert_eq!(state.pop_string(), Err(underflow));
+
+    state.push_int(4);
+    state.push_string("x");
+    assert_eq!(state.pop_string(), Ok("x".to_owned()));
+    assert_eq!(state.pop_float(), Ok(4.0));
+    state.push(&utilities::nil());
+    assert!(matches!(state.pop_bool(), Err(RuntimeError::TypeError(_))));
+}
+
+#[test]
+fn nested_exec_shares_the_limits_of_the_outer_run() {
+    let mut state = State::new();
+    state.set_instruction_limit(Some(2000));
+    // Each exec is well under the limit, but together they exceed it
+    let bytecode = compile(
+        r#"for (i = 0; i < 1000; i++) {
+            exec("for (j = 0; j < 10; j++) { }");
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        state.run(&bytecode),
+        Err(RuntimeError::InstructionLimit(2000))
+    );
+}
+
+#[test]
+fn forked_states_have_separate_globals() {
+    let mut state = State::new();
+    state.run(&compile("x = 1;").unwrap()).unwrap();
+
+    let mut fork = state.fork();
+    let x = fork
+        .run(&compile("x = x + 1; len([x]) + x;").unwrap())
+        .unwrap();
+    assert_eq!(x.unwrap().as_primitive(), Some(int(3)));
+
+    let x = state.run(&compile("x;").unwrap()).unwrap();
+    assert_eq!(x.unwrap().as_primitive(), Some(int(1)));
+}
+
+#[test]
+fn small_integers_and_booleans_are_shared() {
+    assert!(utilities::int(5).ptr_eq(&utilities::int(5)));
+    assert!(utilities::int(-128).ptr_eq(&utilities::int(-128)));
+    assert!(!utilities::int(256).ptr_eq(&utilities::int(256)));
+    assert!(utilities::boolean(true).ptr_eq(&utilities::boolean(true)));
+
+    // Updating a variable must not change the shared object
+    let mut state = State::new();
+    state
+        .run(&compile("x = 5; x++; x--; x++;").unwrap())
+        .unwrap();
+    assert_eq!(utilities::int(5).as_primitive(), Some(int(5)));
+}
+
+#[test]
+fn step_hook_can_inspect_the_locals_of_a_call() {
+    let seen = Arc::new(Mutex::new(None));
+    let mut state = State::new();
+    let hook_seen = seen.clone();
+    state.set_step_hook(Box::new(move |opcode, state| {
+        if let OpCode::Call {
+            name: Some(name), ..
+        } = opcode
+        {
+            if name.as_ref() == "print" {
+                let locals: Vec<_> = state
+                    .current_locals()
+                    .into_iter()
+                    .map(|(name, value)| (name, value.as_primitive()))
+                    .collect();
+                *hook_seen.lock().unwrap() = Some((state.depth(), locals));
+            }
+        }
+        ControlFlow::Continue(())
+    }));
+    let bytecode = compile("f = fn(a) { b = a * 2; print(b); }; f(21);").unwrap();
+    state.run(&bytecode).unwrap();
+
+    let (depth, locals) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(depth, 2);
+    assert_eq!(
+        locals,
+        vec![
+            ("a".to_owned(), Some(int(21))),
+            ("b".to_owned(), Some(int(42))),
+        ]
+    );
+}
+
+#[test]
+fn step_hook_counts_opcodes_and_can_abort() {
+    let count = Arc::new(Mutex::new(0));
+    let mut state = State::new();
+    let hook_count = count.clone();
+    state.set_step_hook(Box::new(move |_, _| {
+        *hook_count.lock().unwrap() += 1;
+        ControlFlow::Continue(())
+    }));
+    let bytecode = compile("x = 1; y = x + 2;").unwrap();
+    state.run(&bytecode).unwrap();
+    assert_eq!(
+        *count.lock().unwrap(),
+        bytecode.instruction_count(),
+        "{bytecode:?}"
+    );
+
+    state.set_step_hook(Box::new(|_, _| ControlFlow::Break(())));
+    assert_eq!(state.run(&bytecode), Err(RuntimeError::Aborted));
+
+    // A state with a hook can still be moved to another thread
+    let handle = std::thread::spawn(move || state.run(&bytecode));
+    assert_eq!(handle.join().unwrap(), Err(RuntimeError::Aborted));
+}
+
+#[test]
+fn bytecode_metrics_count_nested_bodies() {
+    let flat = compile("x = 1;").unwrap();
+    assert_eq!((flat.instruction_count(), flat.max_depth()), (2, 0));
+
+    // `while`, `load x`, `loop`, `break`, then `push_function` with a body of 3, then `store f`
+    let nested = compile("while x { loop { break; } } f = fn(n) { return n; };").unwrap();
+    assert_eq!((nested.instruction_count(), nested.max_depth()), (9, 2));
+
+    let function_in_loop =
+        compile("for (i = 0; i < 3; i++) { g = fn() { while true { } }; }").unwrap();
+    assert_eq!(
+        (
+            function_in_loop.instruction_count(),
+            function_in_loop.max_depth()
+        ),
+        (14, 3)
+    );
+}
+
+#[test]
+fn popping_too_many_frames_keeps_the_global_frame() {
+    let mut state = State::new();
+    state.set_global("x", utilities::int(7));
+    state.push_frame();
+    assert_eq!(state.depth(), 2);
+    for _ in 0..3 {
+        state.pop_frame();
+    }
+    assert_eq!(state.depth(), 1);
+    let x = state.run(&compile("x;").unwrap()).unwrap();
+    assert_eq!(x.unwrap().as_primitive(), Some(int(7)));
+}
+
+#[test]
+fn scripts_read_injected_arguments_and_environment() {
+    let mut state = State::new();
+    state.set_args(["first", "second"]);
+    let bytecode = compile("args[0] + \",\" + args[1] + \":\" + string(len(args));").unwrap();
+    let result = state.run(&bytecode).unwrap().unwrap();
+    assert_eq!(result.as_primitive(), Some(string("first,second:2")));
+
+    // Cargo sets the package name for the tests it runs
+    let bytecode = compile("env(\"CARGO_PKG_NAME\");").unwrap();
+    let result = state.run(&bytecode).unwrap().unwrap();
+    assert_eq!(result.as_primitive(), Some(string(env!("CARGO_PKG_NAME"))));
+    let bytecode = compile("env(\"SCRIPTYSCRIPT_UNSET_VARIABLE\");").unwrap();
+    let result = state.run(&bytecode).unwrap().unwrap();
+    assert_eq!(result.as_primitive(), Some(Primitive::Nil));
+}
+
+#[test]
+fn running_source_gives_the_value_of_its_last_expression() {
+    let mut state = State::new();
+    let value = state.run(&compile("1; 2 + 3;").unwrap()).unwrap();
+    assert_eq!(value.unwrap().as_primitive(), Some(int(5)));
+    assert!(state.run(&compile("x = 1;").unwrap()).unwrap().is_none());
+    assert_eq!(state.operand_stack_size(), 0);
+}
+
+#[test]
+fn bytecode_text_round_trips() {
+    let bytecode = compile(
+        "for (i = 0; i < 3; i++) {
+            if i == 1 { print(\"one\\n\"); } else { continue; }
+        }
+        while x { break; } else { x = nil; }",
+    )
+    .unwrap();
+    let text = bytecode.to_text();
+    let parsed = Bytecode::from_text(&text).unwrap();
+    assert_eq!(parsed, bytecode);
+    assert_eq!(parsed.to_text(), text);
+
+    let golden = "\
+push_int 0
+store \"i\"
+while
+  condition:
+    load \"i\"
+    push_int 3
+    binary <
+  body:
+    push_string \"a\\tb\"
+    load \"print\"
+    call 1 \"print\"
+    if
+      condition:
+        push_bool true
+      body:
+        break
+      else:
+";
+    assert_eq!(Bytecode::from_text(golden).unwrap().to_text(), golden);
+}
+
+/// Sum the integers below `n` in a loop, storing to a local variable each iteration.
+const SUM_BELOW: &str = "
+    total = 0;
+    for (i = 0; i < n; i++) {
+        total = total + i;
+    }
+";
+
+#[test]
+fn function_locals_in_slots_match_variables_by_name() {
+    let function = compile(format!("f = fn(n) {{ {SUM_BELOW} return total; }};")).unwrap();
+    let OpCode::PushFunction { body, locals } = &function.inner()[0] else {
+        panic!("expected a function, got {:?}", function.inner()[0]);
+    };
+    assert_eq!(&locals[..], ["n", "total", "i"]);
+    assert!(body
+        .iter()
+        .all(|op| !matches!(op, OpCode::Load(_) | OpCode::Store(_))));
+
+    let mut state = State::new();
+    state.run(&function).unwrap();
+    let by_slot = common::run_on(&mut state, "return f(100);");
+    let by_name = common::run(&format!("n = 100; {SUM_BELOW} return total;"));
+    assert_eq!(by_slot, Ok(Some(int(4950))));
+    assert_eq!(by_slot, by_name);
+
+    // Nested scopes still find the locals by name
+    assert_eq!(
+        common::run("f = fn(x) { y = x + 1; return do { z = y * 2; z }; }; return f(1);"),
+        Ok(Some(int(4)))
+    );
+}
+
+#[test]
+#[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+fn benchmark_locals_in_slots_against_globals() {
+    use std::time::Instant;
+
+    let mut state = State::new();
+    state.set_global("n", utilities::int(1_000_000));
+    let function = compile(format!("f = fn(n) {{ {SUM_BELOW} return total; }};")).unwrap();
+    state.run(&function).unwrap();
+    let in_function = compile("f(n);").unwrap();
+    let at_top_level = compile(SUM_BELOW).unwrap();
+
+    for (label, bytecode) in [("slots", &in_function), ("globals", &at_top_level)] {
+        let start = Instant::now();
+        state.run(bytecode).unwrap();
+        println!("{label}: {:?}", start.elapsed());
+    }
+}
+
+/// Make a table which contains itself, returning a weak reference to it once the
+/// script no longer references it.
+fn make_cycle(state: &mut State) -> std::sync::Weak<Mutex<ObjectInner>> {
+    let a = state.run(&compile("a = table(); a.me = a; a;").unwrap());
+    let cycle = Arc::downgrade(&a.unwrap().unwrap().inner);
+    state.run(&compile("a = nil;").unwrap()).unwrap();
+    cycle
+}
+
+#[test]
+fn each_state_collects_its_own_cycles() {
+    let mut a = State::new();
+    let mut b = State::new();
+    let in_a = make_cycle(&mut a);
+    let in_b = make_cycle(&mut b);
+
+    assert_eq!(a.collect_garbage(), 1);
+    assert!(in_a.upgrade().is_none());
+    assert!(in_b.upgrade().is_some());
+    assert_eq!(b.collect_garbage(), 1);
+    assert!(in_b.upgrade().is_none());
+
+    // Forked states share the heap
+    let mut forked = a.fork();
+    let in_forked = make_cycle(&mut forked);
+    assert_eq!(a.collect_garbage(), 1);
+    assert!(in_forked.upgrade().is_none());
+}
+
+#[test]
+fn locked_objects_are_not_collected() {
+    let mut state = State::new();
+    let cycle = make_cycle(&mut state);
+    let object = cycle.upgrade().unwrap();
+    let guard = object.lock().unwrap();
+    assert_eq!(state.collect_garbage(), 0);
+    drop(guard);
+    drop(object);
+    assert_eq!(state.collect_garbage(), 1);
+    assert!(cycle.upgrade().is_none());
+}
+
+#[test]
+fn globals_created_outside_a_run_are_collected() {
+    let mut state = State::new();
+    let table = utilities::table();
+    let cycle = Arc::downgrade(&table.inner);
+    state.set_global("a", table);
+    state.run(&compile("a.me = a; a = nil;").unwrap()).unwrap();
+    assert_eq!(state.collect_garbage(), 1);
+    assert!(cycle.upgrade().is_none());
+}
+
+#[test]
+fn states_only_have_the_chosen_stdlib_modules() {
+    let mut core = State::with_modules(&[Module::Core]);
+    assert!(core.run(&compile("print(len([1, 2]));").unwrap()).is_ok());
+    for absent in [
+        "max", "abs", "math", "split", "input", "import", "clock", "exit",
+    ] {
+        let source = format!("return {absent} == nil;");
+        assert_eq!(
+            common::run_on(&mut core, &source),
+            Ok(Some(Primitive::Boolean(true)))
+        );
+    }
+    assert!(matches!(
+        core.run(&compile("max(1, 2);").unwrap()),
+        Err(RuntimeError::TypeError(_))
+    ));
+
+    let mut with_math = State::with_modules(&[Module::Core, Module::Math]);
+    assert_eq!(
+        common::run_on(&mut with_math, "return max(1, 2);"),
+        Ok(Some(int(2)))
+    );
+    assert_eq!(
+        common::run_on(&mut with_math, "return input == nil;"),
+        Ok(Some(Primitive::Boolean(true)))
+    );
+}
+
+/// The marker of the first line of a coverage report which ends with the opcode.
+fn coverage_marker(report: &str, opcode: &str) -> char {
+    let line = report
+        .lines()
+        .find(|line| line.ends_with(opcode))
+        .unwrap_or_else(|| panic!("no {opcode} in the report:\n{report}"));
+    line.chars().next().unwrap()
+}
+
+#[test]
+fn coverage_reports_an_else_which_is_never_taken() {
+    let bytecode = compile(
+        r#"
+        sign = fn(x) {
+            if x >= 0 {
+                return "positive";
+            } else {
+                return "negative";
+            }
+        };
+        a = sign(1);
+        b = sign(2);
+        "#,
+    )
+    .unwrap();
+    let mut state = State::new();
+    state.enable_coverage();
+    state.run(&bytecode).unwrap();
+
+    let report = state.coverage_report(&bytecode).unwrap();
+    assert!(0 < report.covered() && report.covered() < report.total());
+    let text = report.to_string();
+    assert_eq!(coverage_marker(&text, r#"push_string "positive""#), '+');
+    assert_eq!(coverage_marker(&text, r#"push_string "negative""#), '-');
+}
+
+#[test]
+fn coverage_is_not_credited_to_bytecode_which_never_ran() {
+    let mut state = State::new();
+    state.enable_coverage();
+    // Memory freed by bytecode which ran may be reused by bytecode which didn't
+    for _ in 0..10 {
+        let bytecode = compile("f = fn() { return 1; }; f();").unwrap();
+        state.run(&bytecode).unwrap();
+    }
+    let bytecode = compile("f = fn() { return 1; }; f();").unwrap();
+    let report = state.coverage_report(&bytecode).unwrap();
+    assert_eq!(report.covered(), 0);
+    assert!(report.total() > 0);
+
+    // Running a clone counts for the original
+    state.run(&bytecode.clone()).unwrap();
+    let report = state.coverage_report(&bytecode).unwrap();
+    assert_eq!(report.covered(), report.total());
+}
+
+/// What happened to the streams of a state, in order.
+type StreamLog = Arc<Mutex<Vec<String>>>;
+
+/// An output sink which keeps what is written until it is flushed, then logs it.
+struct CapturingSink {
+    pending: String,
+    log: StreamLog,
+}
+
+impl Write for CapturingSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.push_str(std::str::from_utf8(buf).unwrap());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.pending.is_empty() {
+            self.log
+                .lock()
+                .unwrap()
+                .push(std::mem::take(&mut self.pending));
+        }
+        Ok(())
+    }
+}
+
+/// An input source which logs each time it is read from.
+struct LoggingSource {
+    input: Cursor<&'static str>,
+    log: StreamLog,
+}
+
+impl Read for LoggingSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.log.lock().unwrap().push("<read>".to_owned());
+        self.input.read(buf)
+    }
+}
+
+impl BufRead for LoggingSource {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.log.lock().unwrap().push("<read>".to_owned());
+        self.input.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.input.consume(amount);
+    }
+}
+
+/// Make a state with captured streams, giving the log of what happened to them.
+fn capturing_state(input: &'static str, buffered: bool) -> (State, StreamLog) {
+    let log = StreamLog::default();
+    let mut state = State::new();
+    state.set_output(Box::new(CapturingSink {
+        pending: String::new(),
+        log: log.clone(),
+    }));
+    state.set_input(Box::new(LoggingSource {
+        input: Cursor::new(input),
+        log: log.clone(),
+    }));
+    state.set_output_buffered(buffered);
+    (state, log)
+}
+
+#[test]
+fn output_is_flushed_before_an_input_prompt() {
+    let source = "
+        print(\"first\");
+        print(\"second\", 2);
+        name = input(\"name? \");
+        print(\"hello \" + name);
+        return name;
+    ";
+
+    // Unbuffered output is flushed after each print
+    let (mut state, log) = capturing_state("bob\n", false);
+    assert_eq!(common::run_on(&mut state, source), Ok(Some(string("bob"))));
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["first\n", "second2\n", "name? ", "<read>", "hello bob\n"]
+    );
+
+    // Buffered output is still flushed before reading input, but not after each print
+    let (mut state, log) = capturing_state("bob\n", true);
+    assert_eq!(common::run_on(&mut state, source), Ok(Some(string("bob"))));
+    assert_eq!(*log.lock().unwrap(), ["first\nsecond2\nname? ", "<read>"]);
+    common::run_on(&mut state, "flush();").unwrap();
+    assert_eq!(log.lock().unwrap().last().unwrap(), "hello bob\n");
+
+    // Forked states share the streams
+    let (state, log) = capturing_state("", false);
+    common::run_on(&mut state.fork(), "print(\"from a fork\");").unwrap();
+    assert_eq!(*log.lock().unwrap(), ["from a fork\n"]);
+}
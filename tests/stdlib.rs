@@ -0,0 +1,1171 @@
+//! Tests for the builtins of the standard library, called from scripts.
+mod common;
+
+use common::{boolean, eval, eval_err, float, int, run, run_on, string};
+use scriptyscript::{
+    compiler::compile,
+    runtime::{
+        error::RuntimeError,
+        state::State,
+        types::{primitive::Primitive, utilities},
+    },
+};
+
+#[test]
+fn clamp() {
+    assert_eq!(eval("clamp(-5, 0, 10)"), int(0));
+    assert_eq!(eval("clamp(5, 0, 10)"), int(5));
+    assert_eq!(eval("clamp(15, 0, 10)"), int(10));
+    assert_eq!(eval("clamp(0.5, 0, 1)"), float(0.5));
+    assert_eq!(eval("clamp(1.5, 0.0, 1.0)"), float(1.0));
+    assert!(matches!(
+        eval_err("clamp(5, 10, 0)"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn group_separates_thousands() {
+    assert_eq!(eval("group(1234567)"), string("1,234,567"));
+    assert_eq!(eval("group(1234567, \".\")"), string("1.234.567"));
+    assert_eq!(eval("group(123456, \" \")"), string("123 456"));
+    assert_eq!(eval("group(-1234.5)"), string("-1,234.5"));
+    assert_eq!(eval("group(-123)"), string("-123"));
+    assert_eq!(eval("group(0)"), string("0"));
+    assert_eq!(
+        eval("group(math.int_min)"),
+        string("-9,223,372,036,854,775,808")
+    );
+    // An empty separator leaves the digits as they are
+    assert_eq!(eval("group(1234, \"\")"), string("1234"));
+
+    assert_eq!(
+        eval_err("group(\"\")").to_string(),
+        "type error: expected number, got 'string'"
+    );
+    assert!(matches!(
+        eval_err("group()"),
+        RuntimeError::ArgumentCount { .. }
+    ));
+}
+
+#[test]
+fn reading_lines_and_all_of_the_input() {
+    let mut state = State::new();
+    state.set_input(Box::new("first\nsecond\r\nrest\nof it".as_bytes()));
+    assert_eq!(
+        run_on(&mut state, "return read_line();"),
+        Ok(Some(string("first")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return read_line();"),
+        Ok(Some(string("second")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return read_all();"),
+        Ok(Some(string("rest\nof it")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return read_line() == nil;"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return read_all();"),
+        Ok(Some(string("")))
+    );
+
+    // The last line doesn't need a line ending, and an empty line isn't the end
+    state.set_input(Box::new("\nlast".as_bytes()));
+    assert_eq!(
+        run_on(&mut state, "return read_line();"),
+        Ok(Some(string("")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return read_line();"),
+        Ok(Some(string("last")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return read_line() == nil;"),
+        Ok(Some(boolean(true)))
+    );
+}
+
+#[test]
+fn abs_of_the_smallest_integer_is_promoted_to_float() {
+    assert_eq!(eval("abs(-5)"), int(5));
+    assert_eq!(eval("abs(-2.5)"), float(2.5));
+    assert_eq!(eval("abs(math.int_max)"), int(i64::MAX));
+    assert_eq!(
+        eval("abs(math.int_min)"),
+        float(9_223_372_036_854_775_808.0)
+    );
+}
+
+#[test]
+fn wrong_number_of_arguments_is_an_error() {
+    assert_eq!(
+        eval_err("clamp(1, 2)"),
+        RuntimeError::ArgumentCount {
+            expected: "3 arguments".to_owned(),
+            got: 2
+        }
+    );
+    assert_eq!(
+        eval_err("len(1, 2)").to_string(),
+        "expected 1 argument, got 2"
+    );
+    assert_eq!(
+        eval_err("max(1)").to_string(),
+        "expected at least 2 arguments, got 1"
+    );
+    assert_eq!(
+        eval_err("split()").to_string(),
+        "expected 1 to 3 arguments, got 0"
+    );
+
+    // The state is still usable after the error
+    let mut state = State::new();
+    assert!(run_on(&mut state, "x = 1; clamp(x);").is_err());
+    assert_eq!(
+        run_on(&mut state, "return clamp(x, 0, 2);"),
+        Ok(Some(int(1)))
+    );
+}
+
+#[test]
+fn sleep_blocks_for_the_duration() {
+    let elapsed = eval("do { start = clock(); sleep(0.05); clock() - start }");
+    let Primitive::Float(elapsed) = elapsed else {
+        panic!("expected a float, got {elapsed:?}");
+    };
+    assert!(elapsed >= 0.05, "slept for {elapsed}s");
+    assert_eq!(eval("do { sleep(0); 1 }"), int(1));
+    assert!(matches!(eval_err("sleep(-1)"), RuntimeError::ValueError(_)));
+}
+
+#[test]
+fn frozen_tables_can_be_read_but_not_written() {
+    let mut state = State::new();
+    run_on(&mut state, "t = table(); t.a = 1; t[2] = 3; freeze(t);").unwrap();
+    assert_eq!(run_on(&mut state, "return t.a + t[2];"), Ok(Some(int(4))));
+    assert_eq!(
+        run_on(&mut state, "return is_frozen(t);"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "t.a = 2;"),
+        Err(RuntimeError::FrozenTable("a".to_owned()))
+    );
+    assert!(matches!(
+        run_on(&mut state, "t[2] = 0;"),
+        Err(RuntimeError::FrozenTable(_))
+    ));
+    assert_eq!(run_on(&mut state, "return t.a;"), Ok(Some(int(1))));
+    assert_eq!(eval("is_frozen(table())"), boolean(false));
+}
+
+#[test]
+fn rounding_builtins_call_dunders() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "n = table(); n.x = -3; n.__abs__ = fn(self) { abs(self.x) };",
+    )
+    .unwrap();
+    assert_eq!(run_on(&mut state, "return abs(n);"), Ok(Some(int(3))));
+    assert_eq!(eval("floor(2.5)"), int(2));
+    assert_eq!(eval("round(-2.5)"), int(-3));
+}
+
+#[test]
+fn rounding_large_floats_stays_float() {
+    assert_eq!(eval("floor(1e300)"), float(1e300));
+    assert_eq!(eval("round(-1e19)"), float(-1e19));
+    assert_eq!(eval("floor(-9223372036854775808.0)"), int(i64::MIN));
+    assert!(matches!(
+        eval_err("floor(1 / 0.0)"),
+        RuntimeError::ValueError(_)
+    ));
+    assert!(matches!(
+        eval_err("round(0 / 0.0)"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+/// Write a script to a file in the temporary directory, returning its path.
+fn temp_script(name: &str, source: &str) -> String {
+    let path = std::env::temp_dir().join(format!("scriptyscript-{}-{name}", std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn dofile_runs_a_script_in_a_child_scope() {
+    let path = temp_script("dofile.ss", "x = 20; return x + 1;");
+    let mut state = State::new();
+    assert_eq!(
+        run_on(&mut state, &format!("return dofile({path:?});")),
+        Ok(Some(int(21)))
+    );
+    assert_eq!(run_on(&mut state, "return x;"), Ok(Some(Primitive::Nil)));
+
+    let broken = temp_script("dofile-broken.ss", "return 1 + nil;");
+    assert!(matches!(
+        run_on(&mut state, &format!("return dofile({broken:?});")),
+        Err(RuntimeError::InFile { path, .. }) if path == broken
+    ));
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(broken).unwrap();
+}
+
+#[test]
+fn modules_are_imported_once() {
+    let counter = temp_script(
+        "counter.ss",
+        "hits.n = hits.n + 1; m = table(); m.value = 42; return m;",
+    );
+    let module = counter.strip_suffix(".ss").unwrap();
+    let mut state = State::new();
+    run_on(&mut state, "hits = table(); hits.n = 0;").unwrap();
+    assert_eq!(
+        run_on(&mut state, &format!("return import({module:?}).value;")),
+        Ok(Some(int(42)))
+    );
+    // The same file through a different path is still the same module
+    let path = std::path::Path::new(&counter);
+    let aliased = path
+        .parent()
+        .unwrap()
+        .join(".")
+        .join(path.file_name().unwrap());
+    let aliased = aliased.to_str().unwrap();
+    assert_eq!(
+        run_on(&mut state, &format!("return import({aliased:?}).value;")),
+        Ok(Some(int(42)))
+    );
+    assert_eq!(run_on(&mut state, "return hits.n;"), Ok(Some(int(1))));
+
+    // Extensions are matched regardless of case
+    let upper = temp_script("upper.SS", "return 7;");
+    assert_eq!(
+        run_on(&mut state, &format!("return import({upper:?});")),
+        Ok(Some(int(7)))
+    );
+    std::fs::remove_file(counter).unwrap();
+    std::fs::remove_file(upper).unwrap();
+}
+
+#[test]
+fn trimming_sides_of_a_string() {
+    assert_eq!(eval("trim_start(\"  a b \\n\")"), string("a b \n"));
+    assert_eq!(eval("trim_end(\"  a b \\n\")"), string("  a b"));
+    assert_eq!(eval("trim(\"  a b \\n\")"), string("a b"));
+    assert_eq!(eval("trim_start(\"xyaxy\", \"yx\")"), string("axy"));
+    assert_eq!(eval("trim_end(\"xyaxy\", \"yx\")"), string("xya"));
+    assert_eq!(eval("trim(\"xyaxy\", \"yx\")"), string("a"));
+    assert_eq!(eval("trim(\" a \", \"\")"), string(" a "));
+}
+
+#[test]
+fn deep_equality_of_nan_and_cycles() {
+    assert_eq!(eval("deepequal([1, [2]], [1, [2]])"), boolean(true));
+    assert_eq!(eval("deepequal([0 / 0.0], [0 / 0.0])"), boolean(false));
+    assert_eq!(
+        eval("do { nan = 0 / 0.0; deepequal(nan, nan) }"),
+        boolean(false)
+    );
+
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "a = table(); a.x = 1; a.me = a;
+        b = table(); b.x = 1; b.me = b;
+        c = table(); c.x = 2; c.me = c;",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "return deepequal(a, b);"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return deepequal(a, c);"),
+        Ok(Some(boolean(false)))
+    );
+}
+
+#[test]
+fn traceback_names_each_frame() {
+    let traceback = eval(
+        "do {
+            inner = fn() { debug.traceback() };
+            outer = fn() { inner() };
+            outer()
+        }",
+    );
+    assert_eq!(
+        traceback,
+        string("traceback (most recent call last):\n  <main>\n  <do>\n  outer\n  inner")
+    );
+}
+
+#[test]
+fn format_alignment_and_padding() {
+    assert_eq!(eval(r#"format("[{:<5}]", "ab")"#), string("[ab   ]"));
+    assert_eq!(eval(r#"format("[{:>5}]", "ab")"#), string("[   ab]"));
+    assert_eq!(eval(r#"format("[{:^6}]", "ab")"#), string("[  ab  ]"));
+    assert_eq!(eval(r#"format("[{:5}]", 42)"#), string("[   42]"));
+    assert_eq!(eval(r#"format("[{:5}]", "ab")"#), string("[ab   ]"));
+    assert_eq!(eval(r#"format("{:04}", 42)"#), string("0042"));
+    assert_eq!(eval(r#"format("{:04}", -42)"#), string("-042"));
+    assert_eq!(eval(r#"format("{{{}}}", 1)"#), string("{1}"));
+}
+
+#[test]
+fn string_builders_only_mutate_their_own_buffer() {
+    assert_eq!(
+        eval(
+            "do {
+                b = string_builder.new();
+                for (i = 0; i < 100; i++) { string_builder.append(b, string(i % 10)); }
+                string_builder.build(b)
+            }"
+        ),
+        string(&"0123456789".repeat(10))
+    );
+
+    let mut state = State::new();
+    let error = run_on(
+        &mut state,
+        r#"s = "abc"; fake = table(); fake.buffer = s; string_builder.append(fake, "x");"#,
+    );
+    assert!(matches!(error, Err(RuntimeError::TypeError(_))));
+    assert_eq!(run_on(&mut state, "return s;"), Ok(Some(string("abc"))));
+    assert!(matches!(
+        eval_err(r#"string_builder.build("abc")"#),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn sum_and_product_of_lists() {
+    assert_eq!(eval("sum([1, 2, 3])"), int(6));
+    assert_eq!(eval("product([2, 3, 4])"), int(24));
+    assert_eq!(eval("sum([0.5, 0.25])"), float(0.75));
+    assert_eq!(eval("product([0.5, 0.5])"), float(0.25));
+    assert_eq!(eval("sum([1, 0.5])"), float(1.5));
+    assert_eq!(eval("product([3, 0.5])"), float(1.5));
+    assert_eq!(eval("sum([])"), int(0));
+    assert_eq!(eval("product([])"), int(1));
+    assert!(matches!(
+        eval_err("sum([1, \"2\"])"),
+        RuntimeError::TypeError(_)
+    ));
+    assert!(matches!(
+        eval_err("product([nil])"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn count_and_index_of_list_values() {
+    assert_eq!(eval("count([1, 2, 1, 1], 1)"), int(3));
+    assert_eq!(eval("count([1, 2], 3)"), int(0));
+    // Equality is the same as `==`, which doesn't convert between types
+    assert_eq!(eval("count([1, 1.0, \"1\"], 1)"), int(1));
+    assert_eq!(eval("index_of([\"a\", \"b\", \"b\"], \"b\")"), int(1));
+    assert_eq!(eval("index_of([1, 2], 3)"), Primitive::Nil);
+}
+
+#[test]
+fn concat_joins_strings_without_a_separator() {
+    assert_eq!(eval(r#"concat(["a", "bc", "", "d"])"#), string("abcd"));
+    assert_eq!(eval("concat([])"), string(""));
+    assert!(matches!(
+        eval_err(r#"concat(["a", 1])"#),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn min_by_and_max_by_select_by_key() {
+    assert_eq!(
+        eval(r#"min_by(["ccc", "a", "bb"], fn(s) { len(s) })"#),
+        string("a")
+    );
+    assert_eq!(
+        eval(r#"max_by(["ccc", "a", "bb"], fn(s) { len(s) })"#),
+        string("ccc")
+    );
+    assert_eq!(eval("min_by([3, -5, 4], fn(x) { x * x })"), int(3));
+    assert_eq!(eval("max_by([3, -5, 4], fn(x) { x * x })"), int(-5));
+}
+
+#[test]
+fn all_and_any_short_circuit() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "calls = table(); calls.n = 0;
+        positive = fn(x) { calls.n = calls.n + 1; x > 0 };",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "return all([1, -2, 3, 4], positive);"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(run_on(&mut state, "return calls.n;"), Ok(Some(int(2))));
+    assert_eq!(
+        run_on(
+            &mut state,
+            "calls.n = 0; return any([-1, 2, -3], positive);"
+        ),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(run_on(&mut state, "return calls.n;"), Ok(Some(int(2))));
+    assert_eq!(
+        run_on(&mut state, "return all([1, 2], positive);"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return any([-1, -2], positive);"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(eval("all([], fn(x) { false })"), boolean(true));
+    assert_eq!(eval("any([], fn(x) { true })"), boolean(false));
+}
+
+#[test]
+fn tonumber_keeps_integers_whole() {
+    assert_eq!(eval(r#"tonumber("42")"#), int(42));
+    assert_eq!(eval(r#"tonumber("-7")"#), int(-7));
+    assert_eq!(eval(r#"tonumber("2.5")"#), float(2.5));
+    assert_eq!(eval(r#"tonumber("1e3")"#), float(1000.0));
+    assert_eq!(eval(r#"tonumber("x")"#), Primitive::Nil);
+    assert_eq!(eval(r#"tonumber("")"#), Primitive::Nil);
+    assert_eq!(eval("tonumber(5)"), int(5));
+}
+
+#[test]
+fn approx_eq_tolerates_small_differences() {
+    assert_eq!(eval("0.1 + 0.2 == 0.3"), boolean(false));
+    assert_eq!(eval("approx_eq(0.1 + 0.2, 0.3)"), boolean(true));
+    assert_eq!(eval("approx_eq(1.0, 1.1)"), boolean(false));
+    assert_eq!(eval("approx_eq(1.0, 1.1, 0.2)"), boolean(true));
+    assert_eq!(eval("approx_eq(1.0, 1.5, 0.2)"), boolean(false));
+    assert_eq!(eval("approx_eq(3, 3)"), boolean(true));
+    assert_eq!(eval("approx_eq(3, 4)"), boolean(false));
+    assert_eq!(eval("approx_eq(0 / 0.0, 0 / 0.0)"), boolean(false));
+    assert!(matches!(
+        eval_err("approx_eq(1, 1, -1)"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn memoized_functions_run_once_per_argument() {
+    let source = "
+        calls = table();
+        calls.n = 0;
+        square = memoize(fn(x) { calls.n = calls.n + 1; return x * x; });
+        total = square(3) + square(3) + square(4) + square(4) + square(3);
+    ";
+    let mut state = State::new();
+    run_on(&mut state, source).unwrap();
+    assert_eq!(run_on(&mut state, "return calls.n;"), Ok(Some(int(2))));
+    assert_eq!(run_on(&mut state, "return total;"), Ok(Some(int(59))));
+    assert!(matches!(
+        eval_err("memoize(1)"),
+        RuntimeError::TypeError(m) if m == "expected function, got 'int'"
+    ));
+}
+
+#[test]
+fn iterating_the_characters_of_a_string() {
+    let count = "
+        n = 0;
+        last = nil;
+        cs = chars(s);
+        for (i = 0; i < len(cs); i++) {
+            n++;
+            last = cs[i];
+        }
+    ";
+    let mut state = State::new();
+    for (s, n, last) in [("hello", 5, "o"), ("héllo wörld ✓", 13, "✓")] {
+        run_on(&mut state, &format!("s = {s:?};")).unwrap();
+        run_on(&mut state, count).unwrap();
+        assert_eq!(
+            run_on(&mut state, "return n == len(s);"),
+            Ok(Some(boolean(true)))
+        );
+        assert_eq!(run_on(&mut state, "return n;"), Ok(Some(int(n))));
+        assert_eq!(run_on(&mut state, "return last;"), Ok(Some(string(last))));
+    }
+    assert_eq!(eval("len(chars(\"\"))"), int(0));
+}
+
+#[test]
+fn weak_references_do_not_keep_objects_alive() {
+    let mut state = State::new();
+    run_on(&mut state, "t = table(); t.x = 1; w = weak(t);").unwrap();
+    assert_eq!(run_on(&mut state, "return deref(w).x;"), Ok(Some(int(1))));
+    run_on(&mut state, "t = nil;").unwrap();
+    assert_eq!(
+        run_on(&mut state, "return deref(w) == nil;"),
+        Ok(Some(boolean(true)))
+    );
+
+    // A strong reference elsewhere keeps the object alive
+    run_on(
+        &mut state,
+        "t = [1, 2]; keep = table(); keep.t = t; w = weak(t); t = nil;",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "return len(deref(w));"),
+        Ok(Some(int(2)))
+    );
+    run_on(&mut state, "keep.t = nil;").unwrap();
+    assert_eq!(
+        run_on(&mut state, "return deref(w) == nil;"),
+        Ok(Some(boolean(true)))
+    );
+
+    assert!(matches!(eval_err("deref(1)"), RuntimeError::TypeError(_)));
+}
+
+#[test]
+fn padding_strings_to_a_width() {
+    assert_eq!(eval("pad_left(\"ab\", 5)"), string("   ab"));
+    assert_eq!(eval("pad_right(\"ab\", 5)"), string("ab   "));
+    assert_eq!(eval("pad_left(\"7\", 3, \"0\")"), string("007"));
+    assert_eq!(eval("pad_right(\"ab\", 4, \"·\")"), string("ab··"));
+    // Width counts characters, not bytes
+    assert_eq!(eval("pad_left(\"é\", 2)"), string(" é"));
+    // Strings already as wide are unchanged
+    assert_eq!(eval("pad_left(\"abcdef\", 3)"), string("abcdef"));
+    assert_eq!(eval("pad_right(\"abc\", 3, \"-\")"), string("abc"));
+    assert_eq!(eval("pad_left(\"abc\", -1)"), string("abc"));
+    assert!(matches!(
+        eval_err("pad_left(\"a\", 3, \"xy\")"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn pretty_printing_spreads_nested_containers_over_lines() {
+    let mut state = State::new();
+    let source = "t = table(); t.name = \"a\"; t.items = [1, [2, 3]]; t.me = t; return t;";
+    let table = state.run(&compile(source).unwrap()).unwrap().unwrap();
+    let expected = r#"table: {
+    "items": [
+        1,
+        [
+            2,
+            3,
+        ],
+    ],
+    "me": <cycle>,
+    "name": a,
+}"#;
+    assert_eq!(format!("{table:#?}"), expected);
+}
+
+#[test]
+fn setting_checking_and_deleting_keys() {
+    let mut state = State::new();
+    run_on(
+        &mut state,
+        "t = table(); t.a = 1; t.b = nil; t[2] = \"two\";",
+    )
+    .unwrap();
+    assert_eq!(
+        run_on(&mut state, "return has(t, \"a\");"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return has(t, \"b\");"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return has(t, \"c\");"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return has(t, 2);"),
+        Ok(Some(boolean(true)))
+    );
+
+    assert_eq!(
+        run_on(&mut state, "return delete(t, \"a\");"),
+        Ok(Some(int(1)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return has(t, \"a\");"),
+        Ok(Some(boolean(false)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return t.a == nil;"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return delete(t, \"a\") == nil;"),
+        Ok(Some(boolean(true)))
+    );
+    assert_eq!(
+        run_on(&mut state, "return delete(t, 2);"),
+        Ok(Some(string("two")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return has(t, 2);"),
+        Ok(Some(boolean(false)))
+    );
+
+    // Absent keys fall back to `__missing__`, but keys set to nil don't
+    let source = "
+        defaults = table();
+        defaults.__missing__ = fn(t, key) { return key + \"?\"; };
+        d = setmetatable(table(), defaults);
+        d.present = nil;
+    ";
+    run_on(&mut state, source).unwrap();
+    assert_eq!(
+        run_on(&mut state, "return d.absent;"),
+        Ok(Some(string("absent?")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return d[\"other\"];"),
+        Ok(Some(string("other?")))
+    );
+    assert_eq!(
+        run_on(&mut state, "return d.present == nil;"),
+        Ok(Some(boolean(true)))
+    );
+}
+
+#[test]
+fn tables_are_rendered_with_sorted_keys() {
+    let source = "
+        t = table();
+        t.pear = 3;
+        t[2] = \"b\";
+        t.apple = 1;
+        t[false] = 0;
+        t.fig = [1, 2];
+        t[-7] = nil;
+        return string(t);
+    ";
+    let expected = "table: {false: 0, -7: nil, 2: b, \"apple\": 1, \"fig\": [1, 2], \"pear\": 3}";
+    for _ in 0..3 {
+        assert_eq!(
+            run_on(&mut State::new(), source),
+            Ok(Some(string(expected)))
+        );
+    }
+}
+
+#[test]
+fn printing_a_table_which_contains_itself_terminates() {
+    // A deadlock or infinite recursion would never send, so wait on another thread
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let source = "t = table(); t.me = t; t.items = [1, t]; return string(t);";
+        let _ = sender.send(run(source));
+    });
+    let printed = receiver
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .expect("printing a cyclic table should finish");
+    assert_eq!(
+        printed,
+        Ok(Some(string(
+            "table: {\"items\": [1, <cycle>], \"me\": <cycle>}"
+        )))
+    );
+}
+
+#[test]
+fn functions_and_tables_are_printed_without_their_internals() {
+    let mut state = State::new();
+    run_on(&mut state, "f = fn(x) { return x + 1; }; g = fn() {};").unwrap();
+    let printed = |state: &mut State, expression: &str| match run_on(
+        state,
+        &format!("return string({expression});"),
+    ) {
+        Ok(Some(Primitive::String(s))) => s,
+        other => panic!("expected a string, got {other:?}"),
+    };
+
+    let f = printed(&mut state, "f");
+    let id = f.strip_prefix("function #").unwrap();
+    assert!(id.parse::<u64>().is_ok(), "{f:?}");
+    assert_eq!(printed(&mut state, "f"), f);
+    assert_ne!(printed(&mut state, "g"), f);
+    assert_eq!(printed(&mut state, "[f]"), format!("[{f}]"));
+    assert_eq!(printed(&mut state, "len"), "wrapped function");
+
+    run_on(&mut state, "t = table(); t.f = f; t.n = 1;").unwrap();
+    assert_eq!(
+        printed(&mut state, "t"),
+        format!("table: {{\"f\": {f}, \"n\": 1}}")
+    );
+}
+
+#[test]
+fn apply_spreads_a_list_into_arguments() {
+    assert_eq!(eval("apply(max, [3, 9, 2])"), int(9));
+    assert_eq!(eval("apply(max, [1, 2, 3, 4])"), int(4));
+    assert_eq!(eval("apply(fn(a, b) { return a - b; }, [10, 4])"), int(6));
+    assert_eq!(eval("apply(fn() { return 1; }, [])"), int(1));
+    assert!(matches!(
+        eval_err("apply(max, 1)"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+/// Format a number with `numfmt`, given the statements which fill in its `options` table.
+fn numfmt(number: &str, options: &str) -> Primitive {
+    let source = format!("options = table(); {options} return numfmt({number}, options);");
+    run_on(&mut State::new(), &source)
+        .unwrap_or_else(|e| panic!("failed to format {number}: {e}"))
+        .unwrap()
+}
+
+#[test]
+fn numfmt_combines_base_and_width() {
+    let options = "options.base = 16; options.width = 6;";
+    assert_eq!(numfmt("255", options), string("    ff"));
+    assert_eq!(numfmt("-255", options), string("   -ff"));
+    assert_eq!(numfmt("0x1234567", options), string("1234567"));
+
+    let options = "options.base = 2; options.width = 8; options.pad = \"0\";";
+    assert_eq!(numfmt("5", options), string("00000101"));
+    assert_eq!(numfmt("-5", options), string("-0000101"));
+}
+
+#[test]
+fn numfmt_combines_precision_and_sign() {
+    let options = "options.precision = 2; options.sign = true;";
+    assert_eq!(numfmt("3.14159", options), string("+3.14"));
+    assert_eq!(numfmt("-0.5", options), string("-0.50"));
+    assert_eq!(numfmt("42", options), string("+42.00"));
+    assert_eq!(numfmt("-42", options), string("-42.00"));
+    // Integers beyond the precision of a float keep all their digits
+    assert_eq!(
+        numfmt("9007199254740993", options),
+        string("+9007199254740993.00")
+    );
+    assert_eq!(
+        numfmt("-9223372036854775807 - 1", "options.precision = 1;"),
+        string("-9223372036854775808.0")
+    );
+    assert_eq!(numfmt("7", "options.precision = 0;"), string("7"));
+    assert!(matches!(
+        eval_err("numfmt(\"7\")"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+/// Split a string with a builtin which returns a list of strings, giving the strings.
+fn split_with(builtin: &str, input: &str) -> Result<Vec<String>, RuntimeError> {
+    let mut state = State::new();
+    state.set_global("input", utilities::string(input));
+    let source = format!("parts = {builtin}(input); return len(parts);");
+    let Some(Primitive::Integer(count)) = run_on(&mut state, &source)? else {
+        panic!("{builtin} should give a list");
+    };
+    Ok((0..count)
+        .map(
+            |i| match run_on(&mut state, &format!("return parts[{i}];")) {
+                Ok(Some(Primitive::String(part))) => part,
+                other => panic!("expected a string, got {other:?}"),
+            },
+        )
+        .collect())
+}
+
+/// Split a string with `shlex`, giving the tokens.
+fn shlex(input: &str) -> Result<Vec<String>, RuntimeError> {
+    split_with("shlex", input)
+}
+
+/// Split a string with `split`, passing the other arguments given as source (e.g. `, ","`).
+fn split(input: &str, args: &str) -> Vec<String> {
+    split_with(&format!("fn(s) {{ split(s{args}) }}"), input).unwrap()
+}
+
+#[test]
+fn split_on_a_separator() {
+    assert_eq!(split("a,b,c", ", \",\""), ["a", "b", "c"]);
+    assert_eq!(split("a::b::c", ", \"::\""), ["a", "b", "c"]);
+    // Separators at the ends give empty strings, as do repeated separators
+    assert_eq!(split(",a,", ", \",\""), ["", "a", ""]);
+    assert_eq!(split("a,,b", ", \",\""), ["a", "", "b"]);
+    assert_eq!(split("a::::b:::c", ", \"::\""), ["a", "", "b", ":c"]);
+    // Without a separator in the string, there is a single part
+    assert_eq!(split("abc", ", \",\""), ["abc"]);
+    assert_eq!(split("", ", \",\""), [""]);
+    assert!(matches!(
+        eval_err("split(\"a,b\", \"\")"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn split_on_whitespace_by_default() {
+    assert_eq!(split("  a \t b\nc  ", ""), ["a", "b", "c"]);
+    assert_eq!(split("a b", ", nil"), ["a", "b"]);
+    assert!(split("", "").is_empty());
+    assert!(split(" \t ", "").is_empty());
+}
+
+#[test]
+fn split_at_most_limit_times() {
+    assert_eq!(split("a,b,c", ", \",\", 1"), ["a", "b,c"]);
+    assert_eq!(split("a,b,c", ", \",\", 5"), ["a", "b", "c"]);
+    assert_eq!(split("a,b,c", ", \",\", 0"), ["a,b,c"]);
+    assert_eq!(split(" a  b  c ", ", nil, 1"), ["a", "b  c "]);
+    assert!(matches!(
+        eval_err("split(\"a\", \",\", -1)"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn shlex_splits_like_a_shell() {
+    assert_eq!(
+        shlex("  cp   a.txt b.txt ").unwrap(),
+        ["cp", "a.txt", "b.txt"]
+    );
+    // Quoted tokens keep their spaces
+    assert_eq!(
+        shlex(r#"echo "hello world" 'one  two'"#).unwrap(),
+        ["echo", "hello world", "one  two"]
+    );
+    // Escaped quotes, inside double quotes and outside of quotes
+    assert_eq!(
+        shlex(r#"say "a \"quoted\" word" it\'s \\"#).unwrap(),
+        ["say", r#"a "quoted" word"#, "it's", "\\"]
+    );
+    // Single quotes don't have escapes, and other escapes in double quotes are kept
+    assert_eq!(shlex(r#"'a\b' "c\d""#).unwrap(), [r"a\b", r"c\d"]);
+    // Adjacent quoted and unquoted segments form one token
+    assert_eq!(
+        shlex(r#"--name="John Smith"'!' x''y"#).unwrap(),
+        ["--name=John Smith!", "xy"]
+    );
+    assert_eq!(shlex(r#"a "" ''"#).unwrap(), ["a", "", ""]);
+    assert!(shlex("").unwrap().is_empty());
+
+    for input in [r#"echo "unterminated"#, "echo 'unterminated", r"trailing\"] {
+        assert!(matches!(shlex(input), Err(RuntimeError::ValueError(_))));
+    }
+}
+
+#[test]
+fn list_creates_a_list_of_a_given_length() {
+    assert_eq!(eval("len(list(4))"), int(4));
+    assert_eq!(eval("list(4)[3] == nil"), boolean(true));
+    assert_eq!(eval("len(list(0))"), int(0));
+    assert_eq!(eval("list(3, 7)[0] + list(3, 7)[2]"), int(14));
+    assert_eq!(eval("string(list(2, \"x\"))"), string("[x, x]"));
+    assert!(matches!(eval_err("list(-1)"), RuntimeError::ValueError(_)));
+    // Too large to allocate is an error rather than an abort
+    assert!(matches!(
+        eval_err("list(1000000000000000000)"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn gcd_and_lcm_of_integers() {
+    // Coprime pairs
+    assert_eq!(eval("gcd(8, 15)"), int(1));
+    assert_eq!(eval("lcm(8, 15)"), int(120));
+    // A common factor
+    assert_eq!(eval("gcd(12, 18)"), int(6));
+    assert_eq!(eval("lcm(12, 18)"), int(36));
+    // Signs are ignored
+    assert_eq!(eval("gcd(-12, 18)"), int(6));
+    assert_eq!(eval("lcm(12, -18)"), int(36));
+    // Zero
+    assert_eq!(eval("gcd(0, 7)"), int(7));
+    assert_eq!(eval("gcd(-7, 0)"), int(7));
+    assert_eq!(eval("gcd(0, 0)"), int(0));
+    assert_eq!(eval("lcm(0, 7)"), int(0));
+    assert_eq!(eval("lcm(0, 0)"), int(0));
+    // Results too large for an integer are promoted to floats
+    assert_eq!(
+        eval("gcd(-9223372036854775807 - 1, 0)"),
+        float(9.223_372_036_854_776e18)
+    );
+    assert!(matches!(
+        eval_err("gcd(1.5, 3)"),
+        RuntimeError::TypeError(_)
+    ));
+    assert!(matches!(
+        eval_err("lcm(4, \"6\")"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn repeat_joins_copies_of_a_string() {
+    assert_eq!(eval("repeat(\"ab\", 3)"), string("ababab"));
+    assert_eq!(eval("repeat(\"ab\", 1)"), string("ab"));
+    assert_eq!(eval("repeat(\"ab\", 0)"), string(""));
+    assert_eq!(eval("repeat(\"\", 5)"), string(""));
+    // With a separator between the copies, but not after the last one
+    assert_eq!(eval("repeat(\"ab\", 3, \", \")"), string("ab, ab, ab"));
+    assert_eq!(eval("repeat(\"ab\", 1, \", \")"), string("ab"));
+    assert_eq!(eval("repeat(\"ab\", 0, \", \")"), string(""));
+    assert_eq!(eval("repeat(\"\", 3, \"-\")"), string("--"));
+
+    assert!(matches!(
+        eval_err("repeat(\"ab\", -1)"),
+        RuntimeError::ValueError(_)
+    ));
+    // Too large to allocate is an error rather than an abort
+    assert!(matches!(
+        eval_err("repeat(\"ab\", 9223372036854775807)"),
+        RuntimeError::ValueError(_)
+    ));
+    assert!(matches!(
+        eval_err("repeat(\"\", 9223372036854775807, \"-\")"),
+        RuntimeError::ValueError(_)
+    ));
+}
+
+#[test]
+fn saturating_arithmetic_clamps_at_the_integer_bounds() {
+    assert_eq!(eval("sat_add(1, 2)"), int(3));
+    assert_eq!(eval("sat_add(9223372036854775807, 1)"), int(i64::MAX));
+    assert_eq!(eval("sat_add(9223372036854775800, 100)"), int(i64::MAX));
+    assert_eq!(eval("sat_add(-9223372036854775807, -5)"), int(i64::MIN));
+    assert_eq!(eval("sat_sub(5, 7)"), int(-2));
+    assert_eq!(eval("sat_sub(-9223372036854775807, 2)"), int(i64::MIN));
+    assert_eq!(eval("sat_sub(9223372036854775807, -1)"), int(i64::MAX));
+    assert_eq!(eval("sat_mul(-4, 5)"), int(-20));
+    assert_eq!(eval("sat_mul(4611686018427387904, 2)"), int(i64::MAX));
+    assert_eq!(eval("sat_mul(4611686018427387904, -3)"), int(i64::MIN));
+    assert_eq!(eval("sat_mul(-9223372036854775807 - 1, -1)"), int(i64::MAX));
+    assert!(matches!(
+        eval_err("sat_add(1.0, 2)"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn is_compares_identity_rather_than_value() {
+    let source = "
+        original = table();
+        original.x = 1;
+        alias = original;
+        copy = table();
+        copy.x = 1;
+        return string([is(alias, original), is(copy, original), copy == original]);
+    ";
+    assert_eq!(
+        run_on(&mut State::new(), source),
+        Ok(Some(string("[true, false, true]")))
+    );
+    assert_eq!(eval("is([1, 2], [1, 2])"), boolean(false));
+    assert_eq!(eval("fn(x) { return is(x, x); }([1, 2])"), boolean(true));
+    assert_eq!(eval("is(print, print)"), boolean(true));
+}
+
+#[test]
+fn lines_splits_on_either_line_ending() {
+    let lines = |input| split_with("lines", input).unwrap();
+    assert_eq!(lines("one\ntwo\nthree"), ["one", "two", "three"]);
+    // A final newline doesn't start another line
+    assert_eq!(lines("one\ntwo\n"), ["one", "two"]);
+    assert_eq!(lines("one\r\ntwo\nthree\r\n"), ["one", "two", "three"]);
+    // Empty lines are kept
+    assert_eq!(lines("\none\n\r\n\ntwo"), ["", "one", "", "", "two"]);
+    assert_eq!(lines("a\rb"), ["a\rb"]);
+    assert!(lines("").is_empty());
+    assert_eq!(lines("\n"), [""]);
+}
+
+#[test]
+fn locals_gives_a_snapshot_of_the_calling_scope() {
+    let source = "
+        outer = 1;
+        f = fn(x) {
+            name = \"ada\";
+            count = x * 2;
+            vars = locals();
+            vars.count = 100;
+            return [vars.x, vars.name, vars.count, count, has(vars, \"outer\")];
+        };
+        return string(f(21));
+    ";
+    assert_eq!(
+        run_on(&mut State::new(), source),
+        Ok(Some(string("[21, ada, 100, 42, false]")))
+    );
+    // At the top level, the locals are the globals
+    assert_eq!(eval("has(locals(), \"print\")"), boolean(true));
+}
+
+#[test]
+fn bytes_and_from_bytes_round_trip_utf8() {
+    assert_eq!(eval("string(bytes(\"hi!\"))"), string("[104, 105, 33]"));
+    // "é" takes two bytes and "€" three
+    assert_eq!(
+        eval("string(bytes(\"é€\"))"),
+        string("[195, 169, 226, 130, 172]")
+    );
+    assert_eq!(eval("from_bytes([195, 169, 226, 130, 172])"), string("é€"));
+    for text in ["", "plain ascii", "héllo wörld", "日本語", "emoji 🦀!"] {
+        let source = format!("from_bytes(bytes({text:?}))");
+        assert_eq!(eval(&source), string(text));
+    }
+    // A multibyte character cut short, and a byte which never starts a character
+    assert!(matches!(
+        eval_err("from_bytes([104, 195])"),
+        RuntimeError::ValueError(_)
+    ));
+    assert!(matches!(
+        eval_err("from_bytes([255])"),
+        RuntimeError::ValueError(_)
+    ));
+    assert!(matches!(
+        eval_err("from_bytes([256])"),
+        RuntimeError::ValueError(_)
+    ));
+    assert!(matches!(
+        eval_err("from_bytes([\"a\"])"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn base64_round_trips_and_rejects_invalid_input() {
+    // The test vectors of RFC 4648
+    let vectors = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+    for (text, encoded) in vectors {
+        assert_eq!(eval(&format!("base64_encode({text:?})")), string(encoded));
+        let decoded = format!("from_bytes(base64_decode({encoded:?}))");
+        assert_eq!(eval(&decoded), string(text));
+    }
+    assert_eq!(
+        eval("from_bytes(base64_decode(base64_encode(\"héllo wörld\")))"),
+        string("héllo wörld")
+    );
+    // Bytes which aren't UTF-8 survive the round trip
+    assert_eq!(eval("base64_encode([255, 0, 128])"), string("/wCA"));
+    assert_eq!(
+        eval("string(base64_decode(\"/wCA\"))"),
+        string("[255, 0, 128]")
+    );
+
+    for invalid in ["Zm9", "Zm9v!", "Z===", "Zg=a", "=Zg="] {
+        assert!(
+            matches!(
+                eval_err(&format!("base64_decode({invalid:?})")),
+                RuntimeError::ValueError(_)
+            ),
+            "{invalid} should be invalid"
+        );
+    }
+}
+
+#[test]
+fn hash_is_stable_and_follows_contents() {
+    let hash = |value: &str| match eval(&format!("hash({value})")) {
+        Primitive::Integer(x) => x,
+        other => panic!("expected an integer hash, got {other:?}"),
+    };
+
+    // Stable across calls and states
+    assert_eq!(hash("\"hello\""), hash("\"hello\""));
+    assert_eq!(eval("hash([1, \"a\"]) == hash([1, \"a\"])"), boolean(true));
+    assert_eq!(
+        hash("\"hello\""),
+        i64::from_le_bytes(3_908_592_602_279_379_923_u64.to_le_bytes())
+    );
+
+    // Equal values hash equally, whatever order table keys were set in
+    let source = "
+        a = table(); a.x = 1; a.y = [2, 3];
+        b = table(); b.y = [2, 3]; b.x = 1;
+        return (hash(a) == hash(b)) and (hash(a.y) == hash([2, 3]));
+    ";
+    assert_eq!(run_on(&mut State::new(), source), Ok(Some(boolean(true))));
+    assert_eq!(hash("0.0"), hash("-0.0"));
+
+    // Different values hash differently
+    let values = [
+        "nil",
+        "true",
+        "false",
+        "0",
+        "1",
+        "1.5",
+        "\"\"",
+        "\"1\"",
+        "[]",
+        "[1]",
+        "[1, 2]",
+        "[2, 1]",
+        "[[1], 2]",
+        "[1, [2]]",
+        "[\"ab\", \"c\"]",
+        "[\"a\", \"bc\"]",
+        "table()",
+    ];
+    let hashes: Vec<_> = values.iter().map(|value| hash(value)).collect();
+    for (i, a) in hashes.iter().enumerate() {
+        for (j, b) in hashes.iter().enumerate().skip(i + 1) {
+            assert_ne!(a, b, "{} and {} hash equally", values[i], values[j]);
+        }
+    }
+
+    assert!(matches!(
+        eval_err("hash(print)"),
+        RuntimeError::TypeError(_)
+    ));
+    assert!(matches!(
+        eval_err("hash([1, fn() { return 1; }])"),
+        RuntimeError::TypeError(_)
+    ));
+}
+
+#[test]
+fn parse_gives_the_syntax_tree_as_tables() {
+    for source in ["1 + 2", "1 + 2;"] {
+        let root = format!("parse({source:?})");
+        assert_eq!(eval(&format!("{root}.type")), string("Block"));
+        let statement = format!("{root}.statements[0]");
+        assert_eq!(
+            eval(&format!("{statement}.type")),
+            string("BinaryOperation")
+        );
+        assert_eq!(eval(&format!("{statement}.kind")), string("Add"));
+        assert_eq!(eval(&format!("{statement}.left.value")), int(1));
+        assert_eq!(eval(&format!("{statement}.right.value")), int(2));
+    }
+    assert_eq!(eval("len(parse(\"x = 1; f(x);\").statements)"), int(2));
+}
+
+#[test]
+fn parse_reports_invalid_source_as_a_syntax_error() {
+    assert!(matches!(
+        eval_err("parse(\"1 +\")"),
+        RuntimeError::SyntaxError(_)
+    ));
+    assert!(matches!(
+        eval_err("parse(\"x = ;\")"),
+        RuntimeError::SyntaxError(_)
+    ));
+    assert!(matches!(eval_err("parse(1)"), RuntimeError::TypeError(_)));
+}